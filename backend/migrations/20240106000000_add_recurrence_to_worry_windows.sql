@@ -0,0 +1,24 @@
+-- Add recurrence rule to worry_windows (NULL means a one-off window)
+ALTER TABLE worry_windows
+ADD COLUMN recurrence JSONB;
+
+-- Create worry_window_occurrences table for per-occurrence overrides of a series
+CREATE TABLE IF NOT EXISTS worry_window_occurrences (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    worry_window_id UUID NOT NULL REFERENCES worry_windows(id) ON DELETE CASCADE,
+    occurrence_date DATE NOT NULL,
+    is_skipped BOOLEAN NOT NULL DEFAULT FALSE,
+    is_completed BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (worry_window_id, occurrence_date)
+);
+
+-- Create index for occurrence_date for range lookups
+CREATE INDEX idx_worry_window_occurrences_date ON worry_window_occurrences(occurrence_date);
+
+-- Create trigger to auto-update updated_at
+CREATE TRIGGER update_worry_window_occurrences_updated_at
+BEFORE UPDATE ON worry_window_occurrences
+FOR EACH ROW
+EXECUTE FUNCTION update_updated_at_column();
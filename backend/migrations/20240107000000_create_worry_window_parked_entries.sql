@@ -0,0 +1,23 @@
+-- Create worry_window_parked_entries table linking Mental Box entries to a Worry Window occurrence
+CREATE TABLE IF NOT EXISTS worry_window_parked_entries (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    worry_window_id UUID NOT NULL REFERENCES worry_windows(id) ON DELETE CASCADE,
+    mental_box_id UUID NOT NULL REFERENCES mental_box_entries(id) ON DELETE CASCADE,
+    occurrence_date DATE NOT NULL,
+    outcome VARCHAR(20) CHECK (outcome IN ('resolved', 'still_worrying', 'reframed', 'dropped')),
+    outcome_note TEXT,
+    outcome_recorded_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (worry_window_id, occurrence_date, mental_box_id)
+);
+
+-- Create indexes for faster queries
+CREATE INDEX idx_parked_entries_window_date ON worry_window_parked_entries(worry_window_id, occurrence_date);
+CREATE INDEX idx_parked_entries_mental_box_id ON worry_window_parked_entries(mental_box_id);
+
+-- Create trigger to auto-update updated_at
+CREATE TRIGGER update_worry_window_parked_entries_updated_at
+BEFORE UPDATE ON worry_window_parked_entries
+FOR EACH ROW
+EXECUTE FUNCTION update_updated_at_column();
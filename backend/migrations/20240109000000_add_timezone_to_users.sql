@@ -0,0 +1,3 @@
+-- Add IANA timezone to users; day boundaries for stats and worry windows are computed in it
+ALTER TABLE users
+ADD COLUMN timezone VARCHAR(64) NOT NULL DEFAULT 'UTC';
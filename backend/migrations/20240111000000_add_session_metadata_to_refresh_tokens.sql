@@ -0,0 +1,5 @@
+-- Record where each refresh token (session) is used from
+ALTER TABLE refresh_tokens
+ADD COLUMN user_agent TEXT,
+ADD COLUMN ip_address VARCHAR(45),
+ADD COLUMN last_used_at TIMESTAMPTZ;
@@ -0,0 +1,9 @@
+-- Refresh tokens are now stored as a keyed HMAC-SHA256 hash instead of the raw JWT.
+-- Existing plaintext tokens cannot be hashed here without the application key, so they
+-- are removed; affected users simply sign in again.
+DELETE FROM refresh_tokens;
+
+ALTER TABLE refresh_tokens RENAME COLUMN token TO token_hash;
+ALTER TABLE refresh_tokens ALTER COLUMN token_hash TYPE VARCHAR(64);
+
+ALTER INDEX idx_refresh_tokens_token RENAME TO idx_refresh_tokens_token_hash;
@@ -0,0 +1,13 @@
+-- Create login_attempts table used to throttle password guessing.
+-- Attempts are keyed by the normalised email as submitted, so unknown emails are
+-- throttled exactly like registered ones and lockouts do not reveal which exist.
+CREATE TABLE IF NOT EXISTS login_attempts (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    email VARCHAR(255) NOT NULL,
+    ip_address VARCHAR(45),
+    succeeded BOOLEAN NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_login_attempts_email_created_at ON login_attempts(email, created_at DESC);
+CREATE INDEX idx_login_attempts_ip_created_at ON login_attempts(ip_address, created_at DESC);
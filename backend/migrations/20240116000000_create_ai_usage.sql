@@ -0,0 +1,17 @@
+-- Create ai_usage table recording the tokens (and, when reported, the cost) of every
+-- LLM call, so per-user quotas can be enforced and spend can be audited
+CREATE TABLE IF NOT EXISTS ai_usage (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    feature VARCHAR(50) NOT NULL,
+    model VARCHAR(100) NOT NULL,
+    prompt_tokens INTEGER NOT NULL DEFAULT 0,
+    completion_tokens INTEGER NOT NULL DEFAULT 0,
+    total_tokens INTEGER NOT NULL DEFAULT 0,
+    -- Cost in USD as reported by the provider, if it reports one
+    cost DOUBLE PRECISION,
+    stress_reframe_id UUID REFERENCES stress_reframes(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_ai_usage_user_id_created_at ON ai_usage(user_id, created_at DESC);
@@ -0,0 +1,20 @@
+-- Create llm_attempts table recording every call made to the LLM provider, including
+-- retries and fallbacks, so failures and latency can be investigated after the fact.
+-- Attempts made for the same reframe share a generation_id.
+CREATE TABLE IF NOT EXISTS llm_attempts (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    generation_id UUID NOT NULL,
+    user_id UUID REFERENCES users(id) ON DELETE SET NULL,
+    provider VARCHAR(50) NOT NULL,
+    model VARCHAR(100) NOT NULL,
+    attempt INTEGER NOT NULL,
+    outcome VARCHAR(30) NOT NULL,
+    status_code INTEGER,
+    error TEXT,
+    latency_ms INTEGER NOT NULL,
+    total_tokens INTEGER,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_llm_attempts_generation_id ON llm_attempts(generation_id);
+CREATE INDEX idx_llm_attempts_created_at ON llm_attempts(created_at DESC);
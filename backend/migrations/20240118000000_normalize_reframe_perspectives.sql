@@ -0,0 +1,53 @@
+-- Catalog of reframing perspectives users can pick from. prompt_template is the
+-- instruction given to the model for that perspective.
+CREATE TABLE IF NOT EXISTS perspectives (
+    key VARCHAR(50) PRIMARY KEY,
+    name VARCHAR(100) NOT NULL,
+    description TEXT NOT NULL,
+    prompt_template TEXT NOT NULL,
+    -- Perspectives generated when a request does not pick any
+    is_default BOOLEAN NOT NULL DEFAULT FALSE,
+    is_active BOOLEAN NOT NULL DEFAULT TRUE,
+    sort_order INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+INSERT INTO perspectives (key, name, description, prompt_template, is_default, sort_order) VALUES
+    ('stoic', 'Stoic', 'Focus on what you can control',
+     'Focus on what the person can control, accepting what they cannot', TRUE, 10),
+    ('optimist', 'Optimist', 'Look for the silver lining',
+     'Find the silver lining or opportunity in the situation', TRUE, 20),
+    ('realist', 'Realist', 'A balanced, practical view',
+     'Provide a balanced, practical perspective that acknowledges reality', TRUE, 30),
+    ('compassionate_friend', 'Compassionate friend', 'What a kind friend would say',
+     'Respond as a warm, compassionate friend would, with kindness and without judgement', FALSE, 40),
+    ('evidence', 'Evidence for and against', 'Weigh the facts behind the thought',
+     'Briefly weigh the evidence that supports the thought against the evidence that does not', FALSE, 50),
+    ('decatastrophize', 'Decatastrophize', 'Put the worst case in proportion',
+     'Consider the worst, best and most likely outcomes, and how the person could cope with the worst', FALSE, 60);
+
+-- One row per perspective of a reframe
+CREATE TABLE IF NOT EXISTS stress_reframe_perspectives (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    stress_reframe_id UUID NOT NULL REFERENCES stress_reframes(id) ON DELETE CASCADE,
+    perspective_key VARCHAR(50) NOT NULL REFERENCES perspectives(key),
+    content TEXT NOT NULL,
+    position INTEGER NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (stress_reframe_id, perspective_key)
+);
+
+CREATE INDEX idx_stress_reframe_perspectives_reframe_id ON stress_reframe_perspectives(stress_reframe_id, position);
+
+-- Move the existing fixed columns into rows
+INSERT INTO stress_reframe_perspectives (stress_reframe_id, perspective_key, content, position, created_at)
+SELECT id, 'stoic', stoic_reframe, 0, created_at FROM stress_reframes
+UNION ALL
+SELECT id, 'optimist', optimist_reframe, 1, created_at FROM stress_reframes
+UNION ALL
+SELECT id, 'realist', realist_reframe, 2, created_at FROM stress_reframes;
+
+ALTER TABLE stress_reframes
+DROP COLUMN stoic_reframe,
+DROP COLUMN optimist_reframe,
+DROP COLUMN realist_reframe;
@@ -0,0 +1,28 @@
+-- Cognitive distortions detected in a Mental Box entry or in the thought behind a reframe.
+-- An analysis with no distortion rows means none were found.
+CREATE TABLE IF NOT EXISTS thought_analyses (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    mental_box_id UUID REFERENCES mental_box_entries(id) ON DELETE CASCADE,
+    stress_reframe_id UUID REFERENCES stress_reframes(id) ON DELETE CASCADE,
+    model VARCHAR(100) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_thought_analyses_mental_box_id ON thought_analyses(mental_box_id, created_at DESC);
+CREATE INDEX idx_thought_analyses_stress_reframe_id ON thought_analyses(stress_reframe_id);
+
+CREATE TABLE IF NOT EXISTS thought_analysis_distortions (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    analysis_id UUID NOT NULL REFERENCES thought_analyses(id) ON DELETE CASCADE,
+    distortion TEXT NOT NULL CHECK (distortion IN (
+        'all_or_nothing', 'overgeneralization', 'mental_filter', 'discounting_positive',
+        'mind_reading', 'fortune_telling', 'catastrophizing', 'emotional_reasoning',
+        'should_statements', 'labeling', 'personalization', 'blaming'
+    )),
+    confidence REAL NOT NULL CHECK (confidence >= 0 AND confidence <= 1),
+    explanation TEXT NOT NULL,
+    UNIQUE (analysis_id, distortion)
+);
+
+CREATE INDEX idx_thought_analysis_distortions_analysis_id ON thought_analysis_distortions(analysis_id);
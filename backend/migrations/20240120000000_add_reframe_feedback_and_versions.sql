@@ -0,0 +1,48 @@
+-- Reframes regenerated for the same entry share a thread and are numbered by version.
+-- favorited_at is set while the user has pinned the reframe.
+ALTER TABLE stress_reframes
+ADD COLUMN thread_id UUID,
+ADD COLUMN version INTEGER NOT NULL DEFAULT 1,
+ADD COLUMN favorited_at TIMESTAMPTZ;
+
+-- Existing reframes of the same Mental Box entry become versions of one thread
+WITH ranked AS (
+    SELECT
+        id,
+        FIRST_VALUE(id) OVER entry_reframes AS thread_id,
+        ROW_NUMBER() OVER entry_reframes AS version
+    FROM stress_reframes
+    WINDOW entry_reframes AS (PARTITION BY user_id, COALESCE(mental_box_id, id) ORDER BY created_at, id)
+)
+UPDATE stress_reframes sr
+SET thread_id = ranked.thread_id, version = ranked.version
+FROM ranked
+WHERE sr.id = ranked.id;
+
+ALTER TABLE stress_reframes ALTER COLUMN thread_id SET NOT NULL;
+
+-- Also serves the lookup of a thread's latest version
+ALTER TABLE stress_reframes ADD CONSTRAINT stress_reframes_thread_id_version_key UNIQUE (thread_id, version);
+CREATE INDEX idx_stress_reframes_favorited_at ON stress_reframes(user_id, favorited_at DESC)
+WHERE favorited_at IS NOT NULL;
+
+-- One rating per perspective of a reframe
+CREATE TABLE IF NOT EXISTS stress_reframe_feedback (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    stress_reframe_id UUID NOT NULL,
+    perspective_key VARCHAR(50) NOT NULL,
+    helpful BOOLEAN NOT NULL,
+    comment TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (stress_reframe_id, perspective_key),
+    FOREIGN KEY (stress_reframe_id, perspective_key)
+        REFERENCES stress_reframe_perspectives(stress_reframe_id, perspective_key) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_stress_reframe_feedback_perspective_key ON stress_reframe_feedback(perspective_key);
+
+CREATE TRIGGER update_stress_reframe_feedback_updated_at
+BEFORE UPDATE ON stress_reframe_feedback
+FOR EACH ROW
+EXECUTE FUNCTION update_updated_at_column();
@@ -0,0 +1,13 @@
+-- Full-text search over the original thought and every perspective's text. The 'simple'
+-- configuration does no stemming, so English and Thai text are indexed alike.
+ALTER TABLE stress_reframes
+ADD COLUMN search_vector TSVECTOR GENERATED ALWAYS AS (to_tsvector('simple', original_thought)) STORED;
+
+ALTER TABLE stress_reframe_perspectives
+ADD COLUMN search_vector TSVECTOR GENERATED ALWAYS AS (to_tsvector('simple', content)) STORED;
+
+CREATE INDEX idx_stress_reframes_search_vector ON stress_reframes USING GIN (search_vector);
+CREATE INDEX idx_stress_reframe_perspectives_search_vector ON stress_reframe_perspectives USING GIN (search_vector);
+
+-- Keyset pagination walks a user's reframes newest first
+CREATE INDEX idx_stress_reframes_user_id_created_at ON stress_reframes(user_id, created_at DESC, id DESC);
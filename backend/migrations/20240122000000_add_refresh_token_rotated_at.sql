@@ -0,0 +1,4 @@
+-- Set only when a refresh token is exchanged for a new one. Presenting a rotated token again
+-- signals theft; tokens revoked by logout, session revocation or a password change do not.
+ALTER TABLE refresh_tokens
+ADD COLUMN rotated_at TIMESTAMPTZ;
@@ -0,0 +1,2 @@
+-- Whether a request was allowed is derived from the upsert itself, so it need not be stored
+ALTER TABLE rate_limit_buckets DROP COLUMN allowed;
@@ -0,0 +1,16 @@
+-- Create ai_quota_reservations table holding the tokens set aside for LLM calls that are
+-- still running, so concurrent requests cannot all pass the quota check before any of them
+-- has recorded its usage. Rows are deleted once the call's usage is recorded; expires_at
+-- bounds how long a reservation left behind by a crashed request can hold the budget.
+CREATE TABLE IF NOT EXISTS ai_quota_reservations (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    tokens INTEGER NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX idx_ai_quota_reservations_user_id_expires_at ON ai_quota_reservations(user_id, expires_at);
+
+-- Tokens spent by failed attempts count towards the quota too
+CREATE INDEX idx_llm_attempts_user_id_created_at ON llm_attempts(user_id, created_at DESC);
@@ -0,0 +1,9 @@
+-- Substring search (ILIKE '%...%') over the original thought and every perspective's text.
+-- Together with the full-text indexes this lets each table answer a search with a bitmap
+-- OR of two GIN indexes instead of a scan.
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+CREATE INDEX idx_stress_reframes_original_thought_trgm
+ON stress_reframes USING GIN (original_thought gin_trgm_ops);
+CREATE INDEX idx_stress_reframe_perspectives_content_trgm
+ON stress_reframe_perspectives USING GIN (content gin_trgm_ops);
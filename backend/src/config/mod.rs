@@ -1,30 +1,394 @@
+use jsonwebtoken::Algorithm;
 use std::env;
 
 #[derive(Clone)]
 pub struct Config {
     pub database_url: String,
-    pub jwt_secret: String,
-    pub jwt_expiration: i64,
-    pub refresh_token_expiration: i64,
+    pub jwt: JwtConfig,
+    pub login_throttle: LoginThrottleConfig,
+    pub rate_limit: RateLimitConfig,
+    pub ai_quota: AiQuotaConfig,
+    pub llm: LlmConfig,
+    pub public_url: PublicUrl,
+    /// Where the frontend is served: the one origin CORS allows, and the base of emailed links
+    pub frontend_url: String,
+    pub password_reset: PasswordResetConfig,
     pub server_host: String,
     pub server_port: String,
 }
 
+#[derive(Clone)]
+pub struct JwtConfig {
+    /// HS256, RS256 or EdDSA
+    pub algorithm: Algorithm,
+    /// `kid` header written into every newly signed token
+    pub key_id: String,
+    /// Shared secret, used when `algorithm` is HS256
+    pub secret: Option<String>,
+    /// PEM key pair, used when `algorithm` is RS256 or EdDSA
+    pub private_key_path: Option<String>,
+    pub public_key_path: Option<String>,
+    /// Extra keys still accepted for verification, e.g. the previous key during a rotation
+    pub verification_keys: Vec<VerificationKeyConfig>,
+    /// `iss` written into and required of every token
+    pub issuer: String,
+    /// `aud` written into and required of every token
+    pub audience: String,
+    pub expiration: i64,
+    pub refresh_token_expiration: i64,
+    pub refresh_token_hash_key: String,
+}
+
+/// A key from `JWT_VERIFICATION_KEYS`, written as `kid:path` or `kid:algorithm:path`
+#[derive(Clone)]
+pub struct VerificationKeyConfig {
+    pub key_id: String,
+    /// Defaults to `JWT_ALGORITHM`; differs when rotating e.g. from HS256 to RS256
+    pub algorithm: Algorithm,
+    /// A PEM public key, or the raw secret for HS256
+    pub path: String,
+}
+
+fn parse_algorithm(value: &str) -> Option<Algorithm> {
+    match value {
+        "HS256" => Some(Algorithm::HS256),
+        "RS256" => Some(Algorithm::RS256),
+        "EdDSA" => Some(Algorithm::EdDSA),
+        _ => None,
+    }
+}
+
+/// Brute-force protection for `/api/auth/login`
+#[derive(Debug, Clone)]
+pub struct LoginThrottleConfig {
+    /// Failed attempts against one email within `window_seconds` before it is locked
+    pub max_account_failures: i64,
+    /// Failed attempts from one IP (across all emails) within `window_seconds` before it is locked
+    pub max_ip_failures: i64,
+    pub window_seconds: i64,
+    pub lockout_seconds: i64,
+    /// Failures tolerated before responses start being delayed
+    pub free_failures: i64,
+    pub base_delay_ms: u64,
+    pub max_delay_ms: u64,
+}
+
+/// Where clients reach the backend, used to build absolute links such as calendar feed URLs
+#[derive(Debug, Clone)]
+pub struct PublicUrl(String);
+
+impl PublicUrl {
+    pub fn from_env() -> Self {
+        let url = env::var("BACKEND_PUBLIC_URL").unwrap_or_else(|_| "http://localhost:8000".to_string());
+        Self(url.trim_end_matches('/').to_string())
+    }
+
+    /// Appends `path`, which must start with a slash
+    pub fn join(&self, path: &str) -> String {
+        format!("{}{}", self.0, path)
+    }
+}
+
+/// Password reset links emailed to users
+#[derive(Debug, Clone)]
+pub struct PasswordResetConfig {
+    /// Base of the link to the frontend's reset page
+    pub frontend_url: String,
+    /// How long a link stays valid
+    pub expiration_seconds: i64,
+}
+
+impl PasswordResetConfig {
+    pub fn from_env(frontend_url: &str) -> Self {
+        Self {
+            frontend_url: frontend_url.to_string(),
+            expiration_seconds: env_or("PASSWORD_RESET_EXPIRATION", 3600),
+        }
+    }
+}
+
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    env::var(name)
+        .ok()
+        .map(|value| value.parse().unwrap_or_else(|_| panic!("{} must be a number", name)))
+        .unwrap_or(default)
+}
+
+impl LoginThrottleConfig {
+    pub fn from_env() -> Self {
+        Self {
+            max_account_failures: env_or("LOGIN_MAX_ACCOUNT_FAILURES", 5),
+            max_ip_failures: env_or("LOGIN_MAX_IP_FAILURES", 20),
+            window_seconds: env_or("LOGIN_ATTEMPT_WINDOW", 900),
+            lockout_seconds: env_or("LOGIN_LOCKOUT_DURATION", 900),
+            free_failures: 2,
+            base_delay_ms: 250,
+            max_delay_ms: 4000,
+        }
+    }
+}
+
+/// A token bucket holding up to `capacity` requests, refilled at `capacity / period_seconds` per second
+#[derive(Debug, Clone)]
+pub struct RateLimitPolicy {
+    pub name: &'static str,
+    pub capacity: u32,
+    pub period_seconds: u32,
+}
+
+impl RateLimitPolicy {
+    /// Reads an override such as `RATE_LIMIT_AI=10/3600` (10 requests per hour)
+    fn from_env(name: &'static str, var: &str, capacity: u32, period_seconds: u32) -> Self {
+        let (capacity, period_seconds) = match env::var(var) {
+            Ok(value) => value
+                .split_once('/')
+                .and_then(|(c, p)| Some((c.trim().parse().ok()?, p.trim().parse().ok()?)))
+                .filter(|&(c, p)| c > 0 && p > 0)
+                .unwrap_or_else(|| panic!("{} must look like <requests>/<seconds>", var)),
+            Err(_) => (capacity, period_seconds),
+        };
+        Self { name, capacity, period_seconds }
+    }
+
+    pub fn refill_per_second(&self) -> f64 {
+        self.capacity as f64 / self.period_seconds as f64
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimitStoreKind {
+    Memory,
+    Postgres,
+}
+
+#[derive(Debug, Clone)]
+pub struct RateLimitConfig {
+    pub enabled: bool,
+    pub store: RateLimitStoreKind,
+    /// Login, registration and password reset, keyed by client IP
+    pub auth: RateLimitPolicy,
+    /// Endpoints that call the paid LLM API, keyed by user
+    pub ai: RateLimitPolicy,
+    /// Everything else
+    pub default: RateLimitPolicy,
+}
+
+impl RateLimitConfig {
+    pub fn from_env() -> Self {
+        let store = match env::var("RATE_LIMIT_STORE").as_deref() {
+            Ok("postgres") => RateLimitStoreKind::Postgres,
+            Ok("memory") | Err(_) => RateLimitStoreKind::Memory,
+            Ok(other) => panic!("Unsupported RATE_LIMIT_STORE: {} (expected memory or postgres)", other),
+        };
+
+        Self {
+            enabled: env::var("RATE_LIMIT_ENABLED").map(|v| v != "false").unwrap_or(true),
+            store,
+            auth: RateLimitPolicy::from_env("auth", "RATE_LIMIT_AUTH", 10, 60),
+            ai: RateLimitPolicy::from_env("ai", "RATE_LIMIT_AI", 20, 3600),
+            default: RateLimitPolicy::from_env("default", "RATE_LIMIT_DEFAULT", 120, 60),
+        }
+    }
+}
+
+/// Per-user LLM token budgets. Days and months follow the user's own timezone.
+#[derive(Debug, Clone)]
+pub struct AiQuotaConfig {
+    /// Tokens a user may spend per day; 0 disables the limit
+    pub daily_tokens: i64,
+    /// Tokens a user may spend per calendar month; 0 disables the limit
+    pub monthly_tokens: i64,
+    /// Tokens set aside for each LLM call while it runs, until its real usage is recorded
+    pub reserved_tokens_per_call: i64,
+}
+
+impl AiQuotaConfig {
+    pub fn from_env() -> Self {
+        Self {
+            daily_tokens: env_or("AI_DAILY_TOKEN_QUOTA", 20_000),
+            monthly_tokens: env_or("AI_MONTHLY_TOKEN_QUOTA", 300_000),
+            reserved_tokens_per_call: env_or("AI_RESERVED_TOKENS_PER_CALL", 2_000),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LlmProviderKind {
+    OpenRouter,
+    /// Any OpenAI `/chat/completions` server, e.g. a local Ollama or llama.cpp
+    OpenAiCompatible,
+    /// Canned reframes without network access, for tests and local development
+    Offline,
+}
+
+/// How reframes are requested as JSON. Not every OpenAI-compatible server supports
+/// `json_schema`; older ones only understand `json_object` or nothing at all.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LlmResponseFormat {
+    JsonSchema,
+    JsonObject,
+    /// Rely on the prompt alone
+    Text,
+}
+
+/// Which LLM backend generates stress reframes, and how to reach it
+#[derive(Debug, Clone)]
+pub struct LlmConfig {
+    pub provider: LlmProviderKind,
+    /// Base URL of an OpenAI-compatible server, e.g. `http://localhost:11434/v1`
+    pub base_url: String,
+    pub api_key: Option<String>,
+    pub model: String,
+    /// Models tried in order once `model` keeps failing
+    pub fallback_models: Vec<String>,
+    pub response_format: LlmResponseFormat,
+    pub connect_timeout_seconds: u64,
+    /// Limit on a single attempt, including reading the response. Streams are instead
+    /// limited per chunk, so a long answer that keeps arriving is not cut off.
+    pub request_timeout_seconds: u64,
+    /// Limit on a whole reframe across all retries and fallback models
+    pub total_timeout_seconds: u64,
+    /// Retries per model after transient failures (429, 5xx, timeouts)
+    pub max_retries: u32,
+    pub retry_base_delay_ms: u64,
+    pub retry_max_delay_ms: u64,
+    /// Consecutive transient failures that open the circuit breaker
+    pub circuit_failure_threshold: u32,
+    /// How long an open circuit fails fast before letting a trial request through
+    pub circuit_cooldown_seconds: u64,
+    /// Whether new reframes also get a distortion analysis, which costs a second call
+    /// unless an earlier analysis of the same thought can be reused
+    pub analyze_reframes: bool,
+}
+
+impl LlmConfig {
+    pub fn from_env() -> Self {
+        let provider = match env::var("LLM_PROVIDER").as_deref() {
+            Ok("openrouter") | Err(_) => LlmProviderKind::OpenRouter,
+            Ok("openai-compatible") => LlmProviderKind::OpenAiCompatible,
+            Ok("offline") => LlmProviderKind::Offline,
+            Ok(other) => panic!(
+                "Unsupported LLM_PROVIDER: {} (expected openrouter, openai-compatible or offline)",
+                other
+            ),
+        };
+
+        let response_format = match env::var("LLM_RESPONSE_FORMAT").as_deref() {
+            Ok("json_schema") | Err(_) => LlmResponseFormat::JsonSchema,
+            Ok("json_object") => LlmResponseFormat::JsonObject,
+            Ok("text") => LlmResponseFormat::Text,
+            Ok(other) => panic!(
+                "Unsupported LLM_RESPONSE_FORMAT: {} (expected json_schema, json_object or text)",
+                other
+            ),
+        };
+
+        let api_key = match provider {
+            LlmProviderKind::OpenRouter => env::var("OPENROUTER_API_KEY").ok(),
+            _ => env::var("LLM_API_KEY").ok(),
+        };
+        let default_model = match provider {
+            LlmProviderKind::OpenRouter => "google/gemini-2.5-flash", // Fast and affordable model
+            _ => "llama3.1",
+        };
+
+        Self {
+            provider,
+            base_url: env::var("LLM_BASE_URL").unwrap_or_else(|_| "http://localhost:11434/v1".to_string()),
+            api_key: api_key.filter(|key| !key.is_empty()),
+            model: env::var("LLM_MODEL").unwrap_or_else(|_| default_model.to_string()),
+            fallback_models: env::var("LLM_FALLBACK_MODELS")
+                .unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|model| !model.is_empty())
+                .map(str::to_string)
+                .collect(),
+            response_format,
+            connect_timeout_seconds: env_or("LLM_CONNECT_TIMEOUT", 5),
+            request_timeout_seconds: env_or("LLM_REQUEST_TIMEOUT", 30),
+            total_timeout_seconds: env_or("LLM_TOTAL_TIMEOUT", 60),
+            max_retries: env_or("LLM_MAX_RETRIES", 2),
+            retry_base_delay_ms: 500,
+            retry_max_delay_ms: 5000,
+            circuit_failure_threshold: env_or("LLM_CIRCUIT_FAILURE_THRESHOLD", 5),
+            circuit_cooldown_seconds: env_or("LLM_CIRCUIT_COOLDOWN", 30),
+            analyze_reframes: env::var("LLM_ANALYZE_REFRAMES").map(|v| v != "false").unwrap_or(true),
+        }
+    }
+}
+
 impl Config {
     pub fn from_env() -> Self {
+        let frontend_url = env::var("FRONTEND_URL").unwrap_or_else(|_| "http://localhost:3000".to_string());
+        let frontend_url = frontend_url.trim_end_matches('/').to_string();
+
         Self {
             database_url: env::var("DATABASE_URL").expect("DATABASE_URL must be set"),
-            jwt_secret: env::var("JWT_SECRET").expect("JWT_SECRET must be set"),
-            jwt_expiration: env::var("JWT_EXPIRATION")
-                .unwrap_or_else(|_| "900".to_string())
-                .parse()
-                .expect("JWT_EXPIRATION must be a number"),
-            refresh_token_expiration: env::var("REFRESH_TOKEN_EXPIRATION")
-                .unwrap_or_else(|_| "604800".to_string())
-                .parse()
-                .expect("REFRESH_TOKEN_EXPIRATION must be a number"),
+            jwt: JwtConfig::from_env(),
+            login_throttle: LoginThrottleConfig::from_env(),
+            rate_limit: RateLimitConfig::from_env(),
+            ai_quota: AiQuotaConfig::from_env(),
+            llm: LlmConfig::from_env(),
+            public_url: PublicUrl::from_env(),
+            password_reset: PasswordResetConfig::from_env(&frontend_url),
+            frontend_url,
             server_host: env::var("SERVER_HOST").unwrap_or_else(|_| "127.0.0.1".to_string()),
             server_port: env::var("SERVER_PORT").unwrap_or_else(|_| "8000".to_string()),
         }
     }
 }
+
+impl JwtConfig {
+    pub fn from_env() -> Self {
+        let algorithm = match env::var("JWT_ALGORITHM") {
+            Ok(value) => parse_algorithm(&value).unwrap_or_else(|| {
+                panic!("Unsupported JWT_ALGORITHM: {} (expected HS256, RS256 or EdDSA)", value)
+            }),
+            Err(_) => Algorithm::HS256,
+        };
+
+        let secret = env::var("JWT_SECRET").ok();
+        if algorithm == Algorithm::HS256 && secret.is_none() {
+            panic!("JWT_SECRET must be set when JWT_ALGORITHM is HS256");
+        }
+
+        let verification_keys = env::var("JWT_VERIFICATION_KEYS")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                let (kid, rest) = entry
+                    .split_once(':')
+                    .expect("JWT_VERIFICATION_KEYS entries must look like kid:path or kid:algorithm:path");
+                let (key_algorithm, path) = rest
+                    .split_once(':')
+                    .and_then(|(name, path)| Some((parse_algorithm(name)?, path)))
+                    .unwrap_or((algorithm, rest));
+                VerificationKeyConfig {
+                    key_id: kid.to_string(),
+                    algorithm: key_algorithm,
+                    path: path.to_string(),
+                }
+            })
+            .collect();
+
+        Self {
+            algorithm,
+            key_id: env::var("JWT_KEY_ID").unwrap_or_else(|_| "default".to_string()),
+            private_key_path: env::var("JWT_PRIVATE_KEY_PATH").ok(),
+            public_key_path: env::var("JWT_PUBLIC_KEY_PATH").ok(),
+            verification_keys,
+            issuer: env::var("JWT_ISSUER").unwrap_or_else(|_| "sabyejai-backend".to_string()),
+            audience: env::var("JWT_AUDIENCE").unwrap_or_else(|_| "sabyejai-app".to_string()),
+            expiration: env_or("JWT_EXPIRATION", 900),
+            refresh_token_expiration: env_or("REFRESH_TOKEN_EXPIRATION", 604800),
+            refresh_token_hash_key: env::var("REFRESH_TOKEN_HASH_KEY")
+                .ok()
+                .or_else(|| secret.clone())
+                .expect("REFRESH_TOKEN_HASH_KEY must be set when JWT_SECRET is not"),
+            secret,
+        }
+    }
+}
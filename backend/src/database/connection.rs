@@ -1,12 +1,9 @@
 use sqlx::{postgres::PgPoolOptions, PgPool};
-use std::env;
-
-pub async fn create_pool() -> PgPool {
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
 
+pub async fn create_pool(database_url: &str) -> PgPool {
     PgPoolOptions::new()
         .max_connections(5)
-        .connect(&database_url)
+        .connect(database_url)
         .await
         .expect("Failed to create database pool")
 }
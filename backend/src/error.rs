@@ -0,0 +1,350 @@
+use axum::{
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use validator::{ValidationErrors, ValidationErrorsKind};
+
+use crate::services::ai_usage_service::AiUsageError;
+use crate::services::auth_service::AuthError;
+use crate::services::calendar_service::CalendarError;
+use crate::services::llm_service::LlmError;
+use crate::services::password_reset_service::PasswordResetError;
+use crate::services::session_service::SessionError;
+use crate::services::stress_reframe_service::StressReframeError;
+use crate::services::user_service::UserError;
+use crate::services::worry_window_service::WorryWindowError;
+
+/// Error returned by every handler. Client errors carry a stable machine-readable
+/// `code` alongside a human-readable message; server errors are logged and their
+/// details kept out of the response.
+#[derive(Debug, thiserror::Error)]
+pub enum AppError {
+    #[error("{1}")]
+    BadRequest(&'static str, String),
+    #[error("Request validation failed")]
+    Validation(Value),
+    #[error("{1}")]
+    Unauthorized(&'static str, String),
+    #[error("{1}")]
+    NotFound(&'static str, String),
+    #[error("{1}")]
+    Conflict(&'static str, String),
+    /// Carries the number of seconds after which the client may retry
+    #[error("{1}")]
+    TooManyRequests(&'static str, String, u64),
+    #[error("Upstream service error: {0}")]
+    Upstream(String),
+    /// Carries the number of seconds after which the client may retry
+    #[error("{1}")]
+    ServiceUnavailable(&'static str, String, u64),
+    #[error("Internal error: {0}")]
+    Internal(String),
+    #[error("Database error: {0}")]
+    Database(sqlx::Error),
+}
+
+#[derive(Debug, Serialize)]
+pub struct ErrorBody {
+    pub code: &'static str,
+    pub message: String,
+    pub details: Option<Value>,
+}
+
+impl AppError {
+    /// Validation error for a single field, in the same shape as multi-field errors
+    pub fn invalid_field(field: &str, message: &str) -> Self {
+        AppError::Validation(json!({ field: [message] }))
+    }
+
+    pub fn unauthorized() -> Self {
+        AppError::Unauthorized("unauthorized", "Authentication required".to_string())
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AppError::BadRequest(..) => StatusCode::BAD_REQUEST,
+            AppError::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::Unauthorized(..) => StatusCode::UNAUTHORIZED,
+            AppError::NotFound(..) => StatusCode::NOT_FOUND,
+            AppError::Conflict(..) => StatusCode::CONFLICT,
+            AppError::TooManyRequests(..) => StatusCode::TOO_MANY_REQUESTS,
+            AppError::Upstream(_) => StatusCode::BAD_GATEWAY,
+            AppError::ServiceUnavailable(..) => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::Internal(_) | AppError::Database(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// The JSON body sent to clients, also used for errors reported inside an SSE stream
+    pub fn body(self) -> ErrorBody {
+        let message = self.to_string();
+        match self {
+            AppError::BadRequest(code, _)
+            | AppError::Unauthorized(code, _)
+            | AppError::NotFound(code, _)
+            | AppError::Conflict(code, _) => ErrorBody { code, message, details: None },
+            AppError::TooManyRequests(code, _, retry_after)
+            | AppError::ServiceUnavailable(code, _, retry_after) => ErrorBody {
+                code,
+                message,
+                details: Some(json!({ "retry_after": retry_after })),
+            },
+            AppError::Validation(fields) => ErrorBody {
+                code: "validation_failed",
+                message,
+                details: Some(json!({ "fields": fields })),
+            },
+            AppError::Upstream(_) => ErrorBody {
+                code: "upstream_unavailable",
+                message: "An upstream service failed, please try again later".to_string(),
+                details: None,
+            },
+            AppError::Internal(_) | AppError::Database(_) => ErrorBody {
+                code: "internal_error",
+                message: "Internal server error".to_string(),
+                details: None,
+            },
+        }
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        if status.is_server_error() {
+            eprintln!("{}", self);
+        }
+
+        let retry_after = match &self {
+            AppError::TooManyRequests(_, _, seconds) | AppError::ServiceUnavailable(_, _, seconds) => {
+                Some(*seconds)
+            }
+            _ => None,
+        };
+
+        let mut response = (status, Json(self.body())).into_response();
+        if let Some(seconds) = retry_after {
+            response.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from(seconds));
+        }
+        response
+    }
+}
+
+/// Foreign keys a request can violate because the row it refers to was deleted while the
+/// request was in flight, with the error reported for each. Violations of any other
+/// foreign key point at a bug and stay internal errors.
+const MISSING_REFERENCE_CONSTRAINTS: &[(&str, &str, &str)] = &[
+    ("stress_reframes_mental_box_id_fkey", "mental_box_entry_not_found", "Mental box entry not found"),
+    ("thought_analyses_mental_box_id_fkey", "mental_box_entry_not_found", "Mental box entry not found"),
+    ("thought_analyses_stress_reframe_id_fkey", "stress_reframe_not_found", "Stress reframe not found"),
+    ("worry_window_parked_entries_mental_box_id_fkey", "mental_box_entry_not_found", "Mental box entry not found"),
+    ("worry_window_parked_entries_worry_window_id_fkey", "worry_window_not_found", "Worry window not found"),
+    ("worry_window_occurrences_worry_window_id_fkey", "worry_window_not_found", "Worry window not found"),
+    (
+        "stress_reframe_feedback_stress_reframe_id_perspective_key_fkey",
+        "reframe_perspective_not_found",
+        "This reframe has no such perspective",
+    ),
+];
+
+impl From<sqlx::Error> for AppError {
+    fn from(e: sqlx::Error) -> Self {
+        match e {
+            sqlx::Error::RowNotFound => AppError::NotFound("not_found", "Resource not found".to_string()),
+            sqlx::Error::Database(ref db) if db.is_unique_violation() => {
+                AppError::Conflict("already_exists", "Resource already exists".to_string())
+            }
+            sqlx::Error::Database(ref db) if db.is_foreign_key_violation() => {
+                let missing_reference = MISSING_REFERENCE_CONSTRAINTS
+                    .iter()
+                    .find(|(constraint, _, _)| db.constraint() == Some(*constraint));
+                match missing_reference {
+                    Some((_, code, message)) => AppError::NotFound(code, message.to_string()),
+                    // Deleting a row that others still reference
+                    None if db.message().starts_with("update or delete") => {
+                        AppError::Conflict("still_referenced", "Resource is still in use".to_string())
+                    }
+                    None => AppError::Database(e),
+                }
+            }
+            e => AppError::Database(e),
+        }
+    }
+}
+
+// Flattens nested validator errors into `{"field.path": ["message", ...]}`
+fn collect_field_errors(errors: &ValidationErrors, prefix: &str, out: &mut Map<String, Value>) {
+    for (field, kind) in errors.errors() {
+        let path = if prefix.is_empty() {
+            field.to_string()
+        } else {
+            format!("{}.{}", prefix, field)
+        };
+        match kind {
+            ValidationErrorsKind::Field(field_errors) => {
+                let messages = field_errors
+                    .iter()
+                    .map(|e| {
+                        e.message
+                            .as_ref()
+                            .map(|m| m.to_string())
+                            .unwrap_or_else(|| format!("Failed the {} rule", e.code))
+                            .into()
+                    })
+                    .collect();
+                out.insert(path, Value::Array(messages));
+            }
+            ValidationErrorsKind::Struct(nested) => collect_field_errors(nested, &path, out),
+            ValidationErrorsKind::List(items) => {
+                for (index, nested) in items {
+                    collect_field_errors(nested, &format!("{}[{}]", path, index), out);
+                }
+            }
+        }
+    }
+}
+
+impl From<ValidationErrors> for AppError {
+    fn from(errors: ValidationErrors) -> Self {
+        let mut fields = Map::new();
+        collect_field_errors(&errors, "", &mut fields);
+        AppError::Validation(Value::Object(fields))
+    }
+}
+
+impl From<AuthError> for AppError {
+    fn from(e: AuthError) -> Self {
+        let message = e.to_string();
+        match e {
+            AuthError::EmailTaken => AppError::Conflict("email_taken", message),
+            AuthError::InvalidCredentials => AppError::Unauthorized("invalid_credentials", message),
+            AuthError::InvalidRefreshToken => AppError::Unauthorized("invalid_refresh_token", message),
+            AuthError::TooManyAttempts(retry_after) => {
+                AppError::TooManyRequests("too_many_login_attempts", message, retry_after as u64)
+            }
+            AuthError::Token(_) | AuthError::Hashing(_) => AppError::Internal(message),
+            AuthError::Database(e) => e.into(),
+        }
+    }
+}
+
+impl From<UserError> for AppError {
+    fn from(e: UserError) -> Self {
+        let message = e.to_string();
+        match e {
+            UserError::InvalidTimezone(_) => AppError::BadRequest("invalid_timezone", message),
+            UserError::InvalidLanguage(_) => AppError::BadRequest("invalid_language", message),
+            UserError::InvalidTheme(_) => AppError::BadRequest("invalid_theme", message),
+            UserError::InvalidPassword => AppError::Unauthorized("invalid_password", message),
+            UserError::EmailTaken => AppError::Conflict("email_taken", message),
+            UserError::NotFound => AppError::NotFound("user_not_found", message),
+            UserError::Hashing(_) => AppError::Internal(message),
+            UserError::Database(e) => e.into(),
+        }
+    }
+}
+
+impl From<PasswordResetError> for AppError {
+    fn from(e: PasswordResetError) -> Self {
+        let message = e.to_string();
+        match e {
+            PasswordResetError::InvalidToken => AppError::BadRequest("invalid_reset_token", message),
+            PasswordResetError::Hashing(_) => AppError::Internal(message),
+            PasswordResetError::Database(e) => e.into(),
+        }
+    }
+}
+
+impl From<SessionError> for AppError {
+    fn from(e: SessionError) -> Self {
+        let message = e.to_string();
+        match e {
+            SessionError::NotFound => AppError::NotFound("session_not_found", message),
+            SessionError::Database(e) => e.into(),
+        }
+    }
+}
+
+impl From<WorryWindowError> for AppError {
+    fn from(e: WorryWindowError) -> Self {
+        let message = e.to_string();
+        match e {
+            WorryWindowError::NotFound => AppError::NotFound("worry_window_not_found", message),
+            WorryWindowError::MentalBoxEntryNotFound => {
+                AppError::NotFound("mental_box_entry_not_found", message)
+            }
+            WorryWindowError::ParkedWorryNotFound => AppError::NotFound("parked_worry_not_found", message),
+            WorryWindowError::EmptyTitle => AppError::invalid_field("title", &message),
+            WorryWindowError::InvalidTimeRange => AppError::invalid_field("end_time", &message),
+            WorryWindowError::InvalidRecurrence(_) => AppError::invalid_field("recurrence", &message),
+            WorryWindowError::InvalidDateRange => AppError::BadRequest("invalid_date_range", message),
+            WorryWindowError::NotAnOccurrence => AppError::BadRequest("not_an_occurrence", message),
+            WorryWindowError::OccurrenceDateRequired => {
+                AppError::BadRequest("occurrence_date_required", message)
+            }
+            WorryWindowError::Overlap => AppError::Conflict("worry_window_overlap", message),
+            WorryWindowError::AlreadyParked => AppError::Conflict("already_parked", message),
+            WorryWindowError::ParkedWorriesOrphaned => {
+                AppError::Conflict("parked_worries_orphaned", message)
+            }
+            WorryWindowError::Database(e) => e.into(),
+        }
+    }
+}
+
+impl From<CalendarError> for AppError {
+    fn from(e: CalendarError) -> Self {
+        let message = e.to_string();
+        match e {
+            CalendarError::InvalidTimezone(_) => AppError::BadRequest("invalid_timezone", message),
+            CalendarError::FeedNotFound => AppError::NotFound("calendar_feed_not_found", message),
+            CalendarError::WorryWindow(e) => e.into(),
+            CalendarError::Database(e) => e.into(),
+        }
+    }
+}
+
+impl From<AiUsageError> for AppError {
+    fn from(e: AiUsageError) -> Self {
+        let message = e.to_string();
+        match e {
+            AiUsageError::QuotaExceeded { retry_after, .. } => {
+                AppError::TooManyRequests("ai_quota_exceeded", message, retry_after as u64)
+            }
+            AiUsageError::Database(e) => e.into(),
+        }
+    }
+}
+
+impl From<LlmError> for AppError {
+    fn from(e: LlmError) -> Self {
+        match e {
+            LlmError::MissingApiKey(_) => AppError::Internal(e.to_string()),
+            LlmError::CircuitOpen(_, retry_after) => AppError::ServiceUnavailable(
+                "ai_unavailable",
+                "The AI service is temporarily unavailable, please try again later".to_string(),
+                retry_after,
+            ),
+            e => AppError::Upstream(e.to_string()),
+        }
+    }
+}
+
+impl From<StressReframeError> for AppError {
+    fn from(e: StressReframeError) -> Self {
+        let message = e.to_string();
+        match e {
+            StressReframeError::UnknownPerspective(_) | StressReframeError::DuplicatePerspective(_) => {
+                AppError::invalid_field("perspectives", &message)
+            }
+            StressReframeError::NotFound => AppError::NotFound("stress_reframe_not_found", message),
+            StressReframeError::PerspectiveNotFound(_) => {
+                AppError::NotFound("reframe_perspective_not_found", message)
+            }
+            StressReframeError::InvalidCursor => AppError::BadRequest("invalid_cursor", message),
+            StressReframeError::Database(e) => e.into(),
+        }
+    }
+}
@@ -1,19 +1,42 @@
 use axum::{
-    extract::State,
+    extract::{ConnectInfo, State},
     http::{header, HeaderMap, StatusCode},
     Extension, Json,
 };
 use sqlx::PgPool;
+use std::{net::SocketAddr, sync::Arc};
 
-use crate::models::user::{AuthResponse, CreateUserRequest, LoginRequest, User};
+use crate::config::{LoginThrottleConfig, PasswordResetConfig};
+use crate::error::AppError;
+use crate::models::user::{
+    AuthResponse, CreateUserRequest, LoginRequest, PasswordResetConfirmRequest,
+    PasswordResetRequest, User,
+};
 use crate::services::auth_service;
+use crate::services::mailer_service::SharedMailer;
+use crate::services::password_reset_service;
+use crate::utils::client::ClientInfo;
+use crate::utils::jwt::JwtKeys;
+use crate::utils::validation::ValidatedJson;
+
+/// Extracts the refresh token from the `refresh_token` cookie
+pub fn refresh_token_from_cookies(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::COOKIE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|cookies| {
+            cookies
+                .split(';')
+                .find(|cookie| cookie.trim().starts_with("refresh_token="))
+                .map(|cookie| cookie.trim().strip_prefix("refresh_token=").unwrap_or(""))
+        })
+}
 
-fn create_refresh_token_cookie(refresh_token: &str) -> String {
-    // Create HTTP-only secure cookie
-    // Max-Age: 7 days in seconds
+fn create_refresh_token_cookie(refresh_token: &str, max_age: i64) -> String {
+    // Create HTTP-only secure cookie that lives as long as the refresh token itself
     format!(
-        "refresh_token={}; HttpOnly; Secure; SameSite=Strict; Path=/; Max-Age=604800",
-        refresh_token
+        "refresh_token={}; HttpOnly; Secure; SameSite=Strict; Path=/; Max-Age={}",
+        refresh_token, max_age
     )
 }
 
@@ -23,17 +46,19 @@ fn create_cookie_removal() -> String {
 
 pub async fn register(
     State(pool): State<PgPool>,
-    Json(payload): Json<CreateUserRequest>,
-) -> Result<(HeaderMap, Json<AuthResponse>), StatusCode> {
-    let auth_response = auth_service::register_user(&pool, payload)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    State(keys): State<Arc<JwtKeys>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    ValidatedJson(payload): ValidatedJson<CreateUserRequest>,
+) -> Result<(HeaderMap, Json<AuthResponse>), AppError> {
+    let client = ClientInfo::from_request(&headers, peer);
+    let auth_response = auth_service::register_user(&pool, &keys, payload, &client).await?;
 
     // Set refresh token as HTTP-only cookie
     let mut headers = HeaderMap::new();
     headers.insert(
         header::SET_COOKIE,
-        create_refresh_token_cookie(&auth_response.refresh_token)
+        create_refresh_token_cookie(&auth_response.refresh_token, keys.refresh_token_expiration())
             .parse()
             .unwrap(),
     );
@@ -43,17 +68,20 @@ pub async fn register(
 
 pub async fn login(
     State(pool): State<PgPool>,
-    Json(payload): Json<LoginRequest>,
-) -> Result<(HeaderMap, Json<AuthResponse>), StatusCode> {
-    let auth_response = auth_service::login_user(&pool, payload)
-        .await
-        .map_err(|_| StatusCode::UNAUTHORIZED)?;
+    State(keys): State<Arc<JwtKeys>>,
+    State(throttle): State<LoginThrottleConfig>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    ValidatedJson(payload): ValidatedJson<LoginRequest>,
+) -> Result<(HeaderMap, Json<AuthResponse>), AppError> {
+    let client = ClientInfo::from_request(&headers, peer);
+    let auth_response = auth_service::login_user(&pool, &keys, &throttle, payload, &client).await?;
 
     // Set refresh token as HTTP-only cookie
     let mut headers = HeaderMap::new();
     headers.insert(
         header::SET_COOKIE,
-        create_refresh_token_cookie(&auth_response.refresh_token)
+        create_refresh_token_cookie(&auth_response.refresh_token, keys.refresh_token_expiration())
             .parse()
             .unwrap(),
     );
@@ -63,29 +91,21 @@ pub async fn login(
 
 pub async fn refresh(
     State(pool): State<PgPool>,
+    State(keys): State<Arc<JwtKeys>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
     headers: HeaderMap,
-) -> Result<(HeaderMap, Json<AuthResponse>), StatusCode> {
-    // Extract refresh token from cookie
-    let refresh_token = headers
-        .get(header::COOKIE)
-        .and_then(|value| value.to_str().ok())
-        .and_then(|cookies| {
-            cookies
-                .split(';')
-                .find(|cookie| cookie.trim().starts_with("refresh_token="))
-                .map(|cookie| cookie.trim().strip_prefix("refresh_token=").unwrap_or(""))
-        })
-        .ok_or(StatusCode::UNAUTHORIZED)?;
+) -> Result<(HeaderMap, Json<AuthResponse>), AppError> {
+    let refresh_token = refresh_token_from_cookies(&headers).ok_or_else(AppError::unauthorized)?;
+    let client = ClientInfo::from_request(&headers, peer);
 
-    let auth_response = auth_service::refresh_access_token(&pool, refresh_token)
-        .await
-        .map_err(|_| StatusCode::UNAUTHORIZED)?;
+    let auth_response =
+        auth_service::refresh_access_token(&pool, &keys, refresh_token, &client).await?;
 
     // Set new refresh token as HTTP-only cookie
     let mut response_headers = HeaderMap::new();
     response_headers.insert(
         header::SET_COOKIE,
-        create_refresh_token_cookie(&auth_response.refresh_token)
+        create_refresh_token_cookie(&auth_response.refresh_token, keys.refresh_token_expiration())
             .parse()
             .unwrap(),
     );
@@ -95,23 +115,11 @@ pub async fn refresh(
 
 pub async fn logout(
     State(pool): State<PgPool>,
+    State(keys): State<Arc<JwtKeys>>,
     headers: HeaderMap,
-) -> Result<(HeaderMap, StatusCode), StatusCode> {
-    // Extract refresh token from cookie
-    let refresh_token = headers
-        .get(header::COOKIE)
-        .and_then(|value| value.to_str().ok())
-        .and_then(|cookies| {
-            cookies
-                .split(';')
-                .find(|cookie| cookie.trim().starts_with("refresh_token="))
-                .map(|cookie| cookie.trim().strip_prefix("refresh_token=").unwrap_or(""))
-        });
-
-    if let Some(token) = refresh_token {
-        auth_service::revoke_refresh_token(&pool, token)
-            .await
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+) -> Result<(HeaderMap, StatusCode), AppError> {
+    if let Some(token) = refresh_token_from_cookies(&headers) {
+        auth_service::revoke_refresh_token(&pool, &keys, token).await?;
     }
 
     // Clear refresh token cookie
@@ -124,6 +132,29 @@ pub async fn logout(
     Ok((response_headers, StatusCode::NO_CONTENT))
 }
 
-pub async fn me(Extension(user): Extension<User>) -> Result<Json<User>, StatusCode> {
+pub async fn me(Extension(user): Extension<User>) -> Result<Json<User>, AppError> {
     Ok(Json(user))
 }
+
+pub async fn request_password_reset(
+    State(pool): State<PgPool>,
+    State(reset_config): State<PasswordResetConfig>,
+    Extension(mailer): Extension<SharedMailer>,
+    ValidatedJson(payload): ValidatedJson<PasswordResetRequest>,
+) -> StatusCode {
+    // Always accept so the response does not reveal whether the email is registered
+    if let Err(e) = password_reset_service::request_reset(&pool, &reset_config, mailer, &payload.email).await {
+        eprintln!("Password reset request error: {}", e);
+    }
+
+    StatusCode::ACCEPTED
+}
+
+pub async fn confirm_password_reset(
+    State(pool): State<PgPool>,
+    ValidatedJson(payload): ValidatedJson<PasswordResetConfirmRequest>,
+) -> Result<StatusCode, AppError> {
+    password_reset_service::confirm_reset(&pool, payload).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,80 @@
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Extension, Json,
+};
+use sqlx::PgPool;
+
+use crate::config::PublicUrl;
+use crate::models::calendar_feed::{CalendarExportQuery, CalendarFeedResponse, CalendarFeedToken};
+use crate::models::user::User;
+use crate::error::AppError;
+use crate::services::calendar_service;
+use crate::utils::validation::{ValidatedPath, ValidatedQuery};
+
+fn ics_response(body: String) -> impl IntoResponse {
+    (
+        [
+            (header::CONTENT_TYPE, "text/calendar; charset=utf-8"),
+            (header::CONTENT_DISPOSITION, "attachment; filename=\"worry-windows.ics\""),
+        ],
+        body,
+    )
+}
+
+pub async fn export(
+    State(pool): State<PgPool>,
+    Extension(user): Extension<User>,
+    ValidatedQuery(params): ValidatedQuery<CalendarExportQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    let tz = calendar_service::parse_timezone(params.tz.as_deref(), user.tz())?;
+    let body = calendar_service::export_ics(&pool, user.id, tz).await?;
+
+    Ok(ics_response(body))
+}
+
+pub async fn get_feed(
+    State(pool): State<PgPool>,
+    Extension(user): Extension<User>,
+) -> Result<Json<CalendarFeedToken>, AppError> {
+    let feed = calendar_service::get_feed_token(&pool, user.id).await?;
+
+    Ok(Json(feed))
+}
+
+pub async fn rotate_feed(
+    State(pool): State<PgPool>,
+    State(public_url): State<PublicUrl>,
+    Extension(user): Extension<User>,
+) -> Result<Json<CalendarFeedResponse>, AppError> {
+    let (token, created_at) = calendar_service::rotate_feed_token(&pool, user.id).await?;
+
+    Ok(Json(CalendarFeedResponse {
+        url: public_url.join(&format!("/api/calendar/{}/worry-windows.ics", token)),
+        token,
+        created_at,
+    }))
+}
+
+pub async fn revoke_feed(
+    State(pool): State<PgPool>,
+    Extension(user): Extension<User>,
+) -> Result<StatusCode, AppError> {
+    calendar_service::revoke_feed_token(&pool, user.id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Public subscription endpoint; the secret token in the path stands in for a JWT
+pub async fn feed(
+    State(pool): State<PgPool>,
+    ValidatedPath(token): ValidatedPath<String>,
+    ValidatedQuery(params): ValidatedQuery<CalendarExportQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    let (user_id, user_tz) = calendar_service::find_feed_owner(&pool, &token).await?;
+    let tz = calendar_service::parse_timezone(params.tz.as_deref(), user_tz)?;
+    let body = calendar_service::export_ics(&pool, user_id, tz).await?;
+
+    Ok(ics_response(body))
+}
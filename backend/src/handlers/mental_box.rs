@@ -1,15 +1,27 @@
-use axum::{extract::{Path, State}, http::StatusCode, Extension, Json};
+use axum::{extract::State, http::StatusCode, Extension, Json};
 use sqlx::PgPool;
+use std::sync::Arc;
 use uuid::Uuid;
 
+use crate::config::AiQuotaConfig;
+use crate::error::AppError;
 use crate::models::mental_box::{CreateMentalBoxRequest, MentalBoxEntry, UpdateMentalBoxRequest};
+use crate::models::thought_analysis::ThoughtAnalysis;
 use crate::models::user::User;
+use crate::services::ai_usage_service;
+use crate::services::reframer_service::Reframer;
+use crate::services::thought_analysis_service::{self, AnalysisTarget};
+use crate::utils::validation::{ValidatedJson, ValidatedPath};
+
+fn entry_not_found() -> AppError {
+    AppError::NotFound("mental_box_entry_not_found", "Mental box entry not found".to_string())
+}
 
 pub async fn create(
     State(pool): State<PgPool>,
     Extension(user): Extension<User>,
-    Json(payload): Json<CreateMentalBoxRequest>,
-) -> Result<Json<MentalBoxEntry>, StatusCode> {
+    ValidatedJson(payload): ValidatedJson<CreateMentalBoxRequest>,
+) -> Result<Json<MentalBoxEntry>, AppError> {
     let entry = sqlx::query_as::<_, MentalBoxEntry>(
         r#"
         INSERT INTO mental_box_entries (user_id, title, content)
@@ -21,11 +33,7 @@ pub async fn create(
     .bind(&payload.title)
     .bind(&payload.content)
     .fetch_one(&pool)
-    .await
-    .map_err(|e| {
-        eprintln!("Database error creating mental box entry: {}", e);
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?;
+    .await?;
 
     Ok(Json(entry))
 }
@@ -33,7 +41,7 @@ pub async fn create(
 pub async fn list(
     State(pool): State<PgPool>,
     Extension(user): Extension<User>,
-) -> Result<Json<Vec<MentalBoxEntry>>, StatusCode> {
+) -> Result<Json<Vec<MentalBoxEntry>>, AppError> {
     let entries = sqlx::query_as::<_, MentalBoxEntry>(
         r#"
         SELECT id, user_id, title, content, created_at, updated_at
@@ -44,11 +52,7 @@ pub async fn list(
     )
     .bind(user.id)
     .fetch_all(&pool)
-    .await
-    .map_err(|e| {
-        eprintln!("Database error listing mental box entries: {}", e);
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?;
+    .await?;
 
     Ok(Json(entries))
 }
@@ -56,8 +60,8 @@ pub async fn list(
 pub async fn get_by_id(
     State(pool): State<PgPool>,
     Extension(user): Extension<User>,
-    Path(id): Path<Uuid>,
-) -> Result<Json<MentalBoxEntry>, StatusCode> {
+    ValidatedPath(id): ValidatedPath<Uuid>,
+) -> Result<Json<MentalBoxEntry>, AppError> {
     let entry = sqlx::query_as::<_, MentalBoxEntry>(
         r#"
         SELECT id, user_id, title, content, created_at, updated_at
@@ -68,12 +72,8 @@ pub async fn get_by_id(
     .bind(id)
     .bind(user.id)
     .fetch_optional(&pool)
-    .await
-    .map_err(|e| {
-        eprintln!("Database error getting mental box entry: {}", e);
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?
-    .ok_or(StatusCode::NOT_FOUND)?;
+    .await?
+    .ok_or_else(entry_not_found)?;
 
     Ok(Json(entry))
 }
@@ -81,9 +81,9 @@ pub async fn get_by_id(
 pub async fn update(
     State(pool): State<PgPool>,
     Extension(user): Extension<User>,
-    Path(id): Path<Uuid>,
-    Json(payload): Json<UpdateMentalBoxRequest>,
-) -> Result<Json<MentalBoxEntry>, StatusCode> {
+    ValidatedPath(id): ValidatedPath<Uuid>,
+    ValidatedJson(payload): ValidatedJson<UpdateMentalBoxRequest>,
+) -> Result<Json<MentalBoxEntry>, AppError> {
     // First verify the entry belongs to the user
     let existing = sqlx::query_as::<_, MentalBoxEntry>(
         r#"
@@ -95,12 +95,8 @@ pub async fn update(
     .bind(id)
     .bind(user.id)
     .fetch_optional(&pool)
-    .await
-    .map_err(|e| {
-        eprintln!("Database error checking mental box entry: {}", e);
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?
-    .ok_or(StatusCode::NOT_FOUND)?;
+    .await?
+    .ok_or_else(entry_not_found)?;
 
     // Build dynamic update query
     let mut query_parts = vec![];
@@ -132,11 +128,7 @@ pub async fn update(
         .bind(id)
         .bind(user.id)
         .fetch_one(&pool)
-        .await
-        .map_err(|e| {
-            eprintln!("Database error updating mental box entry: {}", e);
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?;
+        .await?;
 
     Ok(Json(updated_entry))
 }
@@ -144,8 +136,8 @@ pub async fn update(
 pub async fn delete(
     State(pool): State<PgPool>,
     Extension(user): Extension<User>,
-    Path(id): Path<Uuid>,
-) -> Result<StatusCode, StatusCode> {
+    ValidatedPath(id): ValidatedPath<Uuid>,
+) -> Result<StatusCode, AppError> {
     let result = sqlx::query(
         r#"
         DELETE FROM mental_box_entries
@@ -155,15 +147,88 @@ pub async fn delete(
     .bind(id)
     .bind(user.id)
     .execute(&pool)
-    .await
-    .map_err(|e| {
-        eprintln!("Database error deleting mental box entry: {}", e);
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?;
+    .await?;
 
     if result.rows_affected() == 0 {
-        return Err(StatusCode::NOT_FOUND);
+        return Err(entry_not_found());
     }
 
     Ok(StatusCode::NO_CONTENT)
 }
+
+/// Classifies the entry's content into cognitive distortions. The latest analysis is
+/// returned as is unless the entry has been edited since.
+pub async fn analyze(
+    State(pool): State<PgPool>,
+    State(quota): State<AiQuotaConfig>,
+    State(reframer): State<Arc<Reframer>>,
+    Extension(user): Extension<User>,
+    ValidatedPath(id): ValidatedPath<Uuid>,
+) -> Result<Json<ThoughtAnalysis>, AppError> {
+    let entry = sqlx::query_as::<_, MentalBoxEntry>(
+        r#"
+        SELECT id, user_id, title, content, created_at, updated_at
+        FROM mental_box_entries
+        WHERE id = $1 AND user_id = $2
+        "#,
+    )
+    .bind(id)
+    .bind(user.id)
+    .fetch_optional(&pool)
+    .await?
+    .ok_or_else(entry_not_found)?;
+
+    if let Some(analysis) = thought_analysis_service::latest_for_entry(&pool, user.id, entry.id).await? {
+        if analysis.created_at >= entry.updated_at {
+            return Ok(Json(analysis));
+        }
+    }
+
+    let reservation = ai_usage_service::reserve(&pool, &quota, &user, 1).await?;
+    let analysis = analyze_entry(&pool, &reframer, user.id, &entry).await;
+    ai_usage_service::release(&pool, reservation).await;
+
+    Ok(Json(analysis?))
+}
+
+// Runs and saves a fresh analysis, recording the tokens it used
+async fn analyze_entry(
+    pool: &PgPool,
+    reframer: &Reframer,
+    user_id: Uuid,
+    entry: &MentalBoxEntry,
+) -> Result<ThoughtAnalysis, AppError> {
+    let result = reframer.analyze(user_id, &entry.content).await?;
+    let analysis =
+        thought_analysis_service::save_analysis(pool, user_id, AnalysisTarget::MentalBoxEntry(entry.id), &result)
+            .await?;
+
+    ai_usage_service::record_usage(
+        pool,
+        user_id,
+        thought_analysis_service::FEATURE_DISTORTION_ANALYSIS,
+        &result.model,
+        &result.usage,
+        None,
+    )
+    .await?;
+
+    Ok(analysis)
+}
+
+pub async fn get_analysis(
+    State(pool): State<PgPool>,
+    Extension(user): Extension<User>,
+    ValidatedPath(id): ValidatedPath<Uuid>,
+) -> Result<Json<ThoughtAnalysis>, AppError> {
+    let analysis = thought_analysis_service::latest_for_entry(&pool, user.id, id)
+        .await?
+        .ok_or_else(|| {
+            AppError::NotFound(
+                "thought_analysis_not_found",
+                "This entry has not been analysed yet".to_string(),
+            )
+        })?;
+
+    Ok(Json(analysis))
+}
@@ -1,4 +1,8 @@
 pub mod auth;
+pub mod calendar;
 pub mod mental_box;
 pub mod mood_tracker;
+pub mod session;
 pub mod stress_reframe;
+pub mod user;
+pub mod worry_window;
@@ -1,5 +1,5 @@
 use axum::{
-    extract::{Path, Query, State},
+    extract::State,
     http::StatusCode,
     Extension,
     Json,
@@ -7,10 +7,12 @@ use axum::{
 use sqlx::PgPool;
 use uuid::Uuid;
 
+use crate::error::AppError;
 use crate::models::mood_tracker::{
-    CreateMoodEntryRequest, MoodEntry, UpdateMoodEntryRequest, MoodStats,
+    CreateMoodEntryRequest, DailyMoodSummary, MoodEntry, UpdateMoodEntryRequest, MoodStats,
 };
 use crate::models::user::User;
+use crate::utils::validation::{ValidatedJson, ValidatedPath, ValidatedQuery};
 
 #[derive(serde::Deserialize)]
 pub struct RecentQuery {
@@ -22,16 +24,21 @@ fn default_limit() -> i64 {
     7
 }
 
+#[derive(serde::Deserialize)]
+pub struct DailyQuery {
+    #[serde(default = "default_days")]
+    days: i32,
+}
+
+fn default_days() -> i32 {
+    30
+}
+
 pub async fn create(
     State(pool): State<PgPool>,
     Extension(user): Extension<User>,
-    Json(payload): Json<CreateMoodEntryRequest>,
-) -> Result<Json<MoodEntry>, StatusCode> {
-    // Validate stress level
-    if payload.stress_level < 1 || payload.stress_level > 10 {
-        return Err(StatusCode::BAD_REQUEST);
-    }
-
+    ValidatedJson(payload): ValidatedJson<CreateMoodEntryRequest>,
+) -> Result<Json<MoodEntry>, AppError> {
     let mood_str = payload.mood.to_string();
 
     let entry = sqlx::query_as::<_, MoodEntry>(
@@ -47,8 +54,7 @@ pub async fn create(
     .bind(payload.note)
     .bind(payload.activities)
     .fetch_one(&pool)
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    .await?;
 
     Ok(Json(entry))
 }
@@ -56,7 +62,7 @@ pub async fn create(
 pub async fn list(
     State(pool): State<PgPool>,
     Extension(user): Extension<User>,
-) -> Result<Json<Vec<MoodEntry>>, StatusCode> {
+) -> Result<Json<Vec<MoodEntry>>, AppError> {
     let entries = sqlx::query_as::<_, MoodEntry>(
         r#"
         SELECT id, user_id, mood, stress_level, note, activities, created_at, updated_at
@@ -67,8 +73,7 @@ pub async fn list(
     )
     .bind(user.id)
     .fetch_all(&pool)
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    .await?;
 
     Ok(Json(entries))
 }
@@ -76,8 +81,8 @@ pub async fn list(
 pub async fn get_recent(
     State(pool): State<PgPool>,
     Extension(user): Extension<User>,
-    Query(params): Query<RecentQuery>,
-) -> Result<Json<Vec<MoodEntry>>, StatusCode> {
+    ValidatedQuery(params): ValidatedQuery<RecentQuery>,
+) -> Result<Json<Vec<MoodEntry>>, AppError> {
     let entries = sqlx::query_as::<_, MoodEntry>(
         r#"
         SELECT id, user_id, mood, stress_level, note, activities, created_at, updated_at
@@ -90,8 +95,7 @@ pub async fn get_recent(
     .bind(user.id)
     .bind(params.limit)
     .fetch_all(&pool)
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    .await?;
 
     Ok(Json(entries))
 }
@@ -99,8 +103,8 @@ pub async fn get_recent(
 pub async fn get_by_id(
     State(pool): State<PgPool>,
     Extension(user): Extension<User>,
-    Path(id): Path<Uuid>,
-) -> Result<Json<MoodEntry>, StatusCode> {
+    ValidatedPath(id): ValidatedPath<Uuid>,
+) -> Result<Json<MoodEntry>, AppError> {
     let entry = sqlx::query_as::<_, MoodEntry>(
         r#"
         SELECT id, user_id, mood, stress_level, note, activities, created_at, updated_at
@@ -111,11 +115,7 @@ pub async fn get_by_id(
     .bind(id)
     .bind(user.id)
     .fetch_one(&pool)
-    .await
-    .map_err(|e| match e {
-        sqlx::Error::RowNotFound => StatusCode::NOT_FOUND,
-        _ => StatusCode::INTERNAL_SERVER_ERROR,
-    })?;
+    .await?;
 
     Ok(Json(entry))
 }
@@ -123,14 +123,12 @@ pub async fn get_by_id(
 pub async fn get_stats(
     State(pool): State<PgPool>,
     Extension(user): Extension<User>,
-) -> Result<Json<MoodStats>, StatusCode> {
+) -> Result<Json<MoodStats>, AppError> {
+    // Day boundaries are midnight in the user's timezone; "this week" is the last 7 local days
     let stats = sqlx::query_as::<_, MoodStats>(
         r#"
-        WITH recent_week AS (
-            SELECT *
-            FROM mood_tracker
-            WHERE user_id = $1
-            AND created_at >= NOW() - INTERVAL '7 days'
+        WITH local_today AS (
+            SELECT date_trunc('day', NOW() AT TIME ZONE $2) AS midnight
         )
         SELECT
             COALESCE(AVG(stress_level), 0)::FLOAT8 as average_stress,
@@ -144,32 +142,61 @@ pub async fn get_stats(
                 'okay'
             ) as most_common_mood,
             COUNT(*)::BIGINT as total_entries,
-            (SELECT COUNT(*)::BIGINT FROM recent_week) as entries_this_week
+            COUNT(*) FILTER (
+                WHERE created_at >= (SELECT midnight FROM local_today) AT TIME ZONE $2
+            )::BIGINT as entries_today,
+            COUNT(*) FILTER (
+                WHERE created_at >= ((SELECT midnight FROM local_today) - INTERVAL '6 days') AT TIME ZONE $2
+            )::BIGINT as entries_this_week
         FROM mood_tracker
         WHERE user_id = $1
         "#,
     )
     .bind(user.id)
+    .bind(user.tz().name())
     .fetch_one(&pool)
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    .await?;
 
     Ok(Json(stats))
 }
 
-pub async fn update(
+pub async fn get_daily(
     State(pool): State<PgPool>,
     Extension(user): Extension<User>,
-    Path(id): Path<Uuid>,
-    Json(payload): Json<UpdateMoodEntryRequest>,
-) -> Result<Json<MoodEntry>, StatusCode> {
-    // Validate stress level if provided
-    if let Some(level) = payload.stress_level {
-        if level < 1 || level > 10 {
-            return Err(StatusCode::BAD_REQUEST);
-        }
+    ValidatedQuery(params): ValidatedQuery<DailyQuery>,
+) -> Result<Json<Vec<DailyMoodSummary>>, AppError> {
+    if !(1..=366).contains(&params.days) {
+        return Err(AppError::invalid_field("days", "Days must be between 1 and 366"));
     }
 
+    let summaries = sqlx::query_as::<_, DailyMoodSummary>(
+        r#"
+        SELECT
+            (created_at AT TIME ZONE $2)::DATE as date,
+            AVG(stress_level)::FLOAT8 as average_stress,
+            COUNT(*)::BIGINT as entry_count
+        FROM mood_tracker
+        WHERE user_id = $1
+        AND created_at >= (date_trunc('day', NOW() AT TIME ZONE $2) - ($3 - 1) * INTERVAL '1 day') AT TIME ZONE $2
+        GROUP BY 1
+        ORDER BY 1
+        "#,
+    )
+    .bind(user.id)
+    .bind(user.tz().name())
+    .bind(params.days)
+    .fetch_all(&pool)
+    .await?;
+
+    Ok(Json(summaries))
+}
+
+pub async fn update(
+    State(pool): State<PgPool>,
+    Extension(user): Extension<User>,
+    ValidatedPath(id): ValidatedPath<Uuid>,
+    ValidatedJson(payload): ValidatedJson<UpdateMoodEntryRequest>,
+) -> Result<Json<MoodEntry>, AppError> {
     // Build dynamic update query
     let mut query = String::from("UPDATE mood_tracker SET updated_at = NOW()");
     let mut param_count = 3; // Start from $3 (id is $1, user_id is $2)
@@ -211,11 +238,7 @@ pub async fn update(
 
     let entry = query_builder
         .fetch_one(&pool)
-        .await
-        .map_err(|e| match e {
-            sqlx::Error::RowNotFound => StatusCode::NOT_FOUND,
-            _ => StatusCode::INTERNAL_SERVER_ERROR,
-        })?;
+        .await?;
 
     Ok(Json(entry))
 }
@@ -223,8 +246,8 @@ pub async fn update(
 pub async fn delete(
     State(pool): State<PgPool>,
     Extension(user): Extension<User>,
-    Path(id): Path<Uuid>,
-) -> Result<StatusCode, StatusCode> {
+    ValidatedPath(id): ValidatedPath<Uuid>,
+) -> Result<StatusCode, AppError> {
     let result = sqlx::query(
         r#"
         DELETE FROM mood_tracker
@@ -234,11 +257,10 @@ pub async fn delete(
     .bind(id)
     .bind(user.id)
     .execute(&pool)
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    .await?;
 
     if result.rows_affected() == 0 {
-        return Err(StatusCode::NOT_FOUND);
+        return Err(AppError::NotFound("mood_entry_not_found", "Mood entry not found".to_string()));
     }
 
     Ok(StatusCode::NO_CONTENT)
@@ -0,0 +1,50 @@
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    Extension, Json,
+};
+use sqlx::PgPool;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::error::AppError;
+use crate::handlers::auth::refresh_token_from_cookies;
+use crate::models::session::{RevokeSessionsResponse, Session};
+use crate::models::user::User;
+use crate::services::session_service;
+use crate::utils::jwt::JwtKeys;
+use crate::utils::validation::ValidatedPath;
+
+pub async fn list(
+    State(pool): State<PgPool>,
+    State(keys): State<Arc<JwtKeys>>,
+    Extension(user): Extension<User>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<Session>>, AppError> {
+    let current = refresh_token_from_cookies(&headers).map(|token| keys.hash_refresh_token(token));
+    let sessions = session_service::list_sessions(&pool, user.id, current.as_deref()).await?;
+
+    Ok(Json(sessions))
+}
+
+pub async fn revoke(
+    State(pool): State<PgPool>,
+    Extension(user): Extension<User>,
+    ValidatedPath(id): ValidatedPath<Uuid>,
+) -> Result<StatusCode, AppError> {
+    session_service::revoke_session(&pool, user.id, id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn revoke_others(
+    State(pool): State<PgPool>,
+    State(keys): State<Arc<JwtKeys>>,
+    Extension(user): Extension<User>,
+    headers: HeaderMap,
+) -> Result<Json<RevokeSessionsResponse>, AppError> {
+    let current = refresh_token_from_cookies(&headers).map(|token| keys.hash_refresh_token(token));
+    let revoked = session_service::revoke_other_sessions(&pool, user.id, current.as_deref()).await?;
+
+    Ok(Json(RevokeSessionsResponse { revoked }))
+}
@@ -1,118 +1,578 @@
-use axum::{extract::State, http::StatusCode, Extension, Json};
+use axum::{
+    extract::State,
+    http::{HeaderMap, HeaderValue, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse,
+    },
+    Extension, Json,
+};
+use serde_json::json;
 use sqlx::PgPool;
+use std::{convert::Infallible, sync::Arc};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use uuid::Uuid;
 
-use crate::models::stress_reframe::{CreateReframeRequest, ReframeResponse, StressReframe};
+use crate::config::AiQuotaConfig;
+use crate::error::AppError;
+use crate::models::ai_usage::AiQuotaResponse;
+use crate::models::stress_reframe::{
+    CreateReframeRequest, ListReframesQuery, Perspective, PerspectiveFeedback, RatePerspectiveRequest,
+    ReframeResponse, RegenerateReframeRequest, DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE,
+};
+use crate::models::thought_analysis::ThoughtAnalysis;
 use crate::models::user::User;
-use crate::services::openrouter_service;
+use crate::services::ai_usage_service;
+use crate::services::llm_service::{
+    DeltaSink, DistortionAnalysis, LlmError, ReframeDelta, ReframeGeneration, ReframeRequest,
+};
+use crate::services::reframer_service::Reframer;
+use crate::services::stress_reframe_service::{self, ReframeFilter};
+use crate::services::thought_analysis_service::{self, AnalysisTarget};
+use crate::utils::validation::{ValidatedJson, ValidatedPath, ValidatedQuery};
+
+/// Events buffered for a slow client before generation waits for it to catch up
+const STREAM_BUFFER: usize = 64;
+
+/// Response header carrying the cursor of the next page of reframes
+pub const NEXT_CURSOR_HEADER: &str = "x-next-cursor";
+
+/// Where a reframe about to be generated will be stored
+struct NewReframe {
+    mental_box_id: Option<Uuid>,
+    original_thought: String,
+    /// Set when regenerating, so the reframe becomes the next version of that thread
+    thread_id: Option<Uuid>,
+}
+
+impl From<CreateReframeRequest> for NewReframe {
+    fn from(payload: CreateReframeRequest) -> Self {
+        Self {
+            mental_box_id: payload.mental_box_id,
+            original_thought: payload.original_thought,
+            thread_id: None,
+        }
+    }
+}
+
+/// Where the distortion analysis shown with a new reframe comes from
+enum SideAnalysis {
+    /// Turned off with `LLM_ANALYZE_REFRAMES=false`
+    Skipped,
+    /// An earlier analysis of the same thought, copied onto the new reframe
+    Reused(ThoughtAnalysis),
+    /// Made by the model alongside the reframe
+    Fresh,
+}
+
+impl SideAnalysis {
+    async fn choose(
+        pool: &PgPool,
+        reframer: &Reframer,
+        user_id: Uuid,
+        reframe: &NewReframe,
+    ) -> Result<Self, AppError> {
+        if !reframer.analyzes_reframes() {
+            return Ok(SideAnalysis::Skipped);
+        }
+
+        let reusable = thought_analysis_service::find_reusable(
+            pool,
+            user_id,
+            &reframe.original_thought,
+            reframe.mental_box_id,
+            reframe.thread_id,
+        )
+        .await?;
+        Ok(reusable.map_or(SideAnalysis::Fresh, SideAnalysis::Reused))
+    }
+
+    /// LLM calls a generated reframe makes, which the quota reservation has to cover
+    fn calls(&self) -> i64 {
+        match self {
+            SideAnalysis::Fresh => 2,
+            SideAnalysis::Skipped | SideAnalysis::Reused(_) => 1,
+        }
+    }
+
+    async fn run(
+        &self,
+        reframer: &Reframer,
+        user_id: Uuid,
+        thought: &str,
+    ) -> Option<Result<DistortionAnalysis, LlmError>> {
+        match self {
+            SideAnalysis::Fresh => Some(reframer.analyze(user_id, thought).await),
+            SideAnalysis::Skipped | SideAnalysis::Reused(_) => None,
+        }
+    }
+}
+
+// Stores a generated reframe and records the tokens it used
+async fn save_reframe(
+    pool: &PgPool,
+    user_id: Uuid,
+    reframe: &NewReframe,
+    generation: &ReframeGeneration,
+) -> Result<ReframeResponse, AppError> {
+    let reframe = stress_reframe_service::save_reframe(
+        pool,
+        user_id,
+        reframe.mental_box_id,
+        &reframe.original_thought,
+        reframe.thread_id,
+        generation,
+    )
+    .await?;
+
+    ai_usage_service::record_usage(
+        pool,
+        user_id,
+        ai_usage_service::FEATURE_STRESS_REFRAME,
+        &generation.model,
+        &generation.usage,
+        Some(reframe.id),
+    )
+    .await?;
+
+    Ok(reframe)
+}
+
+// Records the distortion analysis run alongside a reframe and stores it when the reframe
+// was saved, or copies the reused one onto it. The analysis is extra context, so its
+// failures are logged rather than failing the reframe.
+async fn save_analysis(
+    pool: &PgPool,
+    user_id: Uuid,
+    stress_reframe_id: Option<Uuid>,
+    side: &SideAnalysis,
+    result: Option<Result<DistortionAnalysis, LlmError>>,
+) -> Option<ThoughtAnalysis> {
+    if let SideAnalysis::Reused(existing) = side {
+        let target = AnalysisTarget::StressReframe(stress_reframe_id?);
+        return match thought_analysis_service::copy_analysis(pool, user_id, existing.id, target).await {
+            Ok(copied) => Some(copied),
+            Err(e) => {
+                tracing::error!("Failed to copy distortion analysis: {}", e);
+                None
+            }
+        };
+    }
+
+    let analysis = match result? {
+        Ok(analysis) => analysis,
+        Err(e) => {
+            tracing::warn!("Distortion analysis failed: {}", e);
+            return None;
+        }
+    };
+
+    let recorded = ai_usage_service::record_usage(
+        pool,
+        user_id,
+        thought_analysis_service::FEATURE_DISTORTION_ANALYSIS,
+        &analysis.model,
+        &analysis.usage,
+        stress_reframe_id,
+    )
+    .await;
+    if let Err(e) = recorded {
+        tracing::error!("Failed to record usage of a distortion analysis: {}", e);
+    }
+
+    let target = AnalysisTarget::StressReframe(stress_reframe_id?);
+    match thought_analysis_service::save_analysis(pool, user_id, target, &analysis).await {
+        Ok(saved) => Some(saved),
+        Err(e) => {
+            tracing::error!("Failed to save distortion analysis: {}", e);
+            None
+        }
+    }
+}
+
+// Generates the reframe and, when needed, its distortion analysis side by side, then
+// stores both
+async fn generate_reframe(
+    pool: &PgPool,
+    reframer: &Reframer,
+    user_id: Uuid,
+    reframe: &NewReframe,
+    side: &SideAnalysis,
+    request: &ReframeRequest,
+) -> Result<ReframeResponse, AppError> {
+    let (generation, analysis) = tokio::join!(
+        reframer.generate(user_id, request),
+        side.run(reframer, user_id, &reframe.original_thought),
+    );
+    let generation = match generation {
+        Ok(generation) => generation,
+        Err(e) => {
+            save_analysis(pool, user_id, None, side, analysis).await;
+            return Err(e.into());
+        }
+    };
+    let mut saved = save_reframe(pool, user_id, reframe, &generation).await?;
+    saved.analysis = save_analysis(pool, user_id, Some(saved.id), side, analysis).await;
+
+    Ok(saved)
+}
 
 pub async fn create(
     State(pool): State<PgPool>,
+    State(quota): State<AiQuotaConfig>,
+    State(reframer): State<Arc<Reframer>>,
     Extension(user): Extension<User>,
-    Json(payload): Json<CreateReframeRequest>,
-) -> Result<Json<ReframeResponse>, StatusCode> {
-    // Validate input
-    if payload.original_thought.trim().is_empty() {
-        return Err(StatusCode::BAD_REQUEST);
+    ValidatedJson(payload): ValidatedJson<CreateReframeRequest>,
+) -> Result<Json<ReframeResponse>, AppError> {
+    let perspectives =
+        stress_reframe_service::resolve_perspectives(&pool, user.id, payload.perspectives.as_deref()).await?;
+
+    // If a reframe exists for this mental_box_id, return the cached (or pinned) result
+    if let Some(reframe) =
+        stress_reframe_service::find_cached(&pool, user.id, payload.mental_box_id, &perspectives).await?
+    {
+        return Ok(Json(reframe));
     }
 
-    if payload.original_thought.len() > 1000 {
-        return Err(StatusCode::BAD_REQUEST);
+    // Generate reframes using AI (only if no cached result)
+    let request = stress_reframe_service::build_request(&payload.original_thought, &perspectives);
+    let new_reframe = NewReframe::from(payload);
+    let side = SideAnalysis::choose(&pool, &reframer, user.id, &new_reframe).await?;
+    let reservation = ai_usage_service::reserve(&pool, &quota, &user, side.calls()).await?;
+    let reframe = generate_reframe(&pool, &reframer, user.id, &new_reframe, &side, &request).await;
+    ai_usage_service::release(&pool, reservation).await;
+
+    Ok(Json(reframe?))
+}
+
+/// Generates a fresh version of a reframe, bypassing the cache. Earlier versions are kept
+/// and listed by `history`.
+pub async fn regenerate(
+    State(pool): State<PgPool>,
+    State(quota): State<AiQuotaConfig>,
+    State(reframer): State<Arc<Reframer>>,
+    Extension(user): Extension<User>,
+    ValidatedPath(id): ValidatedPath<Uuid>,
+    ValidatedJson(payload): ValidatedJson<RegenerateReframeRequest>,
+) -> Result<Json<ReframeResponse>, AppError> {
+    let source = stress_reframe_service::get_reframe(&pool, user.id, id).await?;
+
+    // Without a choice, ask for the same perspectives in the same order
+    let keys = payload.perspectives.unwrap_or_else(|| {
+        source
+            .perspectives
+            .iter()
+            .map(|perspective| perspective.key.clone())
+            .collect()
+    });
+    let perspectives = stress_reframe_service::resolve_perspectives(&pool, user.id, Some(&keys)).await?;
+
+    let request = stress_reframe_service::build_request(&source.original_thought, &perspectives);
+    let new_reframe = NewReframe {
+        mental_box_id: source.mental_box_id,
+        original_thought: source.original_thought,
+        thread_id: Some(source.thread_id),
+    };
+    // The thought is the same as the earlier version's, so its analysis is reused
+    let side = SideAnalysis::choose(&pool, &reframer, user.id, &new_reframe).await?;
+    let reservation = ai_usage_service::reserve(&pool, &quota, &user, side.calls()).await?;
+    let reframe = generate_reframe(&pool, &reframer, user.id, &new_reframe, &side, &request).await;
+    ai_usage_service::release(&pool, reservation).await;
+
+    Ok(Json(reframe?))
+}
+
+fn delta_event(perspective: &str, text: &str) -> Event {
+    Event::default()
+        .event("delta")
+        .data(json!({ "perspective": perspective, "text": text }).to_string())
+}
+
+fn done_event(reframe: &ReframeResponse) -> Event {
+    Event::default()
+        .event("done")
+        .data(serde_json::to_string(reframe).unwrap_or_default())
+}
+
+fn error_event(error: AppError) -> Event {
+    if error.status().is_server_error() {
+        tracing::error!("Streaming reframe failed: {}", error);
     }
+    Event::default()
+        .event("error")
+        .data(serde_json::to_string(&error.body()).unwrap_or_default())
+}
 
-    // Check if a reframe already exists for this mental_box_id (cache check)
-    if let Some(mental_box_id) = payload.mental_box_id {
-        let existing_reframe = sqlx::query_as::<_, StressReframe>(
-            r#"
-            SELECT id, user_id, mental_box_id, original_thought, stoic_reframe, optimist_reframe, realist_reframe, created_at
-            FROM stress_reframes
-            WHERE mental_box_id = $1 AND user_id = $2
-            ORDER BY created_at DESC
-            LIMIT 1
-            "#,
+type EventSender = mpsc::Sender<Result<Event, Infallible>>;
+
+fn sse_response(rx: mpsc::Receiver<Result<Event, Infallible>>) -> impl IntoResponse {
+    // Stops the nginx reverse proxy from buffering the stream
+    (
+        [("X-Accel-Buffering", "no")],
+        Sse::new(ReceiverStream::new(rx)).keep_alive(KeepAlive::default()),
+    )
+}
+
+async fn stream_reframe(
+    pool: PgPool,
+    reframer: Arc<Reframer>,
+    user_id: Uuid,
+    reframe: NewReframe,
+    side: SideAnalysis,
+    request: ReframeRequest,
+    events: EventSender,
+) {
+    let (delta_tx, mut delta_rx) = mpsc::channel::<ReframeDelta>(STREAM_BUFFER);
+    let forward_events = events.clone();
+    // Stops reading once the client has gone, which makes the provider's next send fail
+    let forward = async move {
+        while let Some(delta) = delta_rx.recv().await {
+            let event = delta_event(&delta.perspective, &delta.text);
+            if forward_events.send(Ok(event)).await.is_err() {
+                break;
+            }
+        }
+    };
+
+    let sink = DeltaSink::new(delta_tx);
+    let generate_reframer = reframer.clone();
+    let generate = async move { generate_reframer.stream(user_id, &request, &sink).await };
+    let analyze = side.run(&reframer, user_id, &reframe.original_thought);
+    let (result, analysis, ()) = tokio::join!(generate, analyze, forward);
+
+    let generation = match result {
+        Ok(generation) => generation,
+        Err(e) => {
+            save_analysis(&pool, user_id, None, &side, analysis).await;
+            let _ = events.send(Ok(error_event(e.into()))).await;
+            return;
+        }
+    };
+
+    if events.is_closed() {
+        // The client left after generation finished: discard the reframe but still
+        // account for the tokens it cost
+        let recorded = ai_usage_service::record_usage(
+            &pool,
+            user_id,
+            ai_usage_service::FEATURE_STRESS_REFRAME,
+            &generation.model,
+            &generation.usage,
+            None,
         )
-        .bind(mental_box_id)
-        .bind(user.id)
-        .fetch_optional(&pool)
-        .await
-        .map_err(|e| {
-            eprintln!("Database error checking existing reframe: {}", e);
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?;
-
-        // If a reframe exists for this mental_box_id, return the cached result
-        if let Some(reframe) = existing_reframe {
-            return Ok(Json(ReframeResponse {
-                id: reframe.id,
-                mental_box_id: reframe.mental_box_id,
-                original_thought: reframe.original_thought,
-                stoic_reframe: reframe.stoic_reframe,
-                optimist_reframe: reframe.optimist_reframe,
-                realist_reframe: reframe.realist_reframe,
-                created_at: reframe.created_at,
-            }));
+        .await;
+        if let Err(e) = recorded {
+            tracing::error!("Failed to record usage of a discarded reframe: {}", e);
         }
+        save_analysis(&pool, user_id, None, &side, analysis).await;
+        return;
     }
 
-    // Generate reframes using AI (only if no cached result)
-    let reframes = openrouter_service::generate_reframes(&payload.original_thought)
-        .await
-        .map_err(|e| {
-            eprintln!("AI reframing error: {}", e);
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?;
-
-    // Store in database
-    let reframe = sqlx::query_as::<_, StressReframe>(
-        r#"
-        INSERT INTO stress_reframes (user_id, mental_box_id, original_thought, stoic_reframe, optimist_reframe, realist_reframe)
-        VALUES ($1, $2, $3, $4, $5, $6)
-        RETURNING id, user_id, mental_box_id, original_thought, stoic_reframe, optimist_reframe, realist_reframe, created_at
-        "#,
-    )
-    .bind(user.id)
-    .bind(payload.mental_box_id)
-    .bind(&payload.original_thought)
-    .bind(&reframes.stoic)
-    .bind(&reframes.optimist)
-    .bind(&reframes.realist)
-    .fetch_one(&pool)
-    .await
-    .map_err(|e| {
-        eprintln!("Database error creating stress reframe: {}", e);
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?;
-
-    Ok(Json(ReframeResponse {
-        id: reframe.id,
-        mental_box_id: reframe.mental_box_id,
-        original_thought: reframe.original_thought,
-        stoic_reframe: reframe.stoic_reframe,
-        optimist_reframe: reframe.optimist_reframe,
-        realist_reframe: reframe.realist_reframe,
-        created_at: reframe.created_at,
-    }))
+    let event = match save_reframe(&pool, user_id, &reframe, &generation).await {
+        Ok(mut saved) => {
+            saved.analysis = save_analysis(&pool, user_id, Some(saved.id), &side, analysis).await;
+            done_event(&saved)
+        }
+        Err(e) => {
+            save_analysis(&pool, user_id, None, &side, analysis).await;
+            error_event(e)
+        }
+    };
+    let _ = events.send(Ok(event)).await;
+}
+
+/// Streams the reframe as it is generated: `delta` events carry text tagged with its
+/// perspective, then a final `done` event carries the saved reframe (or `error`)
+pub async fn create_stream(
+    State(pool): State<PgPool>,
+    State(quota): State<AiQuotaConfig>,
+    State(reframer): State<Arc<Reframer>>,
+    Extension(user): Extension<User>,
+    ValidatedJson(payload): ValidatedJson<CreateReframeRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let (events, rx) = mpsc::channel(STREAM_BUFFER);
+    let perspectives =
+        stress_reframe_service::resolve_perspectives(&pool, user.id, payload.perspectives.as_deref()).await?;
+
+    // Cached reframes are replayed as one delta per perspective
+    if let Some(reframe) =
+        stress_reframe_service::find_cached(&pool, user.id, payload.mental_box_id, &perspectives).await?
+    {
+        tokio::spawn(async move {
+            for perspective in &reframe.perspectives {
+                let event = delta_event(&perspective.key, &perspective.content);
+                let _ = events.send(Ok(event)).await;
+            }
+            let _ = events.send(Ok(done_event(&reframe))).await;
+        });
+        return Ok(sse_response(rx));
+    }
+
+    // Reserved before the stream starts so quota errors are ordinary JSON responses
+    let request = stress_reframe_service::build_request(&payload.original_thought, &perspectives);
+    let new_reframe = NewReframe::from(payload);
+    let side = SideAnalysis::choose(&pool, &reframer, user.id, &new_reframe).await?;
+    let reservation = ai_usage_service::reserve(&pool, &quota, &user, side.calls()).await?;
+    tokio::spawn(async move {
+        stream_reframe(pool.clone(), reframer, user.id, new_reframe, side, request, events).await;
+        ai_usage_service::release(&pool, reservation).await;
+    });
+
+    Ok(sse_response(rx))
 }
 
+// Lists one page of reframes as a JSON array, so older clients keep working, with the
+// cursor of the next page in a header
+async fn reframe_page(
+    pool: &PgPool,
+    user_id: Uuid,
+    mental_box_id: Option<Uuid>,
+    params: &ListReframesQuery,
+) -> Result<impl IntoResponse, AppError> {
+    let limit = params.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if !(1..=MAX_PAGE_SIZE).contains(&limit) {
+        return Err(AppError::invalid_field("limit", "Limit must be between 1 and 100"));
+    }
+
+    let filter = ReframeFilter {
+        mental_box_id,
+        search: params.q.as_deref(),
+    };
+    let page = stress_reframe_service::list_reframes(pool, user_id, &filter, params.cursor.as_deref(), limit).await?;
+
+    let mut headers = HeaderMap::new();
+    if let Some(next_cursor) = page.next_cursor {
+        if let Ok(value) = HeaderValue::from_str(&next_cursor) {
+            headers.insert(NEXT_CURSOR_HEADER, value);
+        }
+    }
+    Ok((headers, Json(page.reframes)))
+}
+
+/// Lists reframes newest first, optionally filtered by Mental Box entry or search text.
+/// Pass the `X-Next-Cursor` response header back as `cursor` to fetch the next page.
 pub async fn list(
     State(pool): State<PgPool>,
     Extension(user): Extension<User>,
-) -> Result<Json<Vec<StressReframe>>, StatusCode> {
-    let reframes = sqlx::query_as::<_, StressReframe>(
+    ValidatedQuery(params): ValidatedQuery<ListReframesQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    reframe_page(&pool, user.id, params.mental_box_id, &params).await
+}
+
+/// Lists the reframes of one Mental Box entry, with the same paging as `list`
+pub async fn list_for_entry(
+    State(pool): State<PgPool>,
+    Extension(user): Extension<User>,
+    ValidatedPath(mental_box_id): ValidatedPath<Uuid>,
+    ValidatedQuery(params): ValidatedQuery<ListReframesQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    let exists: bool = sqlx::query_scalar(
         r#"
-        SELECT id, user_id, mental_box_id, original_thought, stoic_reframe, optimist_reframe, realist_reframe, created_at
-        FROM stress_reframes
-        WHERE user_id = $1
-        ORDER BY created_at DESC
-        LIMIT 50
+        SELECT EXISTS (SELECT 1 FROM mental_box_entries WHERE id = $1 AND user_id = $2)
         "#,
     )
+    .bind(mental_box_id)
     .bind(user.id)
-    .fetch_all(&pool)
-    .await
-    .map_err(|e| {
-        eprintln!("Database error listing stress reframes: {}", e);
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?;
+    .fetch_one(&pool)
+    .await?;
+    if !exists {
+        return Err(AppError::NotFound(
+            "mental_box_entry_not_found",
+            "Mental box entry not found".to_string(),
+        ));
+    }
+
+    reframe_page(&pool, user.id, Some(mental_box_id), &params).await
+}
+
+pub async fn get_by_id(
+    State(pool): State<PgPool>,
+    Extension(user): Extension<User>,
+    ValidatedPath(id): ValidatedPath<Uuid>,
+) -> Result<Json<ReframeResponse>, AppError> {
+    let reframe = stress_reframe_service::get_reframe(&pool, user.id, id).await?;
+    Ok(Json(reframe))
+}
 
+pub async fn delete(
+    State(pool): State<PgPool>,
+    Extension(user): Extension<User>,
+    ValidatedPath(id): ValidatedPath<Uuid>,
+) -> Result<StatusCode, AppError> {
+    stress_reframe_service::delete_reframe(&pool, user.id, id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Lists the perspectives a reframe can be requested from, those the user rated most
+/// helpful first
+pub async fn perspectives(
+    State(pool): State<PgPool>,
+    Extension(user): Extension<User>,
+) -> Result<Json<Vec<Perspective>>, AppError> {
+    let perspectives = stress_reframe_service::list_perspectives(&pool, user.id).await?;
+    Ok(Json(perspectives))
+}
+
+pub async fn history(
+    State(pool): State<PgPool>,
+    Extension(user): Extension<User>,
+    ValidatedPath(id): ValidatedPath<Uuid>,
+) -> Result<Json<Vec<ReframeResponse>>, AppError> {
+    let reframes = stress_reframe_service::list_history(&pool, user.id, id).await?;
     Ok(Json(reframes))
 }
+
+pub async fn list_favorites(
+    State(pool): State<PgPool>,
+    Extension(user): Extension<User>,
+) -> Result<Json<Vec<ReframeResponse>>, AppError> {
+    let reframes = stress_reframe_service::list_favorites(&pool, user.id).await?;
+    Ok(Json(reframes))
+}
+
+pub async fn add_favorite(
+    State(pool): State<PgPool>,
+    Extension(user): Extension<User>,
+    ValidatedPath(id): ValidatedPath<Uuid>,
+) -> Result<Json<ReframeResponse>, AppError> {
+    let reframe = stress_reframe_service::set_favorite(&pool, user.id, id, true).await?;
+    Ok(Json(reframe))
+}
+
+pub async fn remove_favorite(
+    State(pool): State<PgPool>,
+    Extension(user): Extension<User>,
+    ValidatedPath(id): ValidatedPath<Uuid>,
+) -> Result<Json<ReframeResponse>, AppError> {
+    let reframe = stress_reframe_service::set_favorite(&pool, user.id, id, false).await?;
+    Ok(Json(reframe))
+}
+
+pub async fn rate_perspective(
+    State(pool): State<PgPool>,
+    Extension(user): Extension<User>,
+    ValidatedPath((id, key)): ValidatedPath<(Uuid, String)>,
+    ValidatedJson(payload): ValidatedJson<RatePerspectiveRequest>,
+) -> Result<Json<PerspectiveFeedback>, AppError> {
+    let feedback = stress_reframe_service::rate_perspective(&pool, user.id, id, &key, &payload).await?;
+    Ok(Json(feedback))
+}
+
+pub async fn clear_rating(
+    State(pool): State<PgPool>,
+    Extension(user): Extension<User>,
+    ValidatedPath((id, key)): ValidatedPath<(Uuid, String)>,
+) -> Result<StatusCode, AppError> {
+    stress_reframe_service::clear_rating(&pool, user.id, id, &key).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn quota(
+    State(pool): State<PgPool>,
+    State(quota): State<AiQuotaConfig>,
+    Extension(user): Extension<User>,
+) -> Result<Json<AiQuotaResponse>, AppError> {
+    let status = ai_usage_service::quota_status(&pool, &quota, &user).await?;
+    Ok(Json(status))
+}
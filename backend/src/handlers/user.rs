@@ -0,0 +1,60 @@
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    Extension, Json,
+};
+use sqlx::PgPool;
+use std::sync::Arc;
+
+use crate::error::AppError;
+use crate::handlers::auth::refresh_token_from_cookies;
+use crate::models::user::{
+    ChangeEmailRequest, ChangePasswordRequest, UpdateProfileRequest, UpdateTimezoneRequest, User,
+};
+use crate::services::user_service;
+use crate::utils::jwt::JwtKeys;
+use crate::utils::validation::ValidatedJson;
+
+pub async fn update_profile(
+    State(pool): State<PgPool>,
+    Extension(user): Extension<User>,
+    ValidatedJson(payload): ValidatedJson<UpdateProfileRequest>,
+) -> Result<Json<User>, AppError> {
+    let user = user_service::update_profile(&pool, user, payload).await?;
+
+    Ok(Json(user))
+}
+
+pub async fn update_timezone(
+    State(pool): State<PgPool>,
+    Extension(user): Extension<User>,
+    ValidatedJson(payload): ValidatedJson<UpdateTimezoneRequest>,
+) -> Result<Json<User>, AppError> {
+    let user = user_service::update_timezone(&pool, user.id, &payload.timezone).await?;
+
+    Ok(Json(user))
+}
+
+pub async fn change_email(
+    State(pool): State<PgPool>,
+    Extension(user): Extension<User>,
+    ValidatedJson(payload): ValidatedJson<ChangeEmailRequest>,
+) -> Result<Json<User>, AppError> {
+    let user = user_service::change_email(&pool, user, payload).await?;
+
+    Ok(Json(user))
+}
+
+pub async fn change_password(
+    State(pool): State<PgPool>,
+    State(keys): State<Arc<JwtKeys>>,
+    Extension(user): Extension<User>,
+    headers: HeaderMap,
+    ValidatedJson(payload): ValidatedJson<ChangePasswordRequest>,
+) -> Result<StatusCode, AppError> {
+    // The session making the change stays signed in; all others are revoked
+    let current = refresh_token_from_cookies(&headers).map(|token| keys.hash_refresh_token(token));
+    user_service::change_password(&pool, user, payload, current.as_deref()).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
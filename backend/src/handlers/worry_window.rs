@@ -1,52 +1,153 @@
-use axum::{extract::{Path, State}, http::StatusCode, Json};
+use axum::{extract::State, http::StatusCode, Extension, Json};
+use chrono::NaiveDate;
 use sqlx::PgPool;
 use uuid::Uuid;
 
-use crate::models::worry_window::{CreateWorryWindowRequest, UpdateWorryWindowRequest, WorryWindow};
+use crate::error::AppError;
+use crate::models::user::User;
+use crate::models::worry_window::{
+    CreateWorryWindowRequest, OccurrenceDateQuery, OccurrenceOverrideResponse, OccurrenceRangeQuery,
+    ParkWorryRequest, ParkedWorry, ParkedWorryDetail, RecordOutcomeRequest, UpdateOccurrenceRequest,
+    UpdateWorryWindowRequest, WorryWindow, WorryWindowOccurrence, WorryWindowResponse,
+    WorrySessionSummary,
+};
+use crate::services::worry_window_service;
+use crate::utils::validation::{ValidatedJson, ValidatedPath, ValidatedQuery};
 
 pub async fn create(
     State(pool): State<PgPool>,
-    Json(payload): Json<CreateWorryWindowRequest>,
-) -> Result<Json<WorryWindow>, StatusCode> {
-    // Placeholder
-    Err(StatusCode::NOT_IMPLEMENTED)
+    Extension(user): Extension<User>,
+    ValidatedJson(payload): ValidatedJson<CreateWorryWindowRequest>,
+) -> Result<Json<WorryWindow>, AppError> {
+    let window = worry_window_service::create_window(&pool, user.id, payload).await?;
+
+    Ok(Json(window))
 }
 
 pub async fn list(
     State(pool): State<PgPool>,
-) -> Result<Json<Vec<WorryWindow>>, StatusCode> {
-    // Placeholder
-    Ok(Json(vec![]))
+    Extension(user): Extension<User>,
+) -> Result<Json<Vec<WorryWindow>>, AppError> {
+    let windows = worry_window_service::list_windows(&pool, user.id).await?;
+
+    Ok(Json(windows))
 }
 
 pub async fn get_by_id(
     State(pool): State<PgPool>,
-    Path(id): Path<Uuid>,
-) -> Result<Json<WorryWindow>, StatusCode> {
-    // Placeholder
-    Err(StatusCode::NOT_IMPLEMENTED)
+    Extension(user): Extension<User>,
+    ValidatedPath(id): ValidatedPath<Uuid>,
+) -> Result<Json<WorryWindow>, AppError> {
+    let window = worry_window_service::get_window(&pool, user.id, id).await?;
+
+    Ok(Json(window))
 }
 
 pub async fn update(
     State(pool): State<PgPool>,
-    Path(id): Path<Uuid>,
-    Json(payload): Json<UpdateWorryWindowRequest>,
-) -> Result<Json<WorryWindow>, StatusCode> {
-    // Placeholder
-    Err(StatusCode::NOT_IMPLEMENTED)
+    Extension(user): Extension<User>,
+    ValidatedPath(id): ValidatedPath<Uuid>,
+    ValidatedJson(payload): ValidatedJson<UpdateWorryWindowRequest>,
+) -> Result<Json<WorryWindowResponse>, AppError> {
+    let window = worry_window_service::update_window(&pool, user.id, id, payload).await?;
+
+    Ok(Json(window))
 }
 
 pub async fn delete(
     State(pool): State<PgPool>,
-    Path(id): Path<Uuid>,
-) -> Result<StatusCode, StatusCode> {
-    // Placeholder
-    Err(StatusCode::NOT_IMPLEMENTED)
+    Extension(user): Extension<User>,
+    ValidatedPath(id): ValidatedPath<Uuid>,
+) -> Result<StatusCode, AppError> {
+    worry_window_service::delete_window(&pool, user.id, id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn list_occurrences(
+    State(pool): State<PgPool>,
+    Extension(user): Extension<User>,
+    ValidatedQuery(params): ValidatedQuery<OccurrenceRangeQuery>,
+) -> Result<Json<Vec<WorryWindowOccurrence>>, AppError> {
+    let occurrences = worry_window_service::list_occurrences(&pool, user.id, params.from, params.to)
+        .await?;
+
+    Ok(Json(occurrences))
+}
+
+pub async fn update_occurrence(
+    State(pool): State<PgPool>,
+    Extension(user): Extension<User>,
+    ValidatedPath((id, occurrence_date)): ValidatedPath<(Uuid, NaiveDate)>,
+    ValidatedJson(payload): ValidatedJson<UpdateOccurrenceRequest>,
+) -> Result<Json<OccurrenceOverrideResponse>, AppError> {
+    let occurrence_override =
+        worry_window_service::update_occurrence(&pool, user.id, id, occurrence_date, payload)
+            .await?;
+
+    Ok(Json(occurrence_override))
 }
 
 pub async fn get_today(
     State(pool): State<PgPool>,
-) -> Result<Json<Vec<WorryWindow>>, StatusCode> {
-    // Placeholder
-    Ok(Json(vec![]))
+    Extension(user): Extension<User>,
+) -> Result<Json<Vec<WorryWindowOccurrence>>, AppError> {
+    let windows = worry_window_service::list_today(&pool, user.id, user.tz()).await?;
+
+    Ok(Json(windows))
+}
+
+pub async fn park(
+    State(pool): State<PgPool>,
+    Extension(user): Extension<User>,
+    ValidatedPath(id): ValidatedPath<Uuid>,
+    ValidatedJson(payload): ValidatedJson<ParkWorryRequest>,
+) -> Result<Json<ParkedWorry>, AppError> {
+    let parked = worry_window_service::park_worry(&pool, user.id, id, payload).await?;
+
+    Ok(Json(parked))
+}
+
+pub async fn list_parked(
+    State(pool): State<PgPool>,
+    Extension(user): Extension<User>,
+    ValidatedPath(id): ValidatedPath<Uuid>,
+    ValidatedQuery(params): ValidatedQuery<OccurrenceDateQuery>,
+) -> Result<Json<Vec<ParkedWorryDetail>>, AppError> {
+    let parked = worry_window_service::list_parked(&pool, user.id, id, params.date).await?;
+
+    Ok(Json(parked))
+}
+
+pub async fn record_outcome(
+    State(pool): State<PgPool>,
+    Extension(user): Extension<User>,
+    ValidatedPath((id, parked_id)): ValidatedPath<(Uuid, Uuid)>,
+    ValidatedJson(payload): ValidatedJson<RecordOutcomeRequest>,
+) -> Result<Json<ParkedWorry>, AppError> {
+    let parked = worry_window_service::record_outcome(&pool, user.id, id, parked_id, payload)
+        .await?;
+
+    Ok(Json(parked))
+}
+
+pub async fn unpark(
+    State(pool): State<PgPool>,
+    Extension(user): Extension<User>,
+    ValidatedPath((id, parked_id)): ValidatedPath<(Uuid, Uuid)>,
+) -> Result<StatusCode, AppError> {
+    worry_window_service::unpark_worry(&pool, user.id, id, parked_id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn get_summary(
+    State(pool): State<PgPool>,
+    Extension(user): Extension<User>,
+    ValidatedPath(id): ValidatedPath<Uuid>,
+    ValidatedQuery(params): ValidatedQuery<OccurrenceDateQuery>,
+) -> Result<Json<WorrySessionSummary>, AppError> {
+    let summary = worry_window_service::session_summary(&pool, user.id, id, params.date).await?;
+
+    Ok(Json(summary))
 }
@@ -1,19 +1,28 @@
 mod config;
 mod database;
+mod error;
 mod handlers;
 mod middleware;
 mod models;
 mod services;
+mod state;
 mod utils;
 
 use axum::{
     http::HeaderValue,
-    routing::{get, post},
-    Router,
+    routing::{delete, get, post, put},
+    Extension, Router,
 };
 use tower_http::cors::CorsLayer;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
-use std::env;
+use std::{net::SocketAddr, sync::Arc};
+
+use crate::config::Config;
+use crate::services::llm_service;
+use crate::services::rate_limit_service::RateLimiter;
+use crate::services::reframer_service::Reframer;
+use crate::state::AppState;
+use crate::utils::jwt::JwtKeys;
 
 #[tokio::main]
 async fn main() {
@@ -29,8 +38,13 @@ async fn main() {
         .with(tracing_subscriber::fmt::layer())
         .init();
 
+    let config = Config::from_env();
+
+    // Signing keys are loaded once so a bad key path fails at startup, not on first login
+    let jwt = Arc::new(JwtKeys::from_config(&config.jwt).expect("Failed to load JWT keys"));
+
     // Database connection pool
-    let pool = database::connection::create_pool().await;
+    let pool = database::connection::create_pool(&config.database_url).await;
 
     // Run migrations
     tracing::info!("Running database migrations...");
@@ -39,13 +53,28 @@ async fn main() {
         .await
         .expect("Failed to run migrations");
 
-    // Configure CORS - must specify exact origin when using credentials
-    let frontend_url = env::var("FRONTEND_URL")
-        .unwrap_or_else(|_| "http://localhost:3000".to_string());
+    // Old login attempts are pruned in the background
+    services::login_attempt_service::spawn_pruning(pool.clone(), config.login_throttle.clone());
+
+    let state = AppState {
+        rate_limiter: RateLimiter::new(config.rate_limit.clone(), pool.clone()),
+        reframer: Reframer::new(llm_service::build_provider(&config.llm), &config.llm, pool.clone()),
+        pool,
+        jwt,
+        login_throttle: config.login_throttle.clone(),
+        ai_quota: config.ai_quota.clone(),
+        public_url: config.public_url.clone(),
+        password_reset: config.password_reset.clone(),
+    };
 
+    // Idle rate limit buckets are pruned in the background
+    state.rate_limiter.clone().spawn_pruning();
+
+    // Configure CORS - must specify exact origin when using credentials
     let cors = CorsLayer::new()
         .allow_origin(
-            frontend_url
+            config
+                .frontend_url
                 .parse::<HeaderValue>()
                 .expect("Invalid FRONTEND_URL"),
         )
@@ -61,11 +90,25 @@ async fn main() {
             axum::http::header::AUTHORIZATION,
             axum::http::header::ACCEPT,
         ])
+        .expose_headers([axum::http::HeaderName::from_static(
+            handlers::stress_reframe::NEXT_CURSOR_HEADER,
+        )])
         .allow_credentials(true);
 
     // Build protected routes that require authentication
     let protected_routes = Router::new()
         .route("/api/auth/me", get(handlers::auth::me))
+        // User profile routes
+        .route("/api/users/me", put(handlers::user::update_profile))
+        .route("/api/users/me/timezone", put(handlers::user::update_timezone))
+        .route("/api/users/me/email", put(handlers::user::change_email))
+        .route("/api/users/me/password", put(handlers::user::change_password))
+        // Session routes
+        .route(
+            "/api/auth/sessions",
+            get(handlers::session::list).delete(handlers::session::revoke_others),
+        )
+        .route("/api/auth/sessions/:id", delete(handlers::session::revoke))
         // Mental box routes
         .route(
             "/api/mental-box",
@@ -77,6 +120,11 @@ async fn main() {
                 .put(handlers::mental_box::update)
                 .delete(handlers::mental_box::delete),
         )
+        .route("/api/mental-box/:id/reframes", get(handlers::stress_reframe::list_for_entry))
+        .route(
+            "/api/mental-box/:id/analysis",
+            get(handlers::mental_box::get_analysis).post(handlers::mental_box::analyze),
+        )
         // Mood tracker routes
         .route(
             "/api/mood-tracker",
@@ -90,13 +138,74 @@ async fn main() {
         )
         .route("/api/mood-tracker/recent", get(handlers::mood_tracker::get_recent))
         .route("/api/mood-tracker/stats", get(handlers::mood_tracker::get_stats))
+        .route("/api/mood-tracker/daily", get(handlers::mood_tracker::get_daily))
+        // Worry window routes
+        .route(
+            "/api/worry-window",
+            get(handlers::worry_window::list).post(handlers::worry_window::create),
+        )
+        .route(
+            "/api/worry-window/:id",
+            get(handlers::worry_window::get_by_id)
+                .put(handlers::worry_window::update)
+                .delete(handlers::worry_window::delete),
+        )
+        .route("/api/worry-window/today", get(handlers::worry_window::get_today))
+        .route(
+            "/api/worry-window/occurrences",
+            get(handlers::worry_window::list_occurrences),
+        )
+        .route(
+            "/api/worry-window/:id/occurrences/:date",
+            put(handlers::worry_window::update_occurrence),
+        )
+        .route(
+            "/api/worry-window/:id/parked",
+            get(handlers::worry_window::list_parked).post(handlers::worry_window::park),
+        )
+        .route(
+            "/api/worry-window/:id/parked/:parked_id",
+            put(handlers::worry_window::record_outcome).delete(handlers::worry_window::unpark),
+        )
+        .route("/api/worry-window/:id/summary", get(handlers::worry_window::get_summary))
+        // Calendar export routes
+        .route("/api/worry-window/export.ics", get(handlers::calendar::export))
+        .route(
+            "/api/worry-window/calendar-feed",
+            get(handlers::calendar::get_feed)
+                .post(handlers::calendar::rotate_feed)
+                .delete(handlers::calendar::revoke_feed),
+        )
         // Stress reframe routes
         .route(
             "/api/stress-reframe",
             get(handlers::stress_reframe::list).post(handlers::stress_reframe::create),
         )
+        .route("/api/stress-reframe/stream", post(handlers::stress_reframe::create_stream))
+        .route("/api/stress-reframe/quota", get(handlers::stress_reframe::quota))
+        .route("/api/stress-reframe/perspectives", get(handlers::stress_reframe::perspectives))
+        .route("/api/stress-reframe/favorites", get(handlers::stress_reframe::list_favorites))
+        .route(
+            "/api/stress-reframe/:id",
+            get(handlers::stress_reframe::get_by_id).delete(handlers::stress_reframe::delete),
+        )
+        .route("/api/stress-reframe/:id/regenerate", post(handlers::stress_reframe::regenerate))
+        .route("/api/stress-reframe/:id/history", get(handlers::stress_reframe::history))
+        .route(
+            "/api/stress-reframe/:id/favorite",
+            put(handlers::stress_reframe::add_favorite).delete(handlers::stress_reframe::remove_favorite),
+        )
+        .route(
+            "/api/stress-reframe/:id/perspectives/:key/feedback",
+            put(handlers::stress_reframe::rate_perspective).delete(handlers::stress_reframe::clear_rating),
+        )
+        // Layers run bottom-up: authenticate first so requests are limited per user
+        .route_layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            crate::middleware::rate_limit::rate_limit_middleware,
+        ))
         .route_layer(axum::middleware::from_fn_with_state(
-            pool.clone(),
+            state.clone(),
             crate::middleware::auth::auth_middleware,
         ));
 
@@ -106,23 +215,43 @@ async fn main() {
         .route("/api/auth/register", post(handlers::auth::register))
         .route("/api/auth/login", post(handlers::auth::login))
         .route("/api/auth/refresh", post(handlers::auth::refresh))
-        .route("/api/auth/logout", post(handlers::auth::logout));
+        .route("/api/auth/logout", post(handlers::auth::logout))
+        .route(
+            "/api/auth/password-reset/request",
+            post(handlers::auth::request_password_reset),
+        )
+        .route(
+            "/api/auth/password-reset/confirm",
+            post(handlers::auth::confirm_password_reset),
+        )
+        .route("/api/calendar/:token/worry-windows.ics", get(handlers::calendar::feed))
+        .route_layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            crate::middleware::rate_limit::rate_limit_middleware,
+        ));
+
+    // Email delivery for password resets
+    let mailer = services::mailer_service::from_env();
 
     // Combine routes
     let app = public_routes
         .merge(protected_routes)
+        .layer(Extension(mailer))
         .layer(cors)
-        .with_state(pool);
+        .with_state(state);
 
     // Start server
-    let host = std::env::var("SERVER_HOST").unwrap_or_else(|_| "127.0.0.1".to_string());
-    let port = std::env::var("SERVER_PORT").unwrap_or_else(|_| "8000".to_string());
-    let addr = format!("{}:{}", host, port);
+    let addr = format!("{}:{}", config.server_host, config.server_port);
 
     tracing::info!("Server running on http://{}", addr);
 
     let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    .unwrap();
 }
 
 async fn health_check() -> &'static str {
@@ -1,53 +1,51 @@
 use axum::{
     extract::{Request, State},
-    http::{HeaderMap, StatusCode},
+    http::HeaderMap,
     middleware::Next,
     response::Response,
 };
 use sqlx::PgPool;
+use std::sync::Arc;
 use uuid::Uuid;
 
+use crate::error::AppError;
 use crate::models::user::User;
-use crate::utils::jwt;
+use crate::utils::jwt::JwtKeys;
 
 pub async fn auth_middleware(
     State(pool): State<PgPool>,
+    State(keys): State<Arc<JwtKeys>>,
     headers: HeaderMap,
     mut request: Request,
     next: Next,
-) -> Result<Response, StatusCode> {
+) -> Result<Response, AppError> {
     // Extract token from Authorization header
     let token = headers
         .get("Authorization")
         .and_then(|value| value.to_str().ok())
-        .and_then(|value| {
-            if value.starts_with("Bearer ") {
-                Some(&value[7..])
-            } else {
-                None
-            }
-        })
-        .ok_or(StatusCode::UNAUTHORIZED)?;
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .ok_or_else(AppError::unauthorized)?;
 
     // Verify token and extract claims
-    let claims = jwt::verify_token(token).map_err(|_| StatusCode::UNAUTHORIZED)?;
+    let claims = keys
+        .verify_token(token)
+        .map_err(|_| AppError::Unauthorized("invalid_token", "Access token is invalid or expired".to_string()))?;
 
     // Parse user_id from claims
-    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::UNAUTHORIZED)?;
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| AppError::unauthorized())?;
 
     // Fetch user from database
     let user = sqlx::query_as::<_, User>(
         r#"
-        SELECT id, email, password_hash, username, preferred_language, preferred_theme, created_at, updated_at
+        SELECT id, email, password_hash, username, preferred_language, preferred_theme, timezone, created_at, updated_at
         FROM users
         WHERE id = $1
         "#,
     )
     .bind(user_id)
     .fetch_optional(&pool)
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-    .ok_or(StatusCode::UNAUTHORIZED)?;
+    .await?
+    .ok_or_else(AppError::unauthorized)?;
 
     // Insert user into request extensions
     request.extensions_mut().insert(user);
@@ -0,0 +1,66 @@
+use axum::{
+    extract::{ConnectInfo, MatchedPath, Request, State},
+    http::{HeaderMap, HeaderValue},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::{net::SocketAddr, sync::Arc};
+
+use crate::error::AppError;
+use crate::models::user::User;
+use crate::services::rate_limit_service::{RateLimitDecision, RateLimiter};
+use crate::utils::client::client_ip;
+
+fn insert_headers(headers: &mut HeaderMap, decision: &RateLimitDecision) {
+    headers.insert("RateLimit-Limit", HeaderValue::from(decision.limit));
+    headers.insert("RateLimit-Remaining", HeaderValue::from(decision.remaining));
+    headers.insert("RateLimit-Reset", HeaderValue::from(decision.reset_seconds));
+}
+
+/// Charges each request to a token bucket. Requests are keyed by the authenticated
+/// user when `auth_middleware` has already run, and by client IP otherwise.
+pub async fn rate_limit_middleware(
+    State(limiter): State<Arc<RateLimiter>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if !limiter.enabled() {
+        return next.run(request).await;
+    }
+
+    let path = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| request.uri().path().to_string());
+    let policy = limiter.policy_for(request.method(), &path);
+
+    let subject = match request.extensions().get::<User>() {
+        Some(user) => format!("user:{}", user.id),
+        None => format!("ip:{}", client_ip(request.headers(), peer)),
+    };
+
+    let decision = match limiter.check(policy, &subject).await {
+        Ok(decision) => decision,
+        Err(e) => {
+            // Fail open: an unavailable store should not take the whole API down
+            tracing::error!("Rate limit store error: {}", e);
+            return next.run(request).await;
+        }
+    };
+
+    let mut response = if decision.allowed {
+        next.run(request).await
+    } else {
+        AppError::TooManyRequests(
+            "rate_limited",
+            "Too many requests, please slow down".to_string(),
+            decision.retry_after_seconds,
+        )
+        .into_response()
+    };
+
+    insert_headers(response.headers_mut(), &decision);
+    response
+}
@@ -0,0 +1,18 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// Token usage within one quota period
+#[derive(Debug, Serialize)]
+pub struct QuotaPeriod {
+    /// `None` when the period is unlimited
+    pub limit: Option<i64>,
+    pub used: i64,
+    pub remaining: Option<i64>,
+    pub resets_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AiQuotaResponse {
+    pub daily: QuotaPeriod,
+    pub monthly: QuotaPeriod,
+}
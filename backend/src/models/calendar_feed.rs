@@ -0,0 +1,26 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct CalendarFeedToken {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub last_accessed_at: Option<DateTime<Utc>>,
+}
+
+/// Returned once when a feed is created or rotated; the token cannot be retrieved again
+#[derive(Debug, Serialize)]
+pub struct CalendarFeedResponse {
+    pub url: String,
+    pub token: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CalendarExportQuery {
+    /// IANA timezone name overriding the user's own, e.g. `Asia/Bangkok`
+    pub tz: Option<String>,
+}
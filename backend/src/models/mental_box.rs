@@ -2,6 +2,12 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 use uuid::Uuid;
+use validator::Validate;
+
+use crate::utils::validation::not_blank;
+
+pub const MAX_TITLE_LENGTH: u64 = 255;
+pub const MAX_CONTENT_LENGTH: u64 = 10_000;
 
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct MentalBoxEntry {
@@ -13,14 +19,30 @@ pub struct MentalBoxEntry {
     pub updated_at: DateTime<Utc>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Validate)]
 pub struct CreateMentalBoxRequest {
+    #[validate(
+        custom(function = "not_blank"),
+        length(max = "MAX_TITLE_LENGTH", message = "Title must be at most 255 characters")
+    )]
     pub title: String,
+    #[validate(
+        custom(function = "not_blank"),
+        length(max = "MAX_CONTENT_LENGTH", message = "Content must be at most 10000 characters")
+    )]
     pub content: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Validate)]
 pub struct UpdateMentalBoxRequest {
+    #[validate(
+        custom(function = "not_blank"),
+        length(max = "MAX_TITLE_LENGTH", message = "Title must be at most 255 characters")
+    )]
     pub title: Option<String>,
+    #[validate(
+        custom(function = "not_blank"),
+        length(max = "MAX_CONTENT_LENGTH", message = "Content must be at most 10000 characters")
+    )]
     pub content: Option<String>,
 }
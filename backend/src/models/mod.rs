@@ -1,4 +1,9 @@
 pub mod user;
+pub mod ai_usage;
 pub mod mental_box;
 pub mod mood_tracker;
 pub mod stress_reframe;
+pub mod worry_window;
+pub mod calendar_feed;
+pub mod session;
+pub mod thought_analysis;
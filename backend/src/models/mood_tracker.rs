@@ -1,7 +1,10 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 use uuid::Uuid;
+use validator::Validate;
+
+use crate::utils::validation::validate_activities;
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, sqlx::Type)]
 #[sqlx(type_name = "text")]
@@ -42,19 +45,31 @@ pub struct MoodEntry {
     pub updated_at: DateTime<Utc>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Validate)]
 pub struct CreateMoodEntryRequest {
     pub mood: MoodType,
+    #[validate(range(min = 1, max = 10, message = "Stress level must be between 1 and 10"))]
     pub stress_level: i32,
+    #[validate(length(max = 1000, message = "Note must be at most 1000 characters"))]
     pub note: Option<String>,
+    #[validate(
+        length(max = 20, message = "At most 20 activities can be recorded"),
+        custom(function = "validate_activities")
+    )]
     pub activities: Option<Vec<String>>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Validate)]
 pub struct UpdateMoodEntryRequest {
     pub mood: Option<MoodType>,
+    #[validate(range(min = 1, max = 10, message = "Stress level must be between 1 and 10"))]
     pub stress_level: Option<i32>,
+    #[validate(length(max = 1000, message = "Note must be at most 1000 characters"))]
     pub note: Option<String>,
+    #[validate(
+        length(max = 20, message = "At most 20 activities can be recorded"),
+        custom(function = "validate_activities")
+    )]
     pub activities: Option<Vec<String>>,
 }
 
@@ -63,5 +78,14 @@ pub struct MoodStats {
     pub average_stress: f64,
     pub most_common_mood: String,
     pub total_entries: i64,
+    pub entries_today: i64,
     pub entries_this_week: i64,
 }
+
+/// Mood entries aggregated over one calendar day in the user's timezone
+#[derive(Debug, Serialize, FromRow)]
+pub struct DailyMoodSummary {
+    pub date: NaiveDate,
+    pub average_stress: f64,
+    pub entry_count: i64,
+}
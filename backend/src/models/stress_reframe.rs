@@ -2,6 +2,10 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 use uuid::Uuid;
+use validator::Validate;
+
+use crate::models::thought_analysis::ThoughtAnalysis;
+use crate::utils::validation::not_blank;
 
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct StressReframe {
@@ -9,25 +13,165 @@ pub struct StressReframe {
     pub user_id: Uuid,
     pub mental_box_id: Option<Uuid>,
     pub original_thought: String,
-    pub stoic_reframe: String,
-    pub optimist_reframe: String,
-    pub realist_reframe: String,
+    /// Shared by every version regenerated for the same entry
+    pub thread_id: Uuid,
+    pub version: i32,
+    pub favorited_at: Option<DateTime<Utc>>,
     pub created_at: DateTime<Utc>,
 }
 
-#[derive(Debug, Deserialize)]
+/// An entry in the `perspectives` catalog
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Perspective {
+    pub key: String,
+    pub name: String,
+    pub description: String,
+    /// Instruction given to the model for this perspective
+    #[serde(skip)]
+    pub prompt_template: String,
+    pub is_default: bool,
+}
+
+/// A perspective of a reframe joined with the user's rating of it
+#[derive(Debug, Clone, FromRow)]
+pub struct PerspectiveReframeRow {
+    pub stress_reframe_id: Uuid,
+    pub key: String,
+    pub name: String,
+    pub content: String,
+    pub helpful: Option<bool>,
+    pub comment: Option<String>,
+    pub rated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct PerspectiveFeedback {
+    pub helpful: bool,
+    pub comment: Option<String>,
+    pub rated_at: DateTime<Utc>,
+}
+
+/// The text of one perspective of a reframe
+#[derive(Debug, Clone, Serialize)]
+pub struct PerspectiveReframe {
+    pub key: String,
+    pub name: String,
+    pub content: String,
+    /// The user's rating, if they gave one
+    pub feedback: Option<PerspectiveFeedback>,
+}
+
+impl From<PerspectiveReframeRow> for PerspectiveReframe {
+    fn from(row: PerspectiveReframeRow) -> Self {
+        let feedback = match (row.helpful, row.rated_at) {
+            (Some(helpful), Some(rated_at)) => Some(PerspectiveFeedback {
+                helpful,
+                comment: row.comment,
+                rated_at,
+            }),
+            _ => None,
+        };
+
+        Self {
+            key: row.key,
+            name: row.name,
+            content: row.content,
+            feedback,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Validate)]
 pub struct CreateReframeRequest {
     pub mental_box_id: Option<Uuid>,
+    #[validate(
+        custom(function = "not_blank"),
+        length(max = 1000, message = "Thought must be at most 1000 characters")
+    )]
     pub original_thought: String,
+    /// Perspective keys from the catalog, in display order; defaults to stoic, optimist and realist
+    #[validate(length(min = 1, max = 6, message = "Pick between 1 and 6 perspectives"))]
+    pub perspectives: Option<Vec<String>>,
 }
 
+/// Default and maximum page size when listing reframes
+pub const DEFAULT_PAGE_SIZE: i64 = 50;
+pub const MAX_PAGE_SIZE: i64 = 100;
+
+#[derive(Debug, Deserialize)]
+pub struct ListReframesQuery {
+    /// Page size, defaults to 50 and is capped at 100
+    pub limit: Option<i64>,
+    /// `next_cursor` of the previous page
+    pub cursor: Option<String>,
+    pub mental_box_id: Option<Uuid>,
+    /// Words to look for in the original thought or any perspective's text
+    pub q: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct RegenerateReframeRequest {
+    /// Defaults to the perspectives of the reframe being regenerated
+    #[validate(length(min = 1, max = 6, message = "Pick between 1 and 6 perspectives"))]
+    pub perspectives: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct RatePerspectiveRequest {
+    pub helpful: bool,
+    #[validate(length(max = 1000, message = "Comment must be at most 1000 characters"))]
+    pub comment: Option<String>,
+}
+
+/// A reframe with its perspectives. The `*_reframe` fields keep the original
+/// three-column shape for older clients and are null when not generated.
 #[derive(Debug, Serialize)]
 pub struct ReframeResponse {
     pub id: Uuid,
+    pub user_id: Uuid,
     pub mental_box_id: Option<Uuid>,
     pub original_thought: String,
-    pub stoic_reframe: String,
-    pub optimist_reframe: String,
-    pub realist_reframe: String,
+    pub thread_id: Uuid,
+    pub version: i32,
+    pub is_favorite: bool,
+    pub favorited_at: Option<DateTime<Utc>>,
+    pub stoic_reframe: Option<String>,
+    pub optimist_reframe: Option<String>,
+    pub realist_reframe: Option<String>,
+    pub perspectives: Vec<PerspectiveReframe>,
+    /// Cognitive distortions found in `original_thought`, if it was analysed
+    pub analysis: Option<ThoughtAnalysis>,
     pub created_at: DateTime<Utc>,
 }
+
+impl ReframeResponse {
+    pub fn new(
+        reframe: StressReframe,
+        perspectives: Vec<PerspectiveReframe>,
+        analysis: Option<ThoughtAnalysis>,
+    ) -> Self {
+        let legacy = |key: &str| {
+            perspectives
+                .iter()
+                .find(|perspective| perspective.key == key)
+                .map(|perspective| perspective.content.clone())
+        };
+
+        Self {
+            id: reframe.id,
+            user_id: reframe.user_id,
+            mental_box_id: reframe.mental_box_id,
+            original_thought: reframe.original_thought,
+            thread_id: reframe.thread_id,
+            version: reframe.version,
+            is_favorite: reframe.favorited_at.is_some(),
+            favorited_at: reframe.favorited_at,
+            stoic_reframe: legacy("stoic"),
+            optimist_reframe: legacy("optimist"),
+            realist_reframe: legacy("realist"),
+            perspectives,
+            analysis,
+            created_at: reframe.created_at,
+        }
+    }
+}
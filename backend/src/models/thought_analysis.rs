@@ -0,0 +1,157 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::FromRow;
+use uuid::Uuid;
+
+/// Common thinking patterns from cognitive behavioural therapy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CognitiveDistortion {
+    AllOrNothing,
+    Overgeneralization,
+    MentalFilter,
+    DiscountingPositive,
+    MindReading,
+    FortuneTelling,
+    Catastrophizing,
+    EmotionalReasoning,
+    ShouldStatements,
+    Labeling,
+    Personalization,
+    Blaming,
+}
+
+impl CognitiveDistortion {
+    pub const ALL: [CognitiveDistortion; 12] = [
+        CognitiveDistortion::AllOrNothing,
+        CognitiveDistortion::Overgeneralization,
+        CognitiveDistortion::MentalFilter,
+        CognitiveDistortion::DiscountingPositive,
+        CognitiveDistortion::MindReading,
+        CognitiveDistortion::FortuneTelling,
+        CognitiveDistortion::Catastrophizing,
+        CognitiveDistortion::EmotionalReasoning,
+        CognitiveDistortion::ShouldStatements,
+        CognitiveDistortion::Labeling,
+        CognitiveDistortion::Personalization,
+        CognitiveDistortion::Blaming,
+    ];
+
+    /// Stored in `thought_analysis_distortions.distortion` and returned by the API
+    pub fn key(self) -> &'static str {
+        match self {
+            CognitiveDistortion::AllOrNothing => "all_or_nothing",
+            CognitiveDistortion::Overgeneralization => "overgeneralization",
+            CognitiveDistortion::MentalFilter => "mental_filter",
+            CognitiveDistortion::DiscountingPositive => "discounting_positive",
+            CognitiveDistortion::MindReading => "mind_reading",
+            CognitiveDistortion::FortuneTelling => "fortune_telling",
+            CognitiveDistortion::Catastrophizing => "catastrophizing",
+            CognitiveDistortion::EmotionalReasoning => "emotional_reasoning",
+            CognitiveDistortion::ShouldStatements => "should_statements",
+            CognitiveDistortion::Labeling => "labeling",
+            CognitiveDistortion::Personalization => "personalization",
+            CognitiveDistortion::Blaming => "blaming",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CognitiveDistortion::AllOrNothing => "All-or-nothing thinking",
+            CognitiveDistortion::Overgeneralization => "Overgeneralization",
+            CognitiveDistortion::MentalFilter => "Mental filter",
+            CognitiveDistortion::DiscountingPositive => "Discounting the positive",
+            CognitiveDistortion::MindReading => "Mind reading",
+            CognitiveDistortion::FortuneTelling => "Fortune telling",
+            CognitiveDistortion::Catastrophizing => "Catastrophizing",
+            CognitiveDistortion::EmotionalReasoning => "Emotional reasoning",
+            CognitiveDistortion::ShouldStatements => "Should statements",
+            CognitiveDistortion::Labeling => "Labeling",
+            CognitiveDistortion::Personalization => "Personalization",
+            CognitiveDistortion::Blaming => "Blaming",
+        }
+    }
+
+    /// One-line definition given to the model
+    pub fn description(self) -> &'static str {
+        match self {
+            CognitiveDistortion::AllOrNothing => "seeing things in black and white, with no middle ground",
+            CognitiveDistortion::Overgeneralization => "drawing a sweeping conclusion from a single event",
+            CognitiveDistortion::MentalFilter => "dwelling on one negative detail and ignoring the rest",
+            CognitiveDistortion::DiscountingPositive => "insisting that positive experiences do not count",
+            CognitiveDistortion::MindReading => "assuming what others think without evidence",
+            CognitiveDistortion::FortuneTelling => "predicting that things will turn out badly",
+            CognitiveDistortion::Catastrophizing => "expecting the worst possible outcome, or blowing a problem out of proportion",
+            CognitiveDistortion::EmotionalReasoning => "treating a feeling as proof of a fact",
+            CognitiveDistortion::ShouldStatements => "rigid rules about how oneself or others should or must behave",
+            CognitiveDistortion::Labeling => "attaching a global negative label to oneself or others",
+            CognitiveDistortion::Personalization => "taking responsibility for events outside one's control",
+            CognitiveDistortion::Blaming => "holding others entirely responsible for one's own feelings or problems",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|distortion| distortion.key() == key)
+    }
+}
+
+#[derive(Debug, Clone, FromRow)]
+pub struct ThoughtAnalysisRow {
+    pub id: Uuid,
+    pub mental_box_id: Option<Uuid>,
+    pub stress_reframe_id: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, FromRow)]
+pub struct DistortionRow {
+    pub analysis_id: Uuid,
+    pub distortion: String,
+    pub confidence: f32,
+    pub explanation: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DistortionFinding {
+    pub distortion: String,
+    pub name: String,
+    /// Between 0 and 1
+    pub confidence: f32,
+    pub explanation: String,
+}
+
+impl From<DistortionRow> for DistortionFinding {
+    fn from(row: DistortionRow) -> Self {
+        let name = CognitiveDistortion::from_key(&row.distortion)
+            .map(|distortion| distortion.name().to_string())
+            .unwrap_or_else(|| row.distortion.clone());
+        Self {
+            distortion: row.distortion,
+            name,
+            confidence: row.confidence,
+            explanation: row.explanation,
+        }
+    }
+}
+
+/// Cognitive distortions found in a thought, most confident first. An empty list means
+/// none were found.
+#[derive(Debug, Clone, Serialize)]
+pub struct ThoughtAnalysis {
+    pub id: Uuid,
+    pub mental_box_id: Option<Uuid>,
+    pub stress_reframe_id: Option<Uuid>,
+    pub distortions: Vec<DistortionFinding>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl ThoughtAnalysis {
+    pub fn new(row: ThoughtAnalysisRow, distortions: Vec<DistortionRow>) -> Self {
+        Self {
+            id: row.id,
+            mental_box_id: row.mental_box_id,
+            stress_reframe_id: row.stress_reframe_id,
+            distortions: distortions.into_iter().map(DistortionFinding::from).collect(),
+            created_at: row.created_at,
+        }
+    }
+}
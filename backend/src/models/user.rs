@@ -1,7 +1,12 @@
 use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 use uuid::Uuid;
+use validator::Validate;
+
+use crate::utils::password::validate_strength;
+use crate::utils::validation::not_blank;
 
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct User {
@@ -12,20 +17,36 @@ pub struct User {
     pub username: String,
     pub preferred_language: String,
     pub preferred_theme: String,
+    pub timezone: String, // IANA name, e.g. "Asia/Bangkok"
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
-#[derive(Debug, Deserialize)]
+impl User {
+    /// The user's timezone, falling back to UTC if the stored name is not recognised
+    pub fn tz(&self) -> Tz {
+        self.timezone.parse().unwrap_or(Tz::UTC)
+    }
+}
+
+#[derive(Debug, Deserialize, Validate)]
 pub struct CreateUserRequest {
+    #[validate(email(message = "Must be a valid email address"), length(max = 255))]
     pub email: String,
+    #[validate(custom(function = "validate_strength"))]
     pub password: String,
+    #[validate(
+        custom(function = "not_blank"),
+        length(max = 100, message = "Username must be at most 100 characters")
+    )]
     pub username: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Validate)]
 pub struct LoginRequest {
+    #[validate(length(min = 1, message = "Email is required"))]
     pub email: String,
+    #[validate(length(min = 1, message = "Password is required"))]
     pub password: String,
 }
 
@@ -36,7 +57,51 @@ pub struct AuthResponse {
     pub refresh_token: String,
 }
 
-#[derive(Debug, Deserialize)]
-pub struct RefreshTokenRequest {
-    pub refresh_token: String,
+#[derive(Debug, Deserialize, Validate)]
+pub struct UpdateTimezoneRequest {
+    #[validate(length(min = 1, max = 64, message = "Timezone must be an IANA name such as Asia/Bangkok"))]
+    pub timezone: String,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct UpdateProfileRequest {
+    #[validate(
+        custom(function = "not_blank"),
+        length(max = 100, message = "Username must be at most 100 characters")
+    )]
+    pub username: Option<String>,
+    pub preferred_language: Option<String>,
+    pub preferred_theme: Option<String>,
+    #[validate(length(min = 1, max = 64, message = "Timezone must be an IANA name such as Asia/Bangkok"))]
+    pub timezone: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct ChangeEmailRequest {
+    #[validate(email(message = "Must be a valid email address"), length(max = 255))]
+    pub new_email: String,
+    #[validate(length(min = 1, message = "Current password is required"))]
+    pub current_password: String,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct ChangePasswordRequest {
+    #[validate(length(min = 1, message = "Current password is required"))]
+    pub current_password: String,
+    #[validate(custom(function = "validate_strength"))]
+    pub new_password: String,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct PasswordResetRequest {
+    #[validate(email(message = "Must be a valid email address"))]
+    pub email: String,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct PasswordResetConfirmRequest {
+    #[validate(length(min = 1, message = "Reset token is required"))]
+    pub token: String,
+    #[validate(custom(function = "validate_strength"))]
+    pub new_password: String,
 }
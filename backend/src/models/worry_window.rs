@@ -1,7 +1,50 @@
-use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
-use serde::{Deserialize, Serialize};
-use sqlx::FromRow;
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc, Weekday};
+use serde::{Deserialize, Deserializer, Serialize};
+use sqlx::{types::Json, FromRow};
 use uuid::Uuid;
+use validator::Validate;
+
+use crate::utils::validation::not_blank;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RecurrenceFrequency {
+    #[serde(rename = "daily")]
+    Daily,
+    #[serde(rename = "weekdays")]
+    Weekdays,
+    #[serde(rename = "weekly")]
+    Weekly,
+}
+
+/// How a worry window repeats, anchored on its `scheduled_date`.
+/// `interval` means every N days for `daily` and every N weeks for `weekly`; `weekdays`
+/// only accepts an interval of 1.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecurrenceRule {
+    pub frequency: RecurrenceFrequency,
+    #[serde(default = "default_interval")]
+    pub interval: u32,
+    /// Only used by `weekly`; defaults to the weekday of `scheduled_date`
+    #[serde(default)]
+    pub days_of_week: Vec<Weekday>,
+    /// Last date (inclusive) on which the series may occur
+    pub until: Option<NaiveDate>,
+    /// Total number of occurrences in the series
+    pub count: Option<u32>,
+}
+
+fn default_interval() -> u32 {
+    1
+}
+
+// Distinguishes a missing field (None) from an explicit null (Some(None))
+fn double_option<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct WorryWindow {
@@ -13,25 +56,180 @@ pub struct WorryWindow {
     pub start_time: NaiveTime,
     pub end_time: NaiveTime,
     pub is_completed: bool,
+    pub recurrence: Option<Json<RecurrenceRule>>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Validate)]
 pub struct CreateWorryWindowRequest {
+    #[validate(
+        custom(function = "not_blank"),
+        length(max = 255, message = "Title must be at most 255 characters")
+    )]
     pub title: String,
+    #[validate(length(max = 2000, message = "Description must be at most 2000 characters"))]
     pub description: Option<String>,
     pub scheduled_date: NaiveDate,
     pub start_time: NaiveTime,
     pub end_time: NaiveTime,
+    pub recurrence: Option<RecurrenceRule>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Validate)]
 pub struct UpdateWorryWindowRequest {
+    #[validate(
+        custom(function = "not_blank"),
+        length(max = 255, message = "Title must be at most 255 characters")
+    )]
     pub title: Option<String>,
+    #[validate(length(max = 2000, message = "Description must be at most 2000 characters"))]
     pub description: Option<String>,
     pub scheduled_date: Option<NaiveDate>,
     pub start_time: Option<NaiveTime>,
     pub end_time: Option<NaiveTime>,
     pub is_completed: Option<bool>,
+    /// Send `null` to turn a series back into a one-off window
+    #[serde(default, deserialize_with = "double_option")]
+    pub recurrence: Option<Option<RecurrenceRule>>,
+}
+
+/// Per-occurrence state of a recurring worry window
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct WorryWindowOccurrenceOverride {
+    pub id: Uuid,
+    pub worry_window_id: Uuid,
+    pub occurrence_date: NaiveDate,
+    pub is_skipped: bool,
+    pub is_completed: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct UpdateOccurrenceRequest {
+    pub is_skipped: Option<bool>,
+    pub is_completed: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OccurrenceRangeQuery {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+}
+
+/// A single concrete worry window on a given date, expanded from a window or series
+#[derive(Debug, Clone, Serialize)]
+pub struct WorryWindowOccurrence {
+    pub worry_window_id: Uuid,
+    pub occurrence_date: NaiveDate,
+    pub title: String,
+    pub description: Option<String>,
+    pub start_time: NaiveTime,
+    pub end_time: NaiveTime,
+    pub is_recurring: bool,
+    pub is_completed: bool,
+    pub is_skipped: bool,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum ParkedWorryOutcome {
+    #[serde(rename = "resolved")]
+    Resolved,
+    #[serde(rename = "still_worrying")]
+    StillWorrying,
+    #[serde(rename = "reframed")]
+    Reframed,
+    #[serde(rename = "dropped")]
+    Dropped,
+}
+
+impl std::fmt::Display for ParkedWorryOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParkedWorryOutcome::Resolved => write!(f, "resolved"),
+            ParkedWorryOutcome::StillWorrying => write!(f, "still_worrying"),
+            ParkedWorryOutcome::Reframed => write!(f, "reframed"),
+            ParkedWorryOutcome::Dropped => write!(f, "dropped"),
+        }
+    }
+}
+
+/// A Mental Box entry deferred into one occurrence of a worry window
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ParkedWorry {
+    pub id: Uuid,
+    pub worry_window_id: Uuid,
+    pub mental_box_id: Uuid,
+    pub occurrence_date: NaiveDate,
+    /// A `ParkedWorryOutcome` key, unset until the worry has been reviewed
+    pub outcome: Option<String>,
+    pub outcome_note: Option<String>,
+    pub outcome_recorded_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A parked worry together with the Mental Box entry it refers to
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct ParkedWorryDetail {
+    pub id: Uuid,
+    pub worry_window_id: Uuid,
+    pub mental_box_id: Uuid,
+    pub occurrence_date: NaiveDate,
+    pub title: String,
+    pub content: String,
+    pub outcome: Option<String>,
+    pub outcome_note: Option<String>,
+    pub outcome_recorded_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct ParkWorryRequest {
+    pub mental_box_id: Uuid,
+    /// Required for recurring windows; defaults to `scheduled_date` otherwise
+    pub occurrence_date: Option<NaiveDate>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct RecordOutcomeRequest {
+    pub outcome: ParkedWorryOutcome,
+    #[validate(length(max = 1000, message = "Note must be at most 1000 characters"))]
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OccurrenceDateQuery {
+    pub date: Option<NaiveDate>,
+}
+
+/// What happened to every worry parked in one occurrence of a window
+#[derive(Debug, Clone, Serialize)]
+pub struct WorrySessionSummary {
+    pub worry_window_id: Uuid,
+    pub occurrence_date: NaiveDate,
+    pub total: i64,
+    pub resolved: i64,
+    pub still_worrying: i64,
+    pub reframed: i64,
+    pub dropped: i64,
+    pub pending: i64,
+    pub entries: Vec<ParkedWorryDetail>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WorryWindowResponse {
+    #[serde(flatten)]
+    pub window: WorryWindow,
+    /// Present when this update completed the window
+    pub session_summary: Option<WorrySessionSummary>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OccurrenceOverrideResponse {
+    #[serde(flatten)]
+    pub occurrence: WorryWindowOccurrenceOverride,
+    /// Present when this update completed the occurrence
+    pub session_summary: Option<WorrySessionSummary>,
 }
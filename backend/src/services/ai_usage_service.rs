@@ -0,0 +1,238 @@
+use chrono::{DateTime, Utc};
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+use crate::config::AiQuotaConfig;
+use crate::models::ai_usage::{AiQuotaResponse, QuotaPeriod};
+use crate::models::user::User;
+use crate::services::llm_service::TokenUsage;
+
+/// `feature` recorded for calls made by `POST /api/stress-reframe`
+pub const FEATURE_STRESS_REFRAME: &str = "stress_reframe";
+
+#[derive(Debug, thiserror::Error)]
+pub enum AiUsageError {
+    #[error("{period} AI quota exceeded")]
+    QuotaExceeded { period: &'static str, retry_after: i64 },
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+/// How long a reservation holds back tokens if it is never released, e.g. because the
+/// process died mid-call
+const RESERVATION_TTL_MINUTES: i32 = 10;
+
+struct UsageTotals {
+    daily_used: i64,
+    monthly_used: i64,
+    /// Tokens held back for calls that are still running
+    reserved: i64,
+    day_ends_at: DateTime<Utc>,
+    month_ends_at: DateTime<Utc>,
+}
+
+// Sums usage since the start of the user's current local day and month. Failed attempts
+// that still consumed tokens are counted alongside the recorded usage.
+async fn usage_totals(executor: impl PgExecutor<'_>, user: &User) -> Result<UsageTotals, sqlx::Error> {
+    let (daily_used, monthly_used, reserved, day_ends_at, month_ends_at): (
+        i64,
+        i64,
+        i64,
+        DateTime<Utc>,
+        DateTime<Utc>,
+    ) = sqlx::query_as(
+        r#"
+        WITH bounds AS (
+            SELECT
+                date_trunc('day', NOW() AT TIME ZONE $2) AS local_day,
+                date_trunc('month', NOW() AT TIME ZONE $2) AS local_month
+        ),
+        spent AS (
+            SELECT u.total_tokens, u.created_at
+            FROM ai_usage u, bounds b
+            WHERE u.user_id = $1 AND u.created_at >= b.local_month AT TIME ZONE $2
+            UNION ALL
+            SELECT a.total_tokens, a.created_at
+            FROM llm_attempts a, bounds b
+            WHERE a.user_id = $1
+            AND a.outcome <> 'success'
+            AND a.total_tokens IS NOT NULL
+            AND a.created_at >= b.local_month AT TIME ZONE $2
+        )
+        SELECT
+            COALESCE(SUM(s.total_tokens) FILTER (WHERE s.created_at >= b.local_day AT TIME ZONE $2), 0)::BIGINT,
+            COALESCE(SUM(s.total_tokens), 0)::BIGINT,
+            (
+                SELECT COALESCE(SUM(r.tokens), 0)
+                FROM ai_quota_reservations r
+                WHERE r.user_id = $1 AND r.expires_at > NOW()
+            )::BIGINT,
+            (b.local_day + INTERVAL '1 day') AT TIME ZONE $2,
+            (b.local_month + INTERVAL '1 month') AT TIME ZONE $2
+        FROM bounds b
+        LEFT JOIN spent s ON TRUE
+        GROUP BY b.local_day, b.local_month
+        "#,
+    )
+    .bind(user.id)
+    .bind(user.tz().name())
+    .fetch_one(executor)
+    .await?;
+
+    Ok(UsageTotals {
+        daily_used,
+        monthly_used,
+        reserved,
+        day_ends_at,
+        month_ends_at,
+    })
+}
+
+fn period(limit: i64, used: i64, resets_at: DateTime<Utc>) -> QuotaPeriod {
+    let limit = (limit > 0).then_some(limit);
+    QuotaPeriod {
+        limit,
+        used,
+        remaining: limit.map(|limit| (limit - used).max(0)),
+        resets_at,
+    }
+}
+
+pub async fn quota_status(
+    pool: &PgPool,
+    config: &AiQuotaConfig,
+    user: &User,
+) -> Result<AiQuotaResponse, AiUsageError> {
+    let totals = usage_totals(pool, user).await?;
+
+    Ok(AiQuotaResponse {
+        daily: period(config.daily_tokens, totals.daily_used, totals.day_ends_at),
+        monthly: period(config.monthly_tokens, totals.monthly_used, totals.month_ends_at),
+    })
+}
+
+/// Tokens held back for LLM calls in flight. Hand it to `release` once their usage has
+/// been recorded.
+#[must_use]
+pub struct QuotaReservation {
+    id: Option<Uuid>,
+}
+
+/// Refuses new LLM calls once the user's spent and reserved tokens reach their daily or
+/// monthly budget, otherwise reserves `calls` calls' worth of tokens. Reservations are
+/// taken one at a time per user, so parallel requests cannot all pass on the same totals.
+/// A call admitted while budget remains may still cross the limit, so usage can end above
+/// it by however much the running calls spend beyond what they reserved.
+pub async fn reserve(
+    pool: &PgPool,
+    config: &AiQuotaConfig,
+    user: &User,
+    calls: i64,
+) -> Result<QuotaReservation, AiUsageError> {
+    if config.daily_tokens <= 0 && config.monthly_tokens <= 0 {
+        return Ok(QuotaReservation { id: None });
+    }
+
+    let mut tx = pool.begin().await?;
+    sqlx::query(
+        r#"
+        SELECT pg_advisory_xact_lock(hashtext('ai_quota:' || $1::TEXT))
+        "#,
+    )
+    .bind(user.id)
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query(
+        r#"
+        DELETE FROM ai_quota_reservations
+        WHERE user_id = $1 AND expires_at <= NOW()
+        "#,
+    )
+    .bind(user.id)
+    .execute(&mut *tx)
+    .await?;
+
+    let totals = usage_totals(&mut *tx, user).await?;
+    let now = Utc::now();
+
+    // The monthly limit is checked first since it takes longer to reset
+    if config.monthly_tokens > 0 && totals.monthly_used + totals.reserved >= config.monthly_tokens {
+        return Err(AiUsageError::QuotaExceeded {
+            period: "Monthly",
+            retry_after: (totals.month_ends_at - now).num_seconds().max(1),
+        });
+    }
+    if config.daily_tokens > 0 && totals.daily_used + totals.reserved >= config.daily_tokens {
+        return Err(AiUsageError::QuotaExceeded {
+            period: "Daily",
+            retry_after: (totals.day_ends_at - now).num_seconds().max(1),
+        });
+    }
+
+    let id: Uuid = sqlx::query_scalar(
+        r#"
+        INSERT INTO ai_quota_reservations (user_id, tokens, expires_at)
+        VALUES ($1, $2, NOW() + make_interval(mins => $3))
+        RETURNING id
+        "#,
+    )
+    .bind(user.id)
+    .bind((config.reserved_tokens_per_call * calls).min(i32::MAX as i64) as i32)
+    .bind(RESERVATION_TTL_MINUTES)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(QuotaReservation { id: Some(id) })
+}
+
+/// Gives reserved tokens back once the calls' real usage is recorded. A failure only means
+/// the reservation lingers until it expires, so it is logged rather than returned.
+pub async fn release(pool: &PgPool, reservation: QuotaReservation) {
+    let Some(id) = reservation.id else {
+        return;
+    };
+
+    if let Err(e) = sqlx::query(
+        r#"
+        DELETE FROM ai_quota_reservations
+        WHERE id = $1
+        "#,
+    )
+    .bind(id)
+    .execute(pool)
+    .await
+    {
+        tracing::error!("Failed to release AI quota reservation {}: {}", id, e);
+    }
+}
+
+pub async fn record_usage(
+    pool: &PgPool,
+    user_id: Uuid,
+    feature: &str,
+    model: &str,
+    usage: &TokenUsage,
+    stress_reframe_id: Option<Uuid>,
+) -> Result<(), AiUsageError> {
+    sqlx::query(
+        r#"
+        INSERT INTO ai_usage (user_id, feature, model, prompt_tokens, completion_tokens, total_tokens, cost, stress_reframe_id)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        "#,
+    )
+    .bind(user_id)
+    .bind(feature)
+    .bind(model)
+    .bind(usage.prompt_tokens)
+    .bind(usage.completion_tokens)
+    .bind(usage.total_tokens)
+    .bind(usage.cost)
+    .bind(stress_reframe_id)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
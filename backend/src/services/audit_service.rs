@@ -0,0 +1,45 @@
+use serde_json::Value;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::utils::client::ClientInfo;
+
+pub const REFRESH_TOKEN_REUSE: &str = "refresh_token_reuse";
+pub const ACCOUNT_LOCKED: &str = "account_locked";
+pub const IP_LOCKED: &str = "ip_locked";
+
+/// Records a security event. Failures are logged rather than returned so that
+/// auditing never blocks the request that triggered it.
+pub async fn record_event(
+    pool: &PgPool,
+    user_id: Option<Uuid>,
+    event_type: &str,
+    client: &ClientInfo,
+    details: Value,
+) {
+    tracing::warn!(
+        user_id = ?user_id,
+        ip = ?client.ip_address,
+        details = %details,
+        "Security event: {}",
+        event_type
+    );
+
+    let result = sqlx::query(
+        r#"
+        INSERT INTO security_events (user_id, event_type, ip_address, user_agent, details)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+    )
+    .bind(user_id)
+    .bind(event_type)
+    .bind(&client.ip_address)
+    .bind(&client.user_agent)
+    .bind(&details)
+    .execute(pool)
+    .await;
+
+    if let Err(e) = result {
+        tracing::error!("Failed to record security event {}: {}", event_type, e);
+    }
+}
@@ -1,28 +1,56 @@
-use sqlx::PgPool;
+use sqlx::{PgExecutor, PgPool};
 use chrono::{DateTime, Duration, Utc};
+use serde_json::json;
 use uuid::Uuid;
 
+use crate::config::LoginThrottleConfig;
 use crate::models::user::{AuthResponse, CreateUserRequest, LoginRequest, User};
-use crate::utils::{jwt, password};
+use crate::services::{audit_service, login_attempt_service};
+use crate::utils::client::ClientInfo;
+use crate::utils::jwt::JwtKeys;
+use crate::utils::password;
+
+#[derive(Debug, thiserror::Error)]
+pub enum AuthError {
+    #[error("Email is already registered")]
+    EmailTaken,
+    #[error("Invalid email or password")]
+    InvalidCredentials,
+    #[error("Refresh token is invalid, expired or revoked")]
+    InvalidRefreshToken,
+    #[error("Too many failed login attempts, try again in {0} seconds")]
+    TooManyAttempts(i64),
+    #[error("Token signing error: {0}")]
+    Token(#[from] jsonwebtoken::errors::Error),
+    #[error("Password hashing error: {0}")]
+    Hashing(#[from] bcrypt::BcryptError),
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
 
 async fn store_refresh_token(
     pool: &PgPool,
+    keys: &JwtKeys,
     user_id: Uuid,
     refresh_token: &str,
-) -> Result<(), Box<dyn std::error::Error>> {
-    // Calculate expiration (7 days from now)
-    let expires_at = Utc::now() + Duration::days(7);
+    family_id: Uuid,
+    client: &ClientInfo,
+) -> Result<(), AuthError> {
+    let expires_at = Utc::now() + Duration::seconds(keys.refresh_token_expiration());
 
-    // Store refresh token in database
+    // Store refresh token in database along with the device it was issued to
     sqlx::query(
         r#"
-        INSERT INTO refresh_tokens (user_id, token, expires_at)
-        VALUES ($1, $2, $3)
+        INSERT INTO refresh_tokens (user_id, token_hash, expires_at, family_id, user_agent, ip_address, last_used_at)
+        VALUES ($1, $2, $3, $4, $5, $6, NOW())
         "#,
     )
     .bind(user_id)
-    .bind(refresh_token)
+    .bind(keys.hash_refresh_token(refresh_token))
     .bind(expires_at)
+    .bind(family_id)
+    .bind(&client.user_agent)
+    .bind(&client.ip_address)
     .execute(pool)
     .await?;
 
@@ -31,8 +59,10 @@ async fn store_refresh_token(
 
 pub async fn register_user(
     pool: &PgPool,
+    keys: &JwtKeys,
     payload: CreateUserRequest,
-) -> Result<AuthResponse, Box<dyn std::error::Error>> {
+    client: &ClientInfo,
+) -> Result<AuthResponse, AuthError> {
     // Hash password
     let password_hash = password::hash_password(&payload.password)?;
 
@@ -48,22 +78,42 @@ pub async fn register_user(
     .bind(&password_hash)
     .bind(&payload.username)
     .fetch_one(pool)
-    .await?;
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::Database(db) if db.is_unique_violation() => AuthError::EmailTaken,
+        e => AuthError::Database(e),
+    })?;
 
     // Generate JWT tokens
-    let token = jwt::generate_token(user.id)?;
-    let refresh_token = jwt::generate_refresh_token(user.id)?;
+    let token = keys.generate_token(user.id)?;
+    let refresh_token = keys.generate_refresh_token(user.id)?;
 
-    // Store refresh token
-    store_refresh_token(pool, user.id, &refresh_token).await?;
+    // Store refresh token as the start of a new token family
+    store_refresh_token(pool, keys, user.id, &refresh_token, Uuid::new_v4(), client).await?;
 
     Ok(AuthResponse { user, token, refresh_token })
 }
 
 pub async fn login_user(
     pool: &PgPool,
+    keys: &JwtKeys,
+    throttle: &LoginThrottleConfig,
     payload: LoginRequest,
-) -> Result<AuthResponse, Box<dyn std::error::Error>> {
+    client: &ClientInfo,
+) -> Result<AuthResponse, AuthError> {
+    let attempt_email = login_attempt_service::normalize_email(&payload.email);
+
+    // Held from the lockout check until this attempt is recorded; dropping the
+    // transaction on an early return releases the lock
+    let mut tx = pool.begin().await?;
+    login_attempt_service::lock_email(&mut tx, &attempt_email).await?;
+
+    if let Some(retry_after) =
+        login_attempt_service::lockout_remaining(&mut tx, throttle, &attempt_email, client).await?
+    {
+        return Err(AuthError::TooManyAttempts(retry_after));
+    }
+
     // Find user by email
     let user = sqlx::query_as::<_, User>(
         r#"
@@ -72,56 +122,82 @@ pub async fn login_user(
     )
     .bind(&payload.email)
     .fetch_optional(pool)
-    .await?
-    .ok_or("User not found")?;
+    .await?;
 
-    // Verify password
-    let is_valid = password::verify_password(&payload.password, &user.password_hash)?;
-    if !is_valid {
-        return Err("Invalid password".into());
-    }
+    // Verify password. Unknown emails still pay for a bcrypt verification so response
+    // times do not reveal which addresses are registered.
+    let user = match user {
+        Some(user) if password::verify_password(&payload.password, &user.password_hash)? => user,
+        user => {
+            if user.is_none() {
+                password::dummy_verify(&payload.password);
+            }
+            let delay = login_attempt_service::record_failure(
+                &mut tx,
+                pool,
+                throttle,
+                &attempt_email,
+                user.map(|u| u.id),
+                client,
+            )
+            .await?;
+            tx.commit().await?;
+            tokio::time::sleep(delay).await;
+            return Err(AuthError::InvalidCredentials);
+        }
+    };
+
+    login_attempt_service::record_success(&mut tx, &attempt_email, client).await?;
+    tx.commit().await?;
 
     // Generate JWT tokens
-    let token = jwt::generate_token(user.id)?;
-    let refresh_token = jwt::generate_refresh_token(user.id)?;
+    let token = keys.generate_token(user.id)?;
+    let refresh_token = keys.generate_refresh_token(user.id)?;
 
-    // Store refresh token
-    store_refresh_token(pool, user.id, &refresh_token).await?;
+    // Store refresh token as the start of a new token family
+    store_refresh_token(pool, keys, user.id, &refresh_token, Uuid::new_v4(), client).await?;
 
     Ok(AuthResponse { user, token, refresh_token })
 }
 
 pub async fn refresh_access_token(
     pool: &PgPool,
+    keys: &JwtKeys,
     refresh_token: &str,
-) -> Result<AuthResponse, Box<dyn std::error::Error>> {
+    client: &ClientInfo,
+) -> Result<AuthResponse, AuthError> {
     // Verify refresh token
-    let claims = jwt::verify_refresh_token(refresh_token)?;
-    let user_id = Uuid::parse_str(&claims.sub)?;
+    let claims = keys
+        .verify_refresh_token(refresh_token)
+        .map_err(|_| AuthError::InvalidRefreshToken)?;
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| AuthError::InvalidRefreshToken)?;
+    let token_hash = keys.hash_refresh_token(refresh_token);
 
     // Check if refresh token exists and is not revoked
-    let token_record: Option<(bool, DateTime<Utc>)> = sqlx::query_as(
+    let token_record: Option<(Uuid, bool, bool, DateTime<Utc>)> = sqlx::query_as(
         r#"
-        SELECT revoked, expires_at FROM refresh_tokens
-        WHERE token = $1 AND user_id = $2
+        SELECT family_id, revoked, rotated_at IS NOT NULL, expires_at FROM refresh_tokens
+        WHERE token_hash = $1 AND user_id = $2
         "#,
     )
-    .bind(refresh_token)
+    .bind(&token_hash)
     .bind(user_id)
     .fetch_optional(pool)
     .await?;
 
-    match token_record {
-        Some((revoked, expires_at)) => {
-            if revoked {
-                return Err("Refresh token has been revoked".into());
+    let family_id = match token_record {
+        Some((family_id, revoked, rotated, expires_at)) => {
+            if rotated {
+                revoke_compromised_family(pool, user_id, family_id, client).await?;
+                return Err(AuthError::InvalidRefreshToken);
             }
-            if expires_at < Utc::now() {
-                return Err("Refresh token has expired".into());
+            if revoked || expires_at < Utc::now() {
+                return Err(AuthError::InvalidRefreshToken);
             }
+            family_id
         }
-        None => return Err("Invalid refresh token".into()),
-    }
+        None => return Err(AuthError::InvalidRefreshToken),
+    };
 
     // Get user from database
     let user = sqlx::query_as::<_, User>(
@@ -132,26 +208,46 @@ pub async fn refresh_access_token(
     .bind(user_id)
     .fetch_optional(pool)
     .await?
-    .ok_or("User not found")?;
-
-    // Generate new tokens (token rotation)
-    let new_token = jwt::generate_token(user.id)?;
-    let new_refresh_token = jwt::generate_refresh_token(user.id)?;
+    .ok_or(AuthError::InvalidRefreshToken)?;
 
-    // Revoke old refresh token
-    sqlx::query(
+    // Rotate the old refresh token. Only one caller can win this update, so a token
+    // presented twice concurrently is still detected as reuse.
+    let rotated = sqlx::query(
         r#"
         UPDATE refresh_tokens
-        SET revoked = TRUE, revoked_at = NOW()
-        WHERE token = $1
+        SET revoked = TRUE, revoked_at = NOW(), rotated_at = NOW()
+        WHERE token_hash = $1 AND revoked = FALSE
         "#,
     )
-    .bind(refresh_token)
+    .bind(&token_hash)
     .execute(pool)
     .await?;
 
-    // Store new refresh token
-    store_refresh_token(pool, user.id, &new_refresh_token).await?;
+    if rotated.rows_affected() == 0 {
+        // Lost the race: to a concurrent rotation (reuse) or to a plain revocation
+        let rotated_elsewhere: bool = sqlx::query_scalar(
+            r#"
+            SELECT rotated_at IS NOT NULL FROM refresh_tokens
+            WHERE token_hash = $1
+            "#,
+        )
+        .bind(&token_hash)
+        .fetch_optional(pool)
+        .await?
+        .unwrap_or(false);
+
+        if rotated_elsewhere {
+            revoke_compromised_family(pool, user_id, family_id, client).await?;
+        }
+        return Err(AuthError::InvalidRefreshToken);
+    }
+
+    // Generate new tokens (token rotation)
+    let new_token = keys.generate_token(user.id)?;
+    let new_refresh_token = keys.generate_refresh_token(user.id)?;
+
+    // Store new refresh token in the same family
+    store_refresh_token(pool, keys, user.id, &new_refresh_token, family_id, client).await?;
 
     Ok(AuthResponse {
         user,
@@ -160,20 +256,78 @@ pub async fn refresh_access_token(
     })
 }
 
+/// A rotated token being presented again means it was copied: whoever holds the
+/// latest token in its family may be an attacker, so the whole family is revoked.
+async fn revoke_compromised_family(
+    pool: &PgPool,
+    user_id: Uuid,
+    family_id: Uuid,
+    client: &ClientInfo,
+) -> Result<(), sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+        UPDATE refresh_tokens
+        SET revoked = TRUE, revoked_at = NOW()
+        WHERE family_id = $1 AND revoked = FALSE
+        "#,
+    )
+    .bind(family_id)
+    .execute(pool)
+    .await?;
+
+    audit_service::record_event(
+        pool,
+        Some(user_id),
+        audit_service::REFRESH_TOKEN_REUSE,
+        client,
+        json!({
+            "family_id": family_id,
+            "revoked_tokens": result.rows_affected(),
+        }),
+    )
+    .await;
+
+    Ok(())
+}
+
 pub async fn revoke_refresh_token(
     pool: &PgPool,
+    keys: &JwtKeys,
     refresh_token: &str,
-) -> Result<(), Box<dyn std::error::Error>> {
+) -> Result<(), AuthError> {
     sqlx::query(
         r#"
         UPDATE refresh_tokens
         SET revoked = TRUE, revoked_at = NOW()
-        WHERE token = $1
+        WHERE token_hash = $1
         "#,
     )
-    .bind(refresh_token)
+    .bind(keys.hash_refresh_token(refresh_token))
     .execute(pool)
     .await?;
 
     Ok(())
 }
+
+/// Revokes every active refresh token of a user, optionally sparing the caller's own session
+/// (identified by its hash, see `JwtKeys::hash_refresh_token`). Takes any executor so
+/// callers can revoke inside the transaction that changed the credentials.
+pub async fn revoke_all_refresh_tokens(
+    executor: impl PgExecutor<'_>,
+    user_id: Uuid,
+    except_token_hash: Option<&str>,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+        UPDATE refresh_tokens
+        SET revoked = TRUE, revoked_at = NOW()
+        WHERE user_id = $1 AND revoked = FALSE AND ($2::TEXT IS NULL OR token_hash <> $2)
+        "#,
+    )
+    .bind(user_id)
+    .bind(except_token_hash)
+    .execute(executor)
+    .await?;
+
+    Ok(result.rows_affected())
+}
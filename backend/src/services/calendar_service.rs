@@ -0,0 +1,211 @@
+use chrono::{DateTime, Duration, NaiveDate, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::models::calendar_feed::CalendarFeedToken;
+use crate::models::worry_window::WorryWindowOccurrence;
+use crate::services::worry_window_service::{self, WorryWindowError};
+
+/// How far back and ahead of today the exported calendar reaches
+const EXPORT_PAST_DAYS: i64 = 30;
+const EXPORT_FUTURE_DAYS: i64 = 180;
+
+/// RFC 5545 content lines should not exceed 75 octets
+const MAX_LINE_OCTETS: usize = 75;
+
+#[derive(Debug, thiserror::Error)]
+pub enum CalendarError {
+    #[error("Unknown timezone: {0}")]
+    InvalidTimezone(String),
+    #[error("Calendar feed not found")]
+    FeedNotFound,
+    #[error(transparent)]
+    WorryWindow(#[from] WorryWindowError),
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+/// Resolves an explicit `tz` override, falling back to the user's own timezone
+pub fn parse_timezone(tz: Option<&str>, default: Tz) -> Result<Tz, CalendarError> {
+    match tz {
+        Some(name) => name
+            .parse::<Tz>()
+            .map_err(|_| CalendarError::InvalidTimezone(name.to_string())),
+        None => Ok(default),
+    }
+}
+
+fn escape_text(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+
+// Folds a content line at 75 octets without splitting multi-byte characters
+fn push_line(out: &mut String, line: &str) {
+    let mut width = 0;
+    for ch in line.chars() {
+        if width + ch.len_utf8() > MAX_LINE_OCTETS {
+            out.push_str("\r\n ");
+            width = 1;
+        }
+        out.push(ch);
+        width += ch.len_utf8();
+    }
+    out.push_str("\r\n");
+}
+
+fn to_utc(tz: Tz, date: NaiveDate, time: NaiveTime) -> DateTime<Utc> {
+    let local = date.and_time(time);
+    tz.from_local_datetime(&local)
+        .earliest()
+        // Local times skipped by a DST jump are moved past the gap
+        .or_else(|| tz.from_local_datetime(&(local + Duration::hours(1))).earliest())
+        .map(|dt| dt.with_timezone(&Utc))
+        .unwrap_or_else(|| Utc.from_utc_datetime(&local))
+}
+
+fn format_utc(dt: DateTime<Utc>) -> String {
+    dt.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// Renders occurrences as an RFC 5545 VCALENDAR, one VEVENT per occurrence with UTC times
+pub fn render_calendar(occurrences: &[WorryWindowOccurrence], tz: Tz, now: DateTime<Utc>) -> String {
+    let mut out = String::new();
+    push_line(&mut out, "BEGIN:VCALENDAR");
+    push_line(&mut out, "VERSION:2.0");
+    push_line(&mut out, "PRODID:-//SaByeJai//Worry Windows//EN");
+    push_line(&mut out, "CALSCALE:GREGORIAN");
+    push_line(&mut out, "METHOD:PUBLISH");
+    push_line(&mut out, "X-WR-CALNAME:SaByeJai Worry Windows");
+    push_line(&mut out, &format!("X-WR-TIMEZONE:{}", tz.name()));
+
+    let dtstamp = format_utc(now);
+    for occurrence in occurrences.iter().filter(|o| !o.is_skipped) {
+        let start = to_utc(tz, occurrence.occurrence_date, occurrence.start_time);
+        let end = to_utc(tz, occurrence.occurrence_date, occurrence.end_time);
+
+        push_line(&mut out, "BEGIN:VEVENT");
+        push_line(
+            &mut out,
+            &format!(
+                "UID:{}-{}@sabyejai",
+                occurrence.worry_window_id,
+                occurrence.occurrence_date.format("%Y%m%d")
+            ),
+        );
+        push_line(&mut out, &format!("DTSTAMP:{}", dtstamp));
+        push_line(&mut out, &format!("DTSTART:{}", format_utc(start)));
+        push_line(&mut out, &format!("DTEND:{}", format_utc(end)));
+        push_line(&mut out, &format!("SUMMARY:{}", escape_text(&occurrence.title)));
+        if let Some(description) = &occurrence.description {
+            push_line(&mut out, &format!("DESCRIPTION:{}", escape_text(description)));
+        }
+        push_line(&mut out, "STATUS:CONFIRMED");
+        push_line(&mut out, "END:VEVENT");
+    }
+
+    push_line(&mut out, "END:VCALENDAR");
+    out
+}
+
+pub async fn export_ics(pool: &PgPool, user_id: Uuid, tz: Tz) -> Result<String, CalendarError> {
+    let now = Utc::now();
+    let today = now.with_timezone(&tz).date_naive();
+    let occurrences = worry_window_service::list_occurrences(
+        pool,
+        user_id,
+        today - Duration::days(EXPORT_PAST_DAYS),
+        today + Duration::days(EXPORT_FUTURE_DAYS),
+    )
+    .await?;
+
+    Ok(render_calendar(&occurrences, tz, now))
+}
+
+fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Creates the user's feed token, replacing (and so invalidating) any previous one
+pub async fn rotate_feed_token(
+    pool: &PgPool,
+    user_id: Uuid,
+) -> Result<(String, DateTime<Utc>), CalendarError> {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let token = hex::encode(bytes);
+
+    let created_at: DateTime<Utc> = sqlx::query_scalar(
+        r#"
+        INSERT INTO calendar_feed_tokens (user_id, token_hash)
+        VALUES ($1, $2)
+        ON CONFLICT (user_id) DO UPDATE
+        SET token_hash = EXCLUDED.token_hash, created_at = NOW(), last_accessed_at = NULL
+        RETURNING created_at
+        "#,
+    )
+    .bind(user_id)
+    .bind(hash_token(&token))
+    .fetch_one(pool)
+    .await?;
+
+    Ok((token, created_at))
+}
+
+pub async fn get_feed_token(pool: &PgPool, user_id: Uuid) -> Result<CalendarFeedToken, CalendarError> {
+    sqlx::query_as::<_, CalendarFeedToken>(
+        r#"
+        SELECT id, user_id, created_at, last_accessed_at
+        FROM calendar_feed_tokens
+        WHERE user_id = $1
+        "#,
+    )
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?
+    .ok_or(CalendarError::FeedNotFound)
+}
+
+pub async fn revoke_feed_token(pool: &PgPool, user_id: Uuid) -> Result<(), CalendarError> {
+    let result = sqlx::query(
+        r#"
+        DELETE FROM calendar_feed_tokens
+        WHERE user_id = $1
+        "#,
+    )
+    .bind(user_id)
+    .execute(pool)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(CalendarError::FeedNotFound);
+    }
+
+    Ok(())
+}
+
+/// Resolves a subscription token to its owner and their timezone, and records the access
+pub async fn find_feed_owner(pool: &PgPool, token: &str) -> Result<(Uuid, Tz), CalendarError> {
+    let (user_id, timezone): (Uuid, String) = sqlx::query_as(
+        r#"
+        UPDATE calendar_feed_tokens f
+        SET last_accessed_at = NOW()
+        FROM users u
+        WHERE f.token_hash = $1 AND u.id = f.user_id
+        RETURNING f.user_id, u.timezone
+        "#,
+    )
+    .bind(hash_token(token))
+    .fetch_optional(pool)
+    .await?
+    .ok_or(CalendarError::FeedNotFound)?;
+
+    Ok((user_id, timezone.parse().unwrap_or(Tz::UTC)))
+}
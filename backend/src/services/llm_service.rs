@@ -0,0 +1,1259 @@
+use async_trait::async_trait;
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::sync::mpsc;
+
+use crate::config::{LlmConfig, LlmProviderKind, LlmResponseFormat};
+use crate::models::thought_analysis::CognitiveDistortion;
+
+const OPENROUTER_BASE_URL: &str = "https://openrouter.ai/api/v1";
+
+/// Upper bound on each reframe, generous enough for two sentences in Thai
+pub const MAX_REFRAME_LENGTH: usize = 600;
+
+/// Upper bound on the explanation given for each detected distortion
+pub const MAX_EXPLANATION_LENGTH: usize = 300;
+
+#[derive(Debug, thiserror::Error)]
+pub enum LlmError {
+    #[error("No API key configured for the {0} provider")]
+    MissingApiKey(&'static str),
+    #[error("Request to {0} failed: {1}")]
+    Http(&'static str, reqwest::Error),
+    #[error("{0} API error ({1}): {2}")]
+    Api(&'static str, StatusCode, String),
+    #[error("No response from AI")]
+    EmptyResponse,
+    /// Carries the tokens the unusable answer still cost
+    #[error("Failed to parse AI response: {0}. Response was: {1}")]
+    InvalidResponse(String, String, TokenUsage),
+    #[error("{0} did not respond within the time allowed")]
+    Timeout(&'static str),
+    /// Carries the number of seconds until the provider will be tried again
+    #[error("{0} is temporarily unavailable")]
+    CircuitOpen(&'static str, u64),
+    /// Carries the tokens spent before the client left, estimated when the provider had
+    /// not reported them yet
+    #[error("The client stopped listening to the stream")]
+    Cancelled(TokenUsage),
+}
+
+impl LlmError {
+    /// Failures that may succeed on retry. They also count towards opening the circuit breaker.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            LlmError::Http(_, e) => e.is_timeout() || e.is_connect() || e.is_request(),
+            LlmError::Api(_, status, _) => *status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error(),
+            LlmError::Timeout(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Short label stored with each recorded attempt
+    pub fn outcome(&self) -> &'static str {
+        match self {
+            LlmError::MissingApiKey(_) => "missing_api_key",
+            LlmError::Http(_, e) if e.is_timeout() => "timeout",
+            LlmError::Http(_, _) => "network_error",
+            LlmError::Api(_, StatusCode::TOO_MANY_REQUESTS, _) => "rate_limited",
+            LlmError::Api(_, status, _) if status.is_server_error() => "server_error",
+            LlmError::Api(..) => "client_error",
+            LlmError::EmptyResponse | LlmError::InvalidResponse(..) => "invalid_response",
+            LlmError::Timeout(_) => "timeout",
+            LlmError::CircuitOpen(..) => "circuit_open",
+            LlmError::Cancelled(_) => "cancelled",
+        }
+    }
+
+    /// Tokens consumed by a call that failed anyway, when there were any
+    pub fn usage(&self) -> Option<&TokenUsage> {
+        match self {
+            LlmError::InvalidResponse(_, _, usage) | LlmError::Cancelled(usage) => Some(usage),
+            _ => None,
+        }
+    }
+
+    pub fn status_code(&self) -> Option<u16> {
+        match self {
+            LlmError::Api(_, status, _) => Some(status.as_u16()),
+            LlmError::Http(_, e) => e.status().map(|status| status.as_u16()),
+            _ => None,
+        }
+    }
+}
+
+/// One perspective the model is asked for, as described by the `perspectives` catalog
+#[derive(Debug, Clone)]
+pub struct PerspectivePrompt {
+    pub key: String,
+    pub name: String,
+    pub instruction: String,
+}
+
+/// Everything a provider needs to generate one reframe
+#[derive(Debug, Clone)]
+pub struct ReframeRequest {
+    pub original_thought: String,
+    pub perspectives: Vec<PerspectivePrompt>,
+}
+
+/// The generated text for one perspective
+#[derive(Debug, Clone)]
+pub struct PerspectiveText {
+    pub key: String,
+    pub content: String,
+}
+
+/// A piece of one perspective's text, forwarded while the reframe is being generated
+#[derive(Debug, Serialize)]
+pub struct ReframeDelta {
+    pub perspective: String,
+    pub text: String,
+}
+
+/// Where a streaming provider sends its deltas. Remembers whether anything was sent,
+/// since a stream that has already shown text to the user must not be retried.
+pub struct DeltaSink {
+    tx: mpsc::Sender<ReframeDelta>,
+    started: AtomicBool,
+}
+
+impl DeltaSink {
+    pub fn new(tx: mpsc::Sender<ReframeDelta>) -> Self {
+        Self {
+            tx,
+            started: AtomicBool::new(false),
+        }
+    }
+
+    /// Fails once the receiving side has gone away; the caller reports `Cancelled` with
+    /// what the call has cost so far
+    pub async fn send(&self, delta: ReframeDelta) -> Result<(), mpsc::error::SendError<ReframeDelta>> {
+        self.started.store(true, Ordering::Relaxed);
+        self.tx.send(delta).await
+    }
+
+    pub fn started(&self) -> bool {
+        self.started.load(Ordering::Relaxed)
+    }
+}
+
+/// Tokens consumed by one call, as reported by the provider
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct TokenUsage {
+    #[serde(default)]
+    pub prompt_tokens: i32,
+    #[serde(default)]
+    pub completion_tokens: i32,
+    #[serde(default)]
+    pub total_tokens: i32,
+    /// Cost in USD, present when the provider reports it (OpenRouter does)
+    pub cost: Option<f64>,
+}
+
+impl TokenUsage {
+    /// Rough count for text the provider has not reported usage for, at about four
+    /// characters per token
+    pub fn estimate(prompt: &str, completion: &str) -> Self {
+        let tokens = |text: &str| text.chars().count().div_ceil(4).min(i32::MAX as usize) as i32;
+        let prompt_tokens = tokens(prompt);
+        let completion_tokens = tokens(completion);
+        Self {
+            prompt_tokens,
+            completion_tokens,
+            total_tokens: prompt_tokens.saturating_add(completion_tokens),
+            cost: None,
+        }
+    }
+
+    /// Adds another call's usage, e.g. a repair retry, to this one
+    pub fn add(&mut self, other: &TokenUsage) {
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+        self.total_tokens += other.total_tokens;
+        self.cost = match (self.cost, other.cost) {
+            (None, None) => None,
+            (a, b) => Some(a.unwrap_or(0.0) + b.unwrap_or(0.0)),
+        };
+    }
+}
+
+#[derive(Debug)]
+pub struct ReframeGeneration {
+    /// One entry per requested perspective, in the requested order
+    pub reframes: Vec<PerspectiveText>,
+    pub model: String,
+    pub usage: TokenUsage,
+}
+
+#[derive(Debug, Clone)]
+pub struct DetectedDistortion {
+    pub distortion: CognitiveDistortion,
+    pub confidence: f32,
+    pub explanation: String,
+}
+
+#[derive(Debug)]
+pub struct DistortionAnalysis {
+    /// Most confident first; empty when the thought shows no distortion
+    pub distortions: Vec<DetectedDistortion>,
+    pub model: String,
+    pub usage: TokenUsage,
+}
+
+/// The result of a successful call, whatever was asked for
+pub trait LlmOutput {
+    fn usage(&self) -> &TokenUsage;
+}
+
+impl LlmOutput for ReframeGeneration {
+    fn usage(&self) -> &TokenUsage {
+        &self.usage
+    }
+}
+
+impl LlmOutput for DistortionAnalysis {
+    fn usage(&self) -> &TokenUsage {
+        &self.usage
+    }
+}
+
+/// A backend able to turn a stressful thought into reframes from several perspectives,
+/// and to point out the cognitive distortions behind it
+#[async_trait]
+pub trait ReframeProvider: Send + Sync {
+    /// Short name used in logs and error messages
+    fn name(&self) -> &'static str;
+
+    /// Makes one attempt with `model`; retries and fallbacks are left to the caller
+    async fn generate_reframes(&self, model: &str, request: &ReframeRequest) -> Result<ReframeGeneration, LlmError>;
+
+    /// Like `generate_reframes`, but forwards text to `sink` as it is generated. Providers
+    /// that cannot stream send each perspective whole once generation has finished.
+    async fn stream_reframes(
+        &self,
+        model: &str,
+        request: &ReframeRequest,
+        sink: &DeltaSink,
+    ) -> Result<ReframeGeneration, LlmError> {
+        let generation = self.generate_reframes(model, request).await?;
+        for reframe in &generation.reframes {
+            sink.send(ReframeDelta {
+                perspective: reframe.key.clone(),
+                text: reframe.content.clone(),
+            })
+            .await
+            .map_err(|_| LlmError::Cancelled(generation.usage.clone()))?;
+        }
+        Ok(generation)
+    }
+
+    /// Makes one attempt with `model` to classify `thought` into cognitive distortions
+    async fn detect_distortions(&self, model: &str, thought: &str) -> Result<DistortionAnalysis, LlmError>;
+}
+
+fn reframe_prompt(request: &ReframeRequest) -> String {
+    let instructions: Vec<String> = request
+        .perspectives
+        .iter()
+        .enumerate()
+        .map(|(i, perspective)| format!("{}. {}: {}", i + 1, perspective.name, perspective.instruction))
+        .collect();
+    let example: Vec<String> = request
+        .perspectives
+        .iter()
+        .map(|perspective| {
+            format!(
+                "  \"{}\": \"Your {} reframe here\"",
+                perspective.key,
+                perspective.name.to_lowercase()
+            )
+        })
+        .collect();
+
+    format!(
+        r#"You are a cognitive reframing assistant helping people manage stress.
+Given a stressful thought, provide one reframe for each of the {} perspectives below, in JSON format.
+
+IMPORTANT: You MUST respond in the SAME LANGUAGE as the user's input. If the input is in Thai, respond in Thai. If the input is in English, respond in English. Match the language exactly.
+
+Stressful thought: "{}"
+
+Provide these reframes:
+{}
+
+Respond ONLY with valid JSON in this exact format:
+{{
+{}
+}}
+
+Make each reframe concise (1-2 sentences), supportive, and actionable.
+Remember: Your response MUST be in the same language as the input text above."#,
+        request.perspectives.len(),
+        request.original_thought,
+        instructions.join("\n"),
+        example.join(",\n")
+    )
+}
+
+fn quoted_keys(request: &ReframeRequest) -> String {
+    let keys: Vec<String> = request
+        .perspectives
+        .iter()
+        .map(|perspective| format!("\"{}\"", perspective.key))
+        .collect();
+    keys.join(", ")
+}
+
+fn repair_prompt(request: &ReframeRequest, reason: &str) -> String {
+    format!(
+        r#"Your previous response could not be used: {}.
+Respond again with ONLY a JSON object with exactly these keys: {}.
+Each value must be a non-empty string of at most {} characters, in the same language as the stressful thought.
+Do not include any text before or after the JSON."#,
+        reason,
+        quoted_keys(request),
+        MAX_REFRAME_LENGTH
+    )
+}
+
+/// JSON schema sent to providers that support structured output
+fn reframe_schema(request: &ReframeRequest) -> Value {
+    let reframe = json!({ "type": "string", "minLength": 1, "maxLength": MAX_REFRAME_LENGTH });
+    let properties: Map<String, Value> = request
+        .perspectives
+        .iter()
+        .map(|perspective| (perspective.key.clone(), reframe.clone()))
+        .collect();
+    let required: Vec<&str> = request.perspectives.iter().map(|p| p.key.as_str()).collect();
+
+    json!({
+        "type": "object",
+        "properties": properties,
+        "required": required,
+        "additionalProperties": false,
+    })
+}
+
+fn distortion_prompt(thought: &str) -> String {
+    let catalog: Vec<String> = CognitiveDistortion::ALL
+        .iter()
+        .map(|distortion| format!("- {}: {}", distortion.key(), distortion.description()))
+        .collect();
+
+    format!(
+        r#"You are a cognitive behavioural therapy assistant helping people understand why a thought is stressful.
+Identify which of the cognitive distortions below appear in the thought. Only include distortions that are actually present; an empty list is a valid answer.
+
+IMPORTANT: Write each explanation in the SAME LANGUAGE as the thought. If the thought is in Thai, explain in Thai. If it is in English, explain in English.
+
+Thought: "{}"
+
+Cognitive distortions:
+{}
+
+Respond ONLY with valid JSON in this exact format:
+{{
+  "distortions": [
+    {{ "distortion": "catastrophizing", "confidence": 0.8, "explanation": "One short sentence on where it shows up in the thought" }}
+  ]
+}}
+
+"confidence" is a number between 0 and 1."#,
+        thought,
+        catalog.join("\n")
+    )
+}
+
+fn distortion_repair_prompt(reason: &str) -> String {
+    format!(
+        r#"Your previous response could not be used: {}.
+Respond again with ONLY a JSON object with a "distortions" array. Each item must have a "distortion" from the list you were given, a "confidence" between 0 and 1 and a non-empty "explanation" of at most {} characters.
+Do not include any text before or after the JSON."#,
+        reason, MAX_EXPLANATION_LENGTH
+    )
+}
+
+fn distortion_schema() -> Value {
+    let keys: Vec<&str> = CognitiveDistortion::ALL.iter().map(|distortion| distortion.key()).collect();
+
+    json!({
+        "type": "object",
+        "properties": {
+            "distortions": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "distortion": { "type": "string", "enum": keys },
+                        "confidence": { "type": "number", "minimum": 0, "maximum": 1 },
+                        "explanation": { "type": "string", "minLength": 1, "maxLength": MAX_EXPLANATION_LENGTH },
+                    },
+                    "required": ["distortion", "confidence", "explanation"],
+                    "additionalProperties": false,
+                },
+            },
+        },
+        "required": ["distortions"],
+        "additionalProperties": false,
+    })
+}
+
+// Finds the first balanced `{...}` in `text` that parses as JSON, so responses wrapped in
+// code fences or surrounded by prose still yield their payload
+fn extract_json_object(text: &str) -> Option<&str> {
+    let mut search_from = 0;
+    while let Some(offset) = text[search_from..].find('{') {
+        let start = search_from + offset;
+        let mut depth = 0usize;
+        let mut in_string = false;
+        let mut escaped = false;
+
+        for (i, c) in text[start..].char_indices() {
+            if in_string {
+                match c {
+                    _ if escaped => escaped = false,
+                    '\\' => escaped = true,
+                    '"' => in_string = false,
+                    _ => {}
+                }
+                continue;
+            }
+            match c {
+                '"' => in_string = true,
+                '{' => depth += 1,
+                '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        let candidate = &text[start..=start + i];
+                        if serde_json::from_str::<Value>(candidate).is_ok() {
+                            return Some(candidate);
+                        }
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        search_from = start + 1;
+    }
+    None
+}
+
+/// Follows a streamed JSON object character by character and reports the decoded
+/// text of the requested perspectives' string values as it arrives
+#[derive(Default)]
+struct PerspectiveTracker {
+    /// Keys whose values are forwarded; anything else the model adds is ignored
+    keys: Vec<String>,
+    depth: usize,
+    in_string: bool,
+    string_is_key: bool,
+    expecting_value: bool,
+    key: String,
+    value_perspective: Option<String>,
+    /// Characters of an escape sequence seen so far, starting after the backslash
+    escape: Option<String>,
+}
+
+impl PerspectiveTracker {
+    fn new(request: &ReframeRequest) -> Self {
+        Self {
+            keys: request.perspectives.iter().map(|p| p.key.clone()).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn push(&mut self, chunk: &str) -> Vec<ReframeDelta> {
+        let mut deltas: Vec<ReframeDelta> = Vec::new();
+        for c in chunk.chars() {
+            if let Some(decoded) = self.next_char(c) {
+                if self.string_is_key {
+                    self.key.push(decoded);
+                } else if let Some(perspective) = &self.value_perspective {
+                    match deltas.last_mut() {
+                        Some(last) if &last.perspective == perspective => last.text.push(decoded),
+                        _ => deltas.push(ReframeDelta {
+                            perspective: perspective.clone(),
+                            text: decoded.to_string(),
+                        }),
+                    }
+                }
+            }
+        }
+        deltas
+    }
+
+    // Returns the decoded character when `c` completes one inside a string
+    fn next_char(&mut self, c: char) -> Option<char> {
+        if !self.in_string {
+            match c {
+                '{' => self.depth += 1,
+                '}' => self.depth = self.depth.saturating_sub(1),
+                ':' if self.depth == 1 => self.expecting_value = true,
+                ',' => self.expecting_value = false,
+                '"' if self.depth >= 1 => {
+                    self.in_string = true;
+                    self.string_is_key = self.depth == 1 && !self.expecting_value;
+                    if self.string_is_key {
+                        self.key.clear();
+                    } else if self.depth == 1 {
+                        self.value_perspective = self.keys.contains(&self.key).then(|| self.key.clone());
+                    }
+                    self.expecting_value = false;
+                }
+                _ => {}
+            }
+            return None;
+        }
+
+        if let Some(escape) = self.escape.as_mut() {
+            if escape.is_empty() && c != 'u' {
+                self.escape = None;
+                return Some(match c {
+                    'n' => '\n',
+                    't' => '\t',
+                    'r' => '\r',
+                    'b' => '\u{8}',
+                    'f' => '\u{c}',
+                    other => other,
+                });
+            }
+            escape.push(c);
+            // "u" followed by four hex digits
+            if escape.len() < 5 {
+                return None;
+            }
+            // Surrogate halves cannot be shown on their own and are dropped
+            let decoded = u32::from_str_radix(&escape[1..], 16).ok().and_then(char::from_u32);
+            self.escape = None;
+            return decoded;
+        }
+
+        match c {
+            '\\' => {
+                self.escape = Some(String::new());
+                None
+            }
+            '"' => {
+                self.in_string = false;
+                self.value_perspective = None;
+                None
+            }
+            c => Some(c),
+        }
+    }
+}
+
+// Returns a reason suitable for the repair prompt when the content is unusable
+fn parse_reframes(content: &str, request: &ReframeRequest) -> Result<Vec<PerspectiveText>, String> {
+    let json = extract_json_object(content).ok_or("the response did not contain a JSON object")?;
+    let object: Map<String, Value> = serde_json::from_str(json).map_err(|e| format!("invalid JSON ({})", e))?;
+
+    request
+        .perspectives
+        .iter()
+        .map(|perspective| {
+            let key = &perspective.key;
+            let content = object
+                .get(key)
+                .and_then(Value::as_str)
+                .map(str::trim)
+                .ok_or_else(|| format!("\"{}\" is missing or not a string", key))?;
+            if content.is_empty() {
+                return Err(format!("\"{}\" is empty", key));
+            }
+            if content.chars().count() > MAX_REFRAME_LENGTH {
+                return Err(format!("\"{}\" is longer than {} characters", key, MAX_REFRAME_LENGTH));
+            }
+            Ok(PerspectiveText {
+                key: key.clone(),
+                content: content.to_string(),
+            })
+        })
+        .collect()
+}
+
+// Returns a reason suitable for the repair prompt when the content is unusable
+fn parse_distortions(content: &str) -> Result<Vec<DetectedDistortion>, String> {
+    #[derive(Deserialize)]
+    struct Item {
+        distortion: String,
+        confidence: f32,
+        explanation: String,
+    }
+
+    #[derive(Deserialize)]
+    struct Payload {
+        distortions: Vec<Item>,
+    }
+
+    let json = extract_json_object(content).ok_or("the response did not contain a JSON object")?;
+    let payload: Payload = serde_json::from_str(json).map_err(|e| format!("invalid JSON ({})", e))?;
+
+    let mut detected: Vec<DetectedDistortion> = Vec::with_capacity(payload.distortions.len());
+    for item in payload.distortions {
+        let distortion = CognitiveDistortion::from_key(&item.distortion)
+            .ok_or_else(|| format!("\"{}\" is not one of the listed distortions", item.distortion))?;
+        if detected.iter().any(|d| d.distortion == distortion) {
+            return Err(format!("\"{}\" is listed more than once", item.distortion));
+        }
+        if !(0.0..=1.0).contains(&item.confidence) {
+            return Err(format!("the confidence of \"{}\" is not between 0 and 1", item.distortion));
+        }
+        let explanation = item.explanation.trim();
+        if explanation.is_empty() {
+            return Err(format!("the explanation of \"{}\" is empty", item.distortion));
+        }
+        if explanation.chars().count() > MAX_EXPLANATION_LENGTH {
+            return Err(format!(
+                "the explanation of \"{}\" is longer than {} characters",
+                item.distortion, MAX_EXPLANATION_LENGTH
+            ));
+        }
+        detected.push(DetectedDistortion {
+            distortion,
+            confidence: item.confidence,
+            explanation: explanation.to_string(),
+        });
+    }
+
+    detected.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+    Ok(detected)
+}
+
+#[derive(Debug, Serialize)]
+struct ChatRequest<'a> {
+    model: &'a str,
+    messages: &'a [Message],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    usage: Option<UsageOptions>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<Value>,
+}
+
+/// OpenRouter extension asking for the call's cost alongside the token counts
+#[derive(Debug, Serialize)]
+struct UsageOptions {
+    include: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Message {
+    role: String,
+    content: String,
+}
+
+impl Message {
+    fn user(content: String) -> Self {
+        Self { role: "user".to_string(), content }
+    }
+
+    fn assistant(content: String) -> Self {
+        Self { role: "assistant".to_string(), content }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatResponse {
+    choices: Vec<Choice>,
+    /// The model that actually served the request, which may differ from the one asked for
+    model: Option<String>,
+    usage: Option<TokenUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Choice {
+    message: Message,
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamChunk {
+    #[serde(default)]
+    choices: Vec<StreamChoice>,
+    model: Option<String>,
+    usage: Option<TokenUsage>,
+    /// Errors that happen after streaming has started arrive as a chunk
+    error: Option<Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamChoice {
+    delta: StreamDelta,
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamDelta {
+    content: Option<String>,
+}
+
+struct ChatOutput {
+    content: String,
+    model: String,
+    usage: TokenUsage,
+}
+
+/// Any server implementing the OpenAI `/chat/completions` API, e.g. a local Ollama or
+/// llama.cpp server. The API key is optional since local servers rarely need one.
+pub struct OpenAiCompatibleProvider {
+    name: &'static str,
+    client: Client,
+    base_url: String,
+    api_key: Option<String>,
+    response_format: LlmResponseFormat,
+    extra_headers: Vec<(&'static str, &'static str)>,
+    include_usage: bool,
+    /// Limit on a whole response, or for streams on the wait for each chunk
+    request_timeout: Duration,
+}
+
+impl OpenAiCompatibleProvider {
+    pub fn new(
+        client: Client,
+        base_url: &str,
+        api_key: Option<String>,
+        response_format: LlmResponseFormat,
+        request_timeout: Duration,
+    ) -> Self {
+        Self {
+            name: "OpenAI-compatible",
+            client,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key,
+            response_format,
+            extra_headers: Vec::new(),
+            include_usage: false,
+            request_timeout,
+        }
+    }
+
+    fn response_format(&self, name: &str, schema: Value) -> Option<Value> {
+        match self.response_format {
+            LlmResponseFormat::JsonSchema => Some(json!({
+                "type": "json_schema",
+                "json_schema": { "name": name, "strict": true, "schema": schema },
+            })),
+            LlmResponseFormat::JsonObject => Some(json!({ "type": "json_object" })),
+            LlmResponseFormat::Text => None,
+        }
+    }
+
+    async fn send(
+        &self,
+        model: &str,
+        messages: &[Message],
+        response_format: Option<Value>,
+        stream: bool,
+    ) -> Result<reqwest::Response, LlmError> {
+        let request_body = ChatRequest {
+            model,
+            messages,
+            response_format,
+            usage: self.include_usage.then_some(UsageOptions { include: true }),
+            stream,
+            stream_options: stream.then(|| json!({ "include_usage": true })),
+        };
+
+        let mut http_request = self
+            .client
+            .post(format!("{}/chat/completions", self.base_url))
+            .json(&request_body);
+        if let Some(api_key) = &self.api_key {
+            http_request = http_request.bearer_auth(api_key);
+        }
+        for (name, value) in &self.extra_headers {
+            http_request = http_request.header(*name, *value);
+        }
+
+        // A stream may legitimately run longer than one request is allowed to, so it is
+        // only held to the timeout while waiting for the headers and for each chunk
+        let response = if stream {
+            tokio::time::timeout(self.request_timeout, http_request.send())
+                .await
+                .map_err(|_| LlmError::Timeout(self.name))?
+        } else {
+            http_request.timeout(self.request_timeout).send().await
+        }
+        .map_err(|e| LlmError::Http(self.name, e))?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await.map_err(|e| LlmError::Http(self.name, e))?;
+            return Err(LlmError::Api(self.name, status, error_text));
+        }
+
+        Ok(response)
+    }
+
+    async fn chat(
+        &self,
+        model: &str,
+        messages: &[Message],
+        response_format: Option<Value>,
+    ) -> Result<ChatOutput, LlmError> {
+        let response = self.send(model, messages, response_format, false).await?;
+        let api_response: ChatResponse = response.json().await.map_err(|e| LlmError::Http(self.name, e))?;
+        let choice = api_response.choices.into_iter().next().ok_or(LlmError::EmptyResponse)?;
+
+        Ok(ChatOutput {
+            content: choice.message.content,
+            model: api_response.model.unwrap_or_else(|| model.to_string()),
+            usage: api_response.usage.unwrap_or_default(),
+        })
+    }
+
+    /// Sends `prompt` and parses the answer, making one repair attempt that shows the
+    /// model its own answer and what was wrong with it
+    async fn chat_with_repair<T>(
+        &self,
+        model: &str,
+        prompt: String,
+        response_format: Option<Value>,
+        parse: impl Fn(&str) -> Result<T, String>,
+        repair_prompt: impl Fn(&str) -> String,
+    ) -> Result<(T, String, TokenUsage), LlmError> {
+        let mut messages = vec![Message::user(prompt)];
+        let first = self.chat(model, &messages, response_format.clone()).await?;
+
+        let reason = match parse(&first.content) {
+            Ok(parsed) => return Ok((parsed, first.model, first.usage)),
+            Err(reason) => reason,
+        };
+
+        tracing::warn!("{} returned an unusable response ({}); retrying once", self.name, reason);
+        messages.push(Message::assistant(first.content));
+        messages.push(Message::user(repair_prompt(&reason)));
+        let second = self.chat(model, &messages, response_format).await?;
+
+        let mut usage = first.usage;
+        usage.add(&second.usage);
+        let parsed = match parse(&second.content) {
+            Ok(parsed) => parsed,
+            Err(reason) => return Err(LlmError::InvalidResponse(reason, second.content, usage)),
+        };
+
+        Ok((parsed, second.model, usage))
+    }
+}
+
+#[async_trait]
+impl ReframeProvider for OpenAiCompatibleProvider {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    async fn generate_reframes(&self, model: &str, request: &ReframeRequest) -> Result<ReframeGeneration, LlmError> {
+        let (reframes, model, usage) = self
+            .chat_with_repair(
+                model,
+                reframe_prompt(request),
+                self.response_format("reframes", reframe_schema(request)),
+                |content| parse_reframes(content, request),
+                |reason| repair_prompt(request, reason),
+            )
+            .await?;
+
+        Ok(ReframeGeneration { reframes, model, usage })
+    }
+
+    async fn stream_reframes(
+        &self,
+        model: &str,
+        request: &ReframeRequest,
+        sink: &DeltaSink,
+    ) -> Result<ReframeGeneration, LlmError> {
+        let prompt = reframe_prompt(request);
+        let messages = [Message::user(prompt.clone())];
+        let response_format = self.response_format("reframes", reframe_schema(request));
+        let mut response = self.send(model, &messages, response_format, true).await?;
+
+        let mut tracker = PerspectiveTracker::new(request);
+        let mut buffer: Vec<u8> = Vec::new();
+        let mut content = String::new();
+        let mut served_by = None;
+        let mut usage = TokenUsage::default();
+
+        // Server-sent events: one `data: {json}` line per chunk, ending with `data: [DONE]`
+        'read: loop {
+            let Some(bytes) = tokio::time::timeout(self.request_timeout, response.chunk())
+                .await
+                .map_err(|_| LlmError::Timeout(self.name))?
+                .map_err(|e| LlmError::Http(self.name, e))?
+            else {
+                break;
+            };
+            buffer.extend_from_slice(&bytes);
+            while let Some(end) = buffer.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=end).collect();
+                let line = String::from_utf8_lossy(&line);
+                let Some(data) = line.trim().strip_prefix("data:").map(str::trim) else {
+                    continue;
+                };
+                if data == "[DONE]" {
+                    break 'read;
+                }
+
+                let chunk: StreamChunk = serde_json::from_str(data)
+                    .map_err(|e| LlmError::InvalidResponse(e.to_string(), data.to_string(), usage.clone()))?;
+                if let Some(error) = chunk.error {
+                    return Err(LlmError::Api(self.name, StatusCode::BAD_GATEWAY, error.to_string()));
+                }
+                if let Some(model) = chunk.model {
+                    served_by = Some(model);
+                }
+                if let Some(chunk_usage) = chunk.usage {
+                    usage = chunk_usage;
+                }
+                for choice in chunk.choices {
+                    if let Some(text) = choice.delta.content {
+                        content.push_str(&text);
+                        for delta in tracker.push(&text) {
+                            if sink.send(delta).await.is_err() {
+                                // Usage normally arrives with the last chunk, so it is
+                                // usually still unknown here
+                                if usage.total_tokens == 0 {
+                                    usage = TokenUsage::estimate(&prompt, &content);
+                                }
+                                return Err(LlmError::Cancelled(usage));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        if content.is_empty() {
+            return Err(LlmError::EmptyResponse);
+        }
+        // Text already reached the user, so there is no repair retry here
+        let reframes = match parse_reframes(&content, request) {
+            Ok(reframes) => reframes,
+            Err(reason) => return Err(LlmError::InvalidResponse(reason, content, usage)),
+        };
+
+        Ok(ReframeGeneration {
+            reframes,
+            model: served_by.unwrap_or_else(|| model.to_string()),
+            usage,
+        })
+    }
+
+    async fn detect_distortions(&self, model: &str, thought: &str) -> Result<DistortionAnalysis, LlmError> {
+        let (distortions, model, usage) = self
+            .chat_with_repair(
+                model,
+                distortion_prompt(thought),
+                self.response_format("distortions", distortion_schema()),
+                parse_distortions,
+                distortion_repair_prompt,
+            )
+            .await?;
+
+        Ok(DistortionAnalysis {
+            distortions,
+            model,
+            usage,
+        })
+    }
+}
+
+/// OpenRouter, which speaks the OpenAI API plus app attribution headers and cost reporting
+pub struct OpenRouterProvider {
+    inner: OpenAiCompatibleProvider,
+}
+
+impl OpenRouterProvider {
+    pub fn new(
+        client: Client,
+        api_key: Option<String>,
+        response_format: LlmResponseFormat,
+        request_timeout: Duration,
+    ) -> Self {
+        let mut inner =
+            OpenAiCompatibleProvider::new(client, OPENROUTER_BASE_URL, api_key, response_format, request_timeout);
+        inner.name = "OpenRouter";
+        inner.extra_headers = vec![
+            ("HTTP-Referer", "https://sabyejai.com"),
+            ("X-Title", "SaByeJai Stress Management"),
+        ];
+        inner.include_usage = true;
+        Self { inner }
+    }
+}
+
+#[async_trait]
+impl ReframeProvider for OpenRouterProvider {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    async fn generate_reframes(&self, model: &str, request: &ReframeRequest) -> Result<ReframeGeneration, LlmError> {
+        // Checked per call rather than at startup so the rest of the API runs without a key
+        if self.inner.api_key.is_none() {
+            return Err(LlmError::MissingApiKey(self.name()));
+        }
+        self.inner.generate_reframes(model, request).await
+    }
+
+    async fn stream_reframes(
+        &self,
+        model: &str,
+        request: &ReframeRequest,
+        sink: &DeltaSink,
+    ) -> Result<ReframeGeneration, LlmError> {
+        if self.inner.api_key.is_none() {
+            return Err(LlmError::MissingApiKey(self.name()));
+        }
+        self.inner.stream_reframes(model, request, sink).await
+    }
+
+    async fn detect_distortions(&self, model: &str, thought: &str) -> Result<DistortionAnalysis, LlmError> {
+        if self.inner.api_key.is_none() {
+            return Err(LlmError::MissingApiKey(self.name()));
+        }
+        self.inner.detect_distortions(model, thought).await
+    }
+}
+
+/// Returns fixed reframes and analyses without any network access, for tests and local development
+pub struct OfflineProvider;
+
+#[async_trait]
+impl ReframeProvider for OfflineProvider {
+    fn name(&self) -> &'static str {
+        "offline"
+    }
+
+    async fn generate_reframes(&self, _model: &str, request: &ReframeRequest) -> Result<ReframeGeneration, LlmError> {
+        let thought = request.original_thought.trim();
+        Ok(ReframeGeneration {
+            reframes: request
+                .perspectives
+                .iter()
+                .map(|perspective| PerspectiveText {
+                    key: perspective.key.clone(),
+                    content: format!("{} about \"{}\".", perspective.instruction, thought),
+                })
+                .collect(),
+            model: "offline".to_string(),
+            usage: TokenUsage::default(),
+        })
+    }
+
+    async fn detect_distortions(&self, _model: &str, thought: &str) -> Result<DistortionAnalysis, LlmError> {
+        Ok(DistortionAnalysis {
+            distortions: vec![DetectedDistortion {
+                distortion: CognitiveDistortion::Catastrophizing,
+                confidence: 0.5,
+                explanation: format!("Offline analysis of \"{}\".", thought.trim()),
+            }],
+            model: "offline".to_string(),
+            usage: TokenUsage::default(),
+        })
+    }
+}
+
+/// Builds the configured provider around one shared HTTP client
+pub fn build_provider(config: &LlmConfig) -> Arc<dyn ReframeProvider> {
+    let client = Client::builder()
+        .connect_timeout(Duration::from_secs(config.connect_timeout_seconds))
+        .build()
+        .expect("Failed to build HTTP client");
+    let request_timeout = Duration::from_secs(config.request_timeout_seconds);
+
+    match config.provider {
+        LlmProviderKind::OpenRouter => {
+            if config.api_key.is_none() {
+                tracing::warn!("OPENROUTER_API_KEY is not set; AI reframing will fail");
+            }
+            Arc::new(OpenRouterProvider::new(
+                client,
+                config.api_key.clone(),
+                config.response_format,
+                request_timeout,
+            ))
+        }
+        LlmProviderKind::OpenAiCompatible => Arc::new(OpenAiCompatibleProvider::new(
+            client,
+            &config.base_url,
+            config.api_key.clone(),
+            config.response_format,
+            request_timeout,
+        )),
+        LlmProviderKind::Offline => Arc::new(OfflineProvider),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(keys: &[&str]) -> ReframeRequest {
+        ReframeRequest {
+            original_thought: "I will fail the exam".to_string(),
+            perspectives: keys
+                .iter()
+                .map(|key| PerspectivePrompt {
+                    key: key.to_string(),
+                    name: key.to_string(),
+                    instruction: String::new(),
+                })
+                .collect(),
+        }
+    }
+
+    // Feeds the chunks to a tracker and joins the text forwarded for each perspective
+    fn track(keys: &[&str], chunks: &[&str]) -> Vec<(String, String)> {
+        let mut tracker = PerspectiveTracker::new(&request(keys));
+        let mut texts: Vec<(String, String)> = Vec::new();
+        for delta in chunks.iter().flat_map(|chunk| tracker.push(chunk)) {
+            match texts.last_mut() {
+                Some((perspective, text)) if *perspective == delta.perspective => text.push_str(&delta.text),
+                _ => texts.push((delta.perspective, delta.text)),
+            }
+        }
+        texts
+    }
+
+    #[test]
+    fn extract_json_object_strips_code_fences() {
+        let text = "```json\n{\"friend\": \"You studied\"}\n```";
+        assert_eq!(extract_json_object(text), Some("{\"friend\": \"You studied\"}"));
+    }
+
+    #[test]
+    fn extract_json_object_skips_surrounding_prose() {
+        let text = "Here is the result {not json} and then {\"a\": {\"b\": 1}} as requested.";
+        assert_eq!(extract_json_object(text), Some("{\"a\": {\"b\": 1}}"));
+    }
+
+    #[test]
+    fn extract_json_object_ignores_braces_inside_strings() {
+        let text = "{\"friend\": \"a } brace and an escaped \\\" quote {\"}";
+        assert_eq!(extract_json_object(text), Some(text));
+    }
+
+    #[test]
+    fn extract_json_object_rejects_text_without_an_object() {
+        assert_eq!(extract_json_object("no JSON here"), None);
+        assert_eq!(extract_json_object("{\"unterminated\": 1"), None);
+    }
+
+    #[test]
+    fn tracker_forwards_only_requested_perspectives() {
+        let texts = track(
+            &["friend", "future"],
+            &["{\"friend\": \"You ", "studied\", \"extra\": \"ignored\", \"future\": \"It passes\"}"],
+        );
+        assert_eq!(
+            texts,
+            vec![
+                ("friend".to_string(), "You studied".to_string()),
+                ("future".to_string(), "It passes".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn tracker_ignores_nested_values() {
+        let texts = track(&["friend"], &["{\"meta\": {\"friend\": \"no\"}, \"friend\": \"yes\"}"]);
+        assert_eq!(texts, vec![("friend".to_string(), "yes".to_string())]);
+    }
+
+    #[test]
+    fn tracker_decodes_escapes_split_across_chunks() {
+        let texts = track(&["friend"], &["{\"friend\": \"a\\", "nb \\u00", "e9 \\\"q\\\"\"}"]);
+        assert_eq!(texts, vec![("friend".to_string(), "a\nb é \"q\"".to_string())]);
+    }
+
+    #[test]
+    fn estimate_rounds_partial_tokens_up() {
+        let usage = TokenUsage::estimate("12345678", "ภาษาไทย");
+        assert_eq!((usage.prompt_tokens, usage.completion_tokens, usage.total_tokens), (2, 2, 4));
+        assert_eq!(TokenUsage::estimate("", "").total_tokens, 0);
+    }
+
+    #[test]
+    fn parse_reframes_reads_requested_keys_in_order() {
+        let parsed = parse_reframes(
+            "Sure! {\"future\": \" It passes \", \"friend\": \"You studied\"}",
+            &request(&["friend", "future"]),
+        )
+        .unwrap();
+        let parsed: Vec<(&str, &str)> = parsed.iter().map(|p| (p.key.as_str(), p.content.as_str())).collect();
+        assert_eq!(parsed, vec![("friend", "You studied"), ("future", "It passes")]);
+    }
+
+    #[test]
+    fn parse_reframes_rejects_missing_empty_and_long_values() {
+        let request = request(&["friend"]);
+        assert_eq!(
+            parse_reframes("{\"future\": \"x\"}", &request).unwrap_err(),
+            "\"friend\" is missing or not a string"
+        );
+        assert_eq!(parse_reframes("{\"friend\": \"  \"}", &request).unwrap_err(), "\"friend\" is empty");
+
+        let long = json!({ "friend": "a".repeat(MAX_REFRAME_LENGTH + 1) }).to_string();
+        assert_eq!(
+            parse_reframes(&long, &request).unwrap_err(),
+            format!("\"friend\" is longer than {} characters", MAX_REFRAME_LENGTH)
+        );
+        assert!(parse_reframes("no JSON", &request).is_err());
+    }
+
+    #[test]
+    fn parse_distortions_sorts_by_confidence() {
+        let parsed = parse_distortions(
+            r#"{"distortions": [
+                {"distortion": "labeling", "confidence": 0.4, "explanation": "Calls themself a failure"},
+                {"distortion": "fortune_telling", "confidence": 0.9, "explanation": " Predicts failing "}
+            ]}"#,
+        )
+        .unwrap();
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[0].distortion, CognitiveDistortion::FortuneTelling);
+        assert_eq!(parsed[0].explanation, "Predicts failing");
+        assert_eq!(parsed[1].distortion, CognitiveDistortion::Labeling);
+    }
+
+    #[test]
+    fn parse_distortions_accepts_an_empty_list() {
+        assert!(parse_distortions("{\"distortions\": []}").unwrap().is_empty());
+    }
+
+    #[test]
+    fn parse_distortions_rejects_invalid_items() {
+        let item = |distortion: &str, confidence: f32, explanation: &str| {
+            json!({ "distortions": [
+                { "distortion": distortion, "confidence": confidence, "explanation": explanation }
+            ] })
+            .to_string()
+        };
+
+        assert!(parse_distortions(&item("doom", 0.5, "x")).is_err());
+        assert!(parse_distortions(&item("labeling", 1.5, "x")).is_err());
+        assert!(parse_distortions(&item("labeling", 0.5, " ")).is_err());
+        assert!(parse_distortions(&item("labeling", 0.5, &"a".repeat(MAX_EXPLANATION_LENGTH + 1))).is_err());
+        assert!(parse_distortions(
+            r#"{"distortions": [
+                {"distortion": "labeling", "confidence": 0.5, "explanation": "x"},
+                {"distortion": "labeling", "confidence": 0.6, "explanation": "y"}
+            ]}"#
+        )
+        .is_err());
+        assert!(parse_distortions("{\"other\": []}").is_err());
+    }
+}
@@ -0,0 +1,227 @@
+use chrono::{DateTime, Duration, Utc};
+use serde_json::json;
+use sqlx::{PgConnection, PgPool};
+use uuid::Uuid;
+
+use crate::config::LoginThrottleConfig;
+use crate::services::audit_service;
+use crate::utils::client::ClientInfo;
+
+/// Attempts older than this are pruned, unless the throttle window is longer
+const ATTEMPT_RETENTION_SECONDS: i64 = 24 * 60 * 60;
+
+/// How often old attempts are pruned
+const PRUNE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+
+/// Emails are compared case-insensitively so `A@x.com` and `a@x.com` share one counter
+pub fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+// Failures are counted since the last successful login, within the window
+async fn account_failures(
+    conn: &mut PgConnection,
+    config: &LoginThrottleConfig,
+    email: &str,
+) -> Result<(i64, Option<DateTime<Utc>>), sqlx::Error> {
+    sqlx::query_as(
+        r#"
+        SELECT COUNT(*), MAX(created_at)
+        FROM login_attempts
+        WHERE email = $1
+        AND succeeded = FALSE
+        AND created_at > NOW() - make_interval(secs => $2)
+        AND created_at > COALESCE(
+            (SELECT MAX(created_at) FROM login_attempts WHERE email = $1 AND succeeded = TRUE),
+            '-infinity'
+        )
+        "#,
+    )
+    .bind(email)
+    .bind(config.window_seconds as f64)
+    .fetch_one(conn)
+    .await
+}
+
+async fn ip_failures(
+    conn: &mut PgConnection,
+    config: &LoginThrottleConfig,
+    ip_address: &str,
+) -> Result<(i64, Option<DateTime<Utc>>), sqlx::Error> {
+    sqlx::query_as(
+        r#"
+        SELECT COUNT(*), MAX(created_at)
+        FROM login_attempts
+        WHERE ip_address = $1
+        AND succeeded = FALSE
+        AND created_at > NOW() - make_interval(secs => $2)
+        "#,
+    )
+    .bind(ip_address)
+    .bind(config.window_seconds as f64)
+    .fetch_one(conn)
+    .await
+}
+
+fn remaining_lockout(config: &LoginThrottleConfig, last_failure: Option<DateTime<Utc>>) -> Option<i64> {
+    let locked_until = last_failure? + Duration::seconds(config.lockout_seconds);
+    let remaining = (locked_until - Utc::now()).num_seconds();
+    (remaining > 0).then_some(remaining)
+}
+
+/// Serialises login attempts for `email` until the surrounding transaction ends, so that
+/// concurrent guesses are checked and recorded one at a time instead of all passing the
+/// lockout check before any failure is counted
+pub async fn lock_email(conn: &mut PgConnection, email: &str) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        SELECT pg_advisory_xact_lock(hashtext('login_attempts:' || $1))
+        "#,
+    )
+    .bind(email)
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Returns how many seconds the caller must wait if the email or IP is locked out.
+/// Checked before the password so a correct guess during lockout is still refused.
+pub async fn lockout_remaining(
+    conn: &mut PgConnection,
+    config: &LoginThrottleConfig,
+    email: &str,
+    client: &ClientInfo,
+) -> Result<Option<i64>, sqlx::Error> {
+    let (failures, last_failure) = account_failures(conn, config, email).await?;
+    if failures >= config.max_account_failures {
+        if let Some(remaining) = remaining_lockout(config, last_failure) {
+            return Ok(Some(remaining));
+        }
+    }
+
+    if let Some(ip_address) = &client.ip_address {
+        let (failures, last_failure) = ip_failures(conn, config, ip_address).await?;
+        if failures >= config.max_ip_failures {
+            return Ok(remaining_lockout(config, last_failure));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Records a failed attempt, audits any lockout it triggers, and returns how long the
+/// response should be delayed. Delays double with every failure past the free ones.
+pub async fn record_failure(
+    conn: &mut PgConnection,
+    pool: &PgPool,
+    config: &LoginThrottleConfig,
+    email: &str,
+    user_id: Option<Uuid>,
+    client: &ClientInfo,
+) -> Result<std::time::Duration, sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO login_attempts (email, ip_address, succeeded)
+        VALUES ($1, $2, FALSE)
+        "#,
+    )
+    .bind(email)
+    .bind(&client.ip_address)
+    .execute(&mut *conn)
+    .await?;
+
+    let (account_failures, _) = account_failures(conn, config, email).await?;
+    if account_failures == config.max_account_failures {
+        audit_service::record_event(
+            pool,
+            user_id,
+            audit_service::ACCOUNT_LOCKED,
+            client,
+            json!({
+                "email": email,
+                "failed_attempts": account_failures,
+                "lockout_seconds": config.lockout_seconds,
+            }),
+        )
+        .await;
+    }
+
+    if let Some(ip_address) = &client.ip_address {
+        let (ip_failures, _) = ip_failures(conn, config, ip_address).await?;
+        if ip_failures == config.max_ip_failures {
+            audit_service::record_event(
+                pool,
+                None,
+                audit_service::IP_LOCKED,
+                client,
+                json!({
+                    "failed_attempts": ip_failures,
+                    "lockout_seconds": config.lockout_seconds,
+                }),
+            )
+            .await;
+        }
+    }
+
+    let excess = (account_failures - config.free_failures).max(0);
+    if excess == 0 {
+        return Ok(std::time::Duration::ZERO);
+    }
+    let factor = 1u64 << (excess - 1).min(16);
+    let delay_ms = config.base_delay_ms.saturating_mul(factor).min(config.max_delay_ms);
+
+    Ok(std::time::Duration::from_millis(delay_ms))
+}
+
+/// Records a successful login, which resets the account's failure count
+pub async fn record_success(
+    conn: &mut PgConnection,
+    email: &str,
+    client: &ClientInfo,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO login_attempts (email, ip_address, succeeded)
+        VALUES ($1, $2, TRUE)
+        "#,
+    )
+    .bind(email)
+    .bind(&client.ip_address)
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Deletes attempts too old to affect any lockout, including those against unknown
+/// emails and per-IP failures that no successful login ever clears
+pub async fn prune_attempts(pool: &PgPool, config: &LoginThrottleConfig) -> Result<u64, sqlx::Error> {
+    let retention_seconds = ATTEMPT_RETENTION_SECONDS.max(config.window_seconds + config.lockout_seconds);
+
+    let result = sqlx::query(
+        r#"
+        DELETE FROM login_attempts
+        WHERE created_at < NOW() - make_interval(secs => $1)
+        "#,
+    )
+    .bind(retention_seconds as f64)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Prunes old attempts every `PRUNE_INTERVAL` for as long as the server runs
+pub fn spawn_pruning(pool: PgPool, config: LoginThrottleConfig) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(PRUNE_INTERVAL);
+        loop {
+            interval.tick().await;
+            match prune_attempts(&pool, &config).await {
+                Ok(deleted) => tracing::debug!("Pruned {} old login attempts", deleted),
+                Err(e) => tracing::error!("Failed to prune login attempts: {}", e),
+            }
+        }
+    });
+}
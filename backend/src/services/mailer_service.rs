@@ -0,0 +1,128 @@
+use async_trait::async_trait;
+use lettre::{
+    message::Mailbox, transport::smtp::authentication::Credentials, AsyncSmtpTransport,
+    AsyncTransport, Message, Tokio1Executor,
+};
+use std::{env, path::PathBuf, sync::Arc};
+use uuid::Uuid;
+
+#[derive(Debug, thiserror::Error)]
+pub enum MailerError {
+    #[error("Invalid email address: {0}")]
+    Address(#[from] lettre::address::AddressError),
+    #[error("Failed to build email: {0}")]
+    Build(#[from] lettre::error::Error),
+    #[error("SMTP error: {0}")]
+    Smtp(#[from] lettre::transport::smtp::Error),
+    #[error("Failed to write email: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+#[derive(Debug, Clone)]
+pub struct EmailMessage {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// Delivers transactional emails such as password reset links
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, message: EmailMessage) -> Result<(), MailerError>;
+}
+
+pub type SharedMailer = Arc<dyn Mailer>;
+
+pub struct SmtpMailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl SmtpMailer {
+    pub fn new(
+        host: &str,
+        port: u16,
+        credentials: Option<(String, String)>,
+        from: &str,
+    ) -> Result<Self, MailerError> {
+        let mut builder = AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)?.port(port);
+        if let Some((username, password)) = credentials {
+            builder = builder.credentials(Credentials::new(username, password));
+        }
+
+        Ok(Self {
+            transport: builder.build(),
+            from: from.parse()?,
+        })
+    }
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, message: EmailMessage) -> Result<(), MailerError> {
+        let email = Message::builder()
+            .from(self.from.clone())
+            .to(message.to.parse()?)
+            .subject(message.subject)
+            .body(message.body)?;
+
+        self.transport.send(email).await?;
+        Ok(())
+    }
+}
+
+/// Logs every email and, when `outbox_dir` is set, writes it there as an `.eml` file.
+/// Meant for local development and tests where no SMTP server is available.
+pub struct FileMailer {
+    outbox_dir: Option<PathBuf>,
+}
+
+impl FileMailer {
+    pub fn new(outbox_dir: Option<PathBuf>) -> Self {
+        Self { outbox_dir }
+    }
+}
+
+#[async_trait]
+impl Mailer for FileMailer {
+    async fn send(&self, message: EmailMessage) -> Result<(), MailerError> {
+        tracing::info!(to = %message.to, subject = %message.subject, "Email:\n{}", message.body);
+
+        if let Some(dir) = &self.outbox_dir {
+            tokio::fs::create_dir_all(dir).await?;
+            let path = dir.join(format!(
+                "{}-{}.eml",
+                chrono::Utc::now().format("%Y%m%dT%H%M%S"),
+                Uuid::new_v4()
+            ));
+            let contents = format!(
+                "To: {}\r\nSubject: {}\r\n\r\n{}\r\n",
+                message.to, message.subject, message.body
+            );
+            tokio::fs::write(path, contents).await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Builds the mailer selected by `MAILER` (`smtp` or `file`, the default)
+pub fn from_env() -> SharedMailer {
+    match env::var("MAILER").as_deref() {
+        Ok("smtp") => {
+            let host = env::var("SMTP_HOST").expect("SMTP_HOST must be set when MAILER=smtp");
+            let port = env::var("SMTP_PORT")
+                .unwrap_or_else(|_| "587".to_string())
+                .parse()
+                .expect("SMTP_PORT must be a number");
+            let credentials = env::var("SMTP_USERNAME")
+                .ok()
+                .zip(env::var("SMTP_PASSWORD").ok());
+            let from = env::var("MAIL_FROM")
+                .unwrap_or_else(|_| "SaByeJai <no-reply@sabyejai.com>".to_string());
+
+            Arc::new(SmtpMailer::new(&host, port, credentials, &from).expect("Invalid SMTP configuration"))
+        }
+        _ => Arc::new(FileMailer::new(env::var("MAIL_OUTBOX_DIR").ok().map(PathBuf::from))),
+    }
+}
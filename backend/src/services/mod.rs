@@ -1,4 +1,16 @@
+pub mod ai_usage_service;
+pub mod audit_service;
 pub mod auth_service;
+pub mod calendar_service;
+pub mod login_attempt_service;
+pub mod llm_service;
+pub mod mailer_service;
 pub mod mental_box_service;
 pub mod worry_window_service;
-pub mod openrouter_service;
+pub mod password_reset_service;
+pub mod rate_limit_service;
+pub mod reframer_service;
+pub mod session_service;
+pub mod stress_reframe_service;
+pub mod thought_analysis_service;
+pub mod user_service;
@@ -0,0 +1,176 @@
+use chrono::{Duration, Utc};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::config::PasswordResetConfig;
+use crate::models::user::{PasswordResetConfirmRequest, User};
+use crate::services::auth_service;
+use crate::services::mailer_service::{EmailMessage, SharedMailer};
+use crate::utils::password;
+
+#[derive(Debug, thiserror::Error)]
+pub enum PasswordResetError {
+    #[error("Reset token is invalid, expired or already used")]
+    InvalidToken,
+    #[error("Password hashing error: {0}")]
+    Hashing(#[from] bcrypt::BcryptError),
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+fn reset_link(config: &PasswordResetConfig, token: &str) -> String {
+    format!("{}/reset-password?token={}", config.frontend_url, token)
+}
+
+fn reset_email(user: &User, link: &str, ttl_minutes: i64) -> EmailMessage {
+    let (subject, body) = match user.preferred_language.as_str() {
+        "th" => (
+            "รีเซ็ตรหัสผ่าน SaByeJai".to_string(),
+            format!(
+                "สวัสดี {},\n\nคลิกลิงก์ด้านล่างเพื่อตั้งรหัสผ่านใหม่ ลิงก์นี้ใช้ได้ครั้งเดียวภายใน {} นาที\n\n{}\n\nหากคุณไม่ได้ขอรีเซ็ตรหัสผ่าน สามารถละเว้นอีเมลนี้ได้",
+                user.username, ttl_minutes, link
+            ),
+        ),
+        _ => (
+            "Reset your SaByeJai password".to_string(),
+            format!(
+                "Hi {},\n\nUse the link below to choose a new password. It can be used once within {} minutes.\n\n{}\n\nIf you did not request a password reset, you can ignore this email.",
+                user.username, ttl_minutes, link
+            ),
+        ),
+    };
+
+    EmailMessage {
+        to: user.email.clone(),
+        subject,
+        body,
+    }
+}
+
+/// Issues a reset token and emails it. Succeeds silently for unknown emails so that
+/// callers cannot probe which addresses are registered.
+pub async fn request_reset(
+    pool: &PgPool,
+    config: &PasswordResetConfig,
+    mailer: SharedMailer,
+    email: &str,
+) -> Result<(), PasswordResetError> {
+    let user = sqlx::query_as::<_, User>(
+        r#"
+        SELECT * FROM users WHERE email = $1
+        "#,
+    )
+    .bind(email.trim())
+    .fetch_optional(pool)
+    .await?;
+
+    let Some(user) = user else {
+        return Ok(());
+    };
+
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let token = hex::encode(bytes);
+
+    // Only the most recently requested link stays valid
+    sqlx::query(
+        r#"
+        DELETE FROM password_reset_tokens
+        WHERE user_id = $1 AND used_at IS NULL
+        "#,
+    )
+    .bind(user.id)
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO password_reset_tokens (user_id, token_hash, expires_at)
+        VALUES ($1, $2, $3)
+        "#,
+    )
+    .bind(user.id)
+    .bind(hash_token(&token))
+    .bind(Utc::now() + Duration::seconds(config.expiration_seconds))
+    .execute(pool)
+    .await?;
+
+    let message = reset_email(&user, &reset_link(config, &token), config.expiration_seconds / 60);
+
+    // Deliver in the background so response time does not reveal whether the email exists
+    tokio::spawn(async move {
+        if let Err(e) = mailer.send(message).await {
+            tracing::error!("Failed to send password reset email: {}", e);
+        }
+    });
+
+    Ok(())
+}
+
+/// Consumes a reset token, sets the new password and signs the user out everywhere
+pub async fn confirm_reset(
+    pool: &PgPool,
+    payload: PasswordResetConfirmRequest,
+) -> Result<(), PasswordResetError> {
+    let token_hash = hash_token(&payload.token);
+
+    // Reject bad tokens before paying for bcrypt
+    let is_valid: bool = sqlx::query_scalar(
+        r#"
+        SELECT EXISTS (
+            SELECT 1 FROM password_reset_tokens
+            WHERE token_hash = $1 AND used_at IS NULL AND expires_at > NOW()
+        )
+        "#,
+    )
+    .bind(&token_hash)
+    .fetch_one(pool)
+    .await?;
+
+    if !is_valid {
+        return Err(PasswordResetError::InvalidToken);
+    }
+
+    let password_hash = password::hash_password(&payload.new_password)?;
+
+    let mut tx = pool.begin().await?;
+
+    // Marking the token used in the same statement that checks it keeps it single-use
+    let user_id: Uuid = sqlx::query_scalar(
+        r#"
+        UPDATE password_reset_tokens
+        SET used_at = NOW()
+        WHERE token_hash = $1 AND used_at IS NULL AND expires_at > NOW()
+        RETURNING user_id
+        "#,
+    )
+    .bind(&token_hash)
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(PasswordResetError::InvalidToken)?;
+
+    sqlx::query(
+        r#"
+        UPDATE users
+        SET password_hash = $1
+        WHERE id = $2
+        "#,
+    )
+    .bind(&password_hash)
+    .bind(user_id)
+    .execute(&mut *tx)
+    .await?;
+
+    // Sessions are signed out together with the password change, or not at all
+    auth_service::revoke_all_refresh_tokens(&mut *tx, user_id, None).await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
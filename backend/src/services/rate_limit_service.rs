@@ -0,0 +1,290 @@
+use async_trait::async_trait;
+use axum::http::Method;
+use sqlx::PgPool;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::config::{RateLimitConfig, RateLimitPolicy, RateLimitStoreKind};
+
+/// The in-memory store drops idle, refilled buckets once it holds this many keys
+const MEMORY_STORE_PRUNE_THRESHOLD: usize = 10_000;
+
+/// How often stores that do not prune themselves are asked to drop idle buckets
+const PRUNE_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Outcome of taking one token from a bucket
+#[derive(Debug, Clone)]
+pub struct RateLimitDecision {
+    pub allowed: bool,
+    pub limit: u32,
+    pub remaining: u32,
+    /// Seconds until the bucket is full again
+    pub reset_seconds: u64,
+    /// Seconds until the next request would be allowed; zero when `allowed`
+    pub retry_after_seconds: u64,
+}
+
+impl RateLimitDecision {
+    fn new(policy: &RateLimitPolicy, tokens: f64, allowed: bool) -> Self {
+        let rate = policy.refill_per_second();
+        let reset_seconds = ((policy.capacity as f64 - tokens).max(0.0) / rate).ceil() as u64;
+        let retry_after_seconds = if allowed {
+            0
+        } else {
+            ((1.0 - tokens) / rate).ceil().max(1.0) as u64
+        };
+
+        Self {
+            allowed,
+            limit: policy.capacity,
+            remaining: tokens.floor().max(0.0) as u32,
+            reset_seconds,
+            retry_after_seconds,
+        }
+    }
+}
+
+// Refills the bucket for the elapsed time, then takes one token if there is one
+fn take_token(policy: &RateLimitPolicy, tokens: f64, elapsed_seconds: f64) -> (f64, bool) {
+    let refilled = (tokens + elapsed_seconds * policy.refill_per_second()).min(policy.capacity as f64);
+    if refilled >= 1.0 {
+        (refilled - 1.0, true)
+    } else {
+        (refilled, false)
+    }
+}
+
+/// Storage for token buckets, shared by every request the limiter sees
+#[async_trait]
+pub trait RateLimitStore: Send + Sync {
+    async fn take(&self, key: &str, policy: &RateLimitPolicy) -> Result<RateLimitDecision, sqlx::Error>;
+
+    /// Drops the buckets of `policy` that have been idle long enough to be full again,
+    /// returning how many were removed
+    async fn prune(&self, _policy: &RateLimitPolicy) -> Result<u64, sqlx::Error> {
+        Ok(0)
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    /// When the bucket will have refilled completely, after which it can be forgotten
+    full_at: Instant,
+}
+
+/// Buckets kept in this process. Budgets are per instance, so N instances allow N times the budget.
+#[derive(Default)]
+pub struct MemoryStore {
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+#[async_trait]
+impl RateLimitStore for MemoryStore {
+    async fn take(&self, key: &str, policy: &RateLimitPolicy) -> Result<RateLimitDecision, sqlx::Error> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().expect("rate limit store lock poisoned");
+
+        if buckets.len() >= MEMORY_STORE_PRUNE_THRESHOLD && !buckets.contains_key(key) {
+            // A full bucket behaves exactly like a missing one
+            buckets.retain(|_, bucket| bucket.full_at > now);
+        }
+
+        let bucket = buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: policy.capacity as f64,
+            updated: now,
+            full_at: now,
+        });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        let (tokens, allowed) = take_token(policy, bucket.tokens, elapsed);
+        let decision = RateLimitDecision::new(policy, tokens, allowed);
+        bucket.tokens = tokens;
+        bucket.updated = now;
+        bucket.full_at = now + Duration::from_secs(decision.reset_seconds);
+
+        Ok(decision)
+    }
+}
+
+/// Buckets kept in the `rate_limit_buckets` table, so all instances share one budget
+pub struct PostgresStore {
+    pool: PgPool,
+}
+
+impl PostgresStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl RateLimitStore for PostgresStore {
+    async fn take(&self, key: &str, policy: &RateLimitPolicy) -> Result<RateLimitDecision, sqlx::Error> {
+        // Refill and take in a single upsert so concurrent requests cannot overspend. A
+        // refused request leaves the row as it was, so `updated_at` only moves when a token
+        // is taken and tells the two outcomes apart.
+        let (tokens, allowed): (f64, bool) = sqlx::query_as(
+            r#"
+            INSERT INTO rate_limit_buckets (key, tokens, updated_at)
+            VALUES ($1, $2 - 1, NOW())
+            ON CONFLICT (key) DO UPDATE
+            SET (tokens, updated_at) = (
+                SELECT
+                    CASE WHEN refilled >= 1 THEN refilled - 1 ELSE rate_limit_buckets.tokens END,
+                    CASE WHEN refilled >= 1 THEN NOW() ELSE rate_limit_buckets.updated_at END
+                FROM (
+                    SELECT LEAST(
+                        $2,
+                        rate_limit_buckets.tokens
+                            + EXTRACT(EPOCH FROM NOW() - rate_limit_buckets.updated_at)::FLOAT8 * $3
+                    ) AS refilled
+                ) refill
+            )
+            RETURNING
+                LEAST($2, tokens + EXTRACT(EPOCH FROM NOW() - updated_at)::FLOAT8 * $3),
+                updated_at = NOW()
+            "#,
+        )
+        .bind(key)
+        .bind(policy.capacity as f64)
+        .bind(policy.refill_per_second())
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(RateLimitDecision::new(policy, tokens, allowed))
+    }
+
+    async fn prune(&self, policy: &RateLimitPolicy) -> Result<u64, sqlx::Error> {
+        // Even an empty bucket is full again after one period, and a full bucket behaves
+        // exactly like a missing one
+        let result = sqlx::query(
+            r#"
+            DELETE FROM rate_limit_buckets
+            WHERE updated_at < NOW() - make_interval(secs => $1)
+            AND key LIKE $2
+            "#,
+        )
+        .bind(policy.period_seconds as f64)
+        .bind(format!("{}:%", policy.name))
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+/// Picks the policy for a route and charges the caller's bucket
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    store: Box<dyn RateLimitStore>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig, pool: PgPool) -> Arc<Self> {
+        let store: Box<dyn RateLimitStore> = match config.store {
+            RateLimitStoreKind::Memory => Box::new(MemoryStore::default()),
+            RateLimitStoreKind::Postgres => Box::new(PostgresStore::new(pool)),
+        };
+
+        Arc::new(Self { config, store })
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    /// Prunes idle buckets every `PRUNE_INTERVAL` for as long as the server runs
+    pub fn spawn_pruning(self: Arc<Self>) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(PRUNE_INTERVAL);
+            loop {
+                interval.tick().await;
+                for policy in [&self.config.auth, &self.config.ai, &self.config.default] {
+                    match self.store.prune(policy).await {
+                        Ok(deleted) => tracing::debug!("Pruned {} idle {} rate limit buckets", deleted, policy.name),
+                        Err(e) => tracing::error!("Failed to prune {} rate limit buckets: {}", policy.name, e),
+                    }
+                }
+            }
+        });
+    }
+
+    /// Budget for a route, looked up by method and matched path (e.g. `/api/mental-box/:id`)
+    pub fn policy_for(&self, method: &Method, path: &str) -> &RateLimitPolicy {
+        match (method, path) {
+            (&Method::POST, "/api/auth/login")
+            | (&Method::POST, "/api/auth/register")
+            | (&Method::POST, "/api/auth/password-reset/request")
+            | (&Method::POST, "/api/auth/password-reset/confirm") => &self.config.auth,
+            (&Method::POST, "/api/stress-reframe") | (&Method::POST, "/api/stress-reframe/stream") => {
+                &self.config.ai
+            }
+            (&Method::POST, path) if path.starts_with("/api/mental-box/") && path.ends_with("/analysis") => {
+                &self.config.ai
+            }
+            (&Method::POST, path) if path.starts_with("/api/stress-reframe/") && path.ends_with("/regenerate") => {
+                &self.config.ai
+            }
+            _ => &self.config.default,
+        }
+    }
+
+    /// Takes a token for `subject` (a user id or client IP). Buckets are per policy,
+    /// so spending the AI budget does not eat into the default one.
+    pub async fn check(
+        &self,
+        policy: &RateLimitPolicy,
+        subject: &str,
+    ) -> Result<RateLimitDecision, sqlx::Error> {
+        let key = format!("{}:{}", policy.name, subject);
+        self.store.take(&key, policy).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Ten requests per ten seconds, so one token refills every second
+    const POLICY: RateLimitPolicy = RateLimitPolicy {
+        name: "test",
+        capacity: 10,
+        period_seconds: 10,
+    };
+
+    #[test]
+    fn take_token_spends_one_token() {
+        assert_eq!(take_token(&POLICY, 10.0, 0.0), (9.0, true));
+    }
+
+    #[test]
+    fn take_token_refuses_an_empty_bucket() {
+        assert_eq!(take_token(&POLICY, 0.5, 0.0), (0.5, false));
+    }
+
+    #[test]
+    fn take_token_refills_for_elapsed_time() {
+        assert_eq!(take_token(&POLICY, 0.0, 2.5), (1.5, true));
+    }
+
+    #[test]
+    fn take_token_caps_refill_at_capacity() {
+        assert_eq!(take_token(&POLICY, 5.0, 3600.0), (9.0, true));
+    }
+
+    #[test]
+    fn decision_reports_retry_after_for_refusals() {
+        let decision = RateLimitDecision::new(&POLICY, 0.25, false);
+        assert_eq!(decision.remaining, 0);
+        assert_eq!(decision.retry_after_seconds, 1);
+        assert_eq!(decision.reset_seconds, 10);
+
+        let decision = RateLimitDecision::new(&POLICY, 9.0, true);
+        assert_eq!(decision.remaining, 9);
+        assert_eq!(decision.retry_after_seconds, 0);
+        assert_eq!(decision.reset_seconds, 1);
+    }
+}
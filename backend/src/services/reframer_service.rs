@@ -0,0 +1,441 @@
+use rand::Rng;
+use sqlx::PgPool;
+use std::{
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use uuid::Uuid;
+
+use crate::config::LlmConfig;
+use crate::services::llm_service::{
+    DeltaSink, DistortionAnalysis, LlmError, LlmOutput, ReframeGeneration, ReframeProvider, ReframeRequest,
+    TokenUsage,
+};
+
+/// Longest error text kept per recorded attempt
+const MAX_RECORDED_ERROR_LENGTH: usize = 500;
+
+enum CircuitState {
+    Closed { consecutive_failures: u32 },
+    Open { until: Instant },
+    /// The cooldown has passed and a single trial request is in flight
+    HalfOpen,
+}
+
+/// Fails fast while the provider looks down, instead of making every user wait for timeouts
+struct CircuitBreaker {
+    state: Mutex<CircuitState>,
+    failure_threshold: u32,
+    cooldown: Duration,
+}
+
+impl CircuitBreaker {
+    fn new(failure_threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            state: Mutex::new(CircuitState::Closed { consecutive_failures: 0 }),
+            failure_threshold,
+            cooldown,
+        }
+    }
+
+    /// Returns the seconds left until the next trial when the circuit is open
+    fn try_acquire(&self) -> Result<CircuitPermit<'_>, u64> {
+        let mut state = self.state.lock().expect("circuit breaker lock poisoned");
+        let trial = match *state {
+            CircuitState::Closed { .. } => false,
+            CircuitState::Open { until } => {
+                let now = Instant::now();
+                if now < until {
+                    return Err((until - now).as_secs().max(1));
+                }
+                *state = CircuitState::HalfOpen;
+                true
+            }
+            CircuitState::HalfOpen => return Err(1),
+        };
+
+        Ok(CircuitPermit {
+            breaker: self,
+            trial,
+            settled: false,
+        })
+    }
+
+    fn record_success(&self) {
+        *self.state.lock().expect("circuit breaker lock poisoned") =
+            CircuitState::Closed { consecutive_failures: 0 };
+    }
+
+    /// Returns true when this failure opened the circuit
+    fn record_failure(&self) -> bool {
+        let mut state = self.state.lock().expect("circuit breaker lock poisoned");
+        let failures = match *state {
+            CircuitState::Closed { consecutive_failures } => consecutive_failures + 1,
+            CircuitState::HalfOpen => self.failure_threshold,
+            CircuitState::Open { .. } => return false,
+        };
+
+        if failures >= self.failure_threshold {
+            *state = CircuitState::Open { until: Instant::now() + self.cooldown };
+            true
+        } else {
+            *state = CircuitState::Closed { consecutive_failures: failures };
+            false
+        }
+    }
+
+    /// A trial that failed for a non-transient reason still proves the provider is up
+    fn release(&self) {
+        let mut state = self.state.lock().expect("circuit breaker lock poisoned");
+        if let CircuitState::HalfOpen = *state {
+            *state = CircuitState::Closed { consecutive_failures: 0 };
+        }
+    }
+
+    // A trial that ended without an outcome proved nothing, so the next request may
+    // make a new one straight away
+    fn abandon_trial(&self) {
+        let mut state = self.state.lock().expect("circuit breaker lock poisoned");
+        if let CircuitState::HalfOpen = *state {
+            *state = CircuitState::Open { until: Instant::now() };
+        }
+    }
+}
+
+/// Permission to make one call through the breaker, settled with `succeed`, `fail` or
+/// `release`. A trial dropped unsettled, e.g. because the request was cancelled, reopens
+/// the circuit instead of leaving it half-open for good.
+struct CircuitPermit<'a> {
+    breaker: &'a CircuitBreaker,
+    trial: bool,
+    settled: bool,
+}
+
+impl CircuitPermit<'_> {
+    fn succeed(mut self) {
+        self.settled = true;
+        self.breaker.record_success();
+    }
+
+    /// Returns true when this failure opened the circuit
+    fn fail(mut self) -> bool {
+        self.settled = true;
+        self.breaker.record_failure()
+    }
+
+    fn release(mut self) {
+        self.settled = true;
+        self.breaker.release();
+    }
+}
+
+impl Drop for CircuitPermit<'_> {
+    fn drop(&mut self) {
+        if self.trial && !self.settled {
+            self.breaker.abandon_trial();
+        }
+    }
+}
+
+struct Attempt<'a> {
+    generation_id: Uuid,
+    user_id: Uuid,
+    model: &'a str,
+    attempt: i32,
+    latency: Duration,
+}
+
+/// Makes LLM calls through the configured provider with a total deadline, retries with
+/// jittered backoff, a circuit breaker and fallback models, recording every attempt
+pub struct Reframer {
+    provider: Arc<dyn ReframeProvider>,
+    pool: PgPool,
+    models: Vec<String>,
+    max_retries: u32,
+    retry_base_delay: Duration,
+    retry_max_delay: Duration,
+    total_timeout: Duration,
+    breaker: CircuitBreaker,
+    analyze_reframes: bool,
+}
+
+impl Reframer {
+    pub fn new(provider: Arc<dyn ReframeProvider>, config: &LlmConfig, pool: PgPool) -> Arc<Self> {
+        let mut models = vec![config.model.clone()];
+        models.extend(config.fallback_models.iter().cloned());
+
+        Arc::new(Self {
+            provider,
+            pool,
+            models,
+            max_retries: config.max_retries,
+            retry_base_delay: Duration::from_millis(config.retry_base_delay_ms),
+            retry_max_delay: Duration::from_millis(config.retry_max_delay_ms),
+            total_timeout: Duration::from_secs(config.total_timeout_seconds),
+            breaker: CircuitBreaker::new(
+                config.circuit_failure_threshold,
+                Duration::from_secs(config.circuit_cooldown_seconds),
+            ),
+            analyze_reframes: config.analyze_reframes,
+        })
+    }
+
+    /// Whether new reframes come with a distortion analysis
+    pub fn analyzes_reframes(&self) -> bool {
+        self.analyze_reframes
+    }
+
+    // "Full jitter": a random delay up to an exponentially growing cap
+    fn backoff(&self, retry: u32) -> Duration {
+        let cap = self
+            .retry_base_delay
+            .saturating_mul(1 << retry.min(16))
+            .min(self.retry_max_delay);
+        let millis = rand::thread_rng().gen_range(0..=cap.as_millis() as u64);
+        Duration::from_millis(millis)
+    }
+
+    pub async fn generate(&self, user_id: Uuid, request: &ReframeRequest) -> Result<ReframeGeneration, LlmError> {
+        self.run(user_id, None, |model| self.provider.generate_reframes(model, request)).await
+    }
+
+    /// Streams the reframe into `sink`. Failures are only retried, or handed to a fallback
+    /// model, until the first text has been sent.
+    pub async fn stream(
+        &self,
+        user_id: Uuid,
+        request: &ReframeRequest,
+        sink: &DeltaSink,
+    ) -> Result<ReframeGeneration, LlmError> {
+        self.run(user_id, Some(sink), |model| self.provider.stream_reframes(model, request, sink)).await
+    }
+
+    /// Classifies a thought into cognitive distortions
+    pub async fn analyze(&self, user_id: Uuid, thought: &str) -> Result<DistortionAnalysis, LlmError> {
+        self.run(user_id, None, |model| self.provider.detect_distortions(model, thought)).await
+    }
+
+    async fn run<'a, T, F, Fut>(
+        &'a self,
+        user_id: Uuid,
+        sink: Option<&DeltaSink>,
+        call: F,
+    ) -> Result<T, LlmError>
+    where
+        T: LlmOutput,
+        F: Fn(&'a str) -> Fut,
+        Fut: Future<Output = Result<T, LlmError>>,
+    {
+        let generation_id = Uuid::new_v4();
+        let deadline = Instant::now() + self.total_timeout;
+        let mut attempt = 0;
+        let mut last_error = LlmError::EmptyResponse;
+
+        for (index, model) in self.models.iter().enumerate() {
+            for retry in 0..=self.max_retries {
+                let permit = self
+                    .breaker
+                    .try_acquire()
+                    .map_err(|retry_after| LlmError::CircuitOpen(self.provider.name(), retry_after))?;
+
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining.is_zero() {
+                    return Err(LlmError::Timeout(self.provider.name()));
+                }
+
+                attempt += 1;
+                let started = Instant::now();
+                let result = tokio::time::timeout(remaining, call(model))
+                    .await
+                    .unwrap_or_else(|_| Err(LlmError::Timeout(self.provider.name())));
+                let record = Attempt {
+                    generation_id,
+                    user_id,
+                    model,
+                    attempt,
+                    latency: started.elapsed(),
+                };
+
+                let error = match result {
+                    Ok(output) => {
+                        permit.succeed();
+                        self.record_attempt(&record, Ok(output.usage())).await;
+                        return Ok(output);
+                    }
+                    Err(error) => error,
+                };
+
+                let transient = error.is_transient();
+                if transient {
+                    if permit.fail() {
+                        tracing::error!("{} circuit opened after repeated failures: {}", self.provider.name(), error);
+                    }
+                } else {
+                    permit.release();
+                }
+                self.record_attempt(&record, Err(&error)).await;
+
+                // No other model can succeed without credentials or an audience, and
+                // text already shown to the user cannot be taken back
+                if matches!(error, LlmError::MissingApiKey(_) | LlmError::Cancelled(_))
+                    || sink.is_some_and(|sink| sink.started())
+                {
+                    return Err(error);
+                }
+                last_error = error;
+                if !transient {
+                    break;
+                }
+
+                if retry < self.max_retries {
+                    let delay = self.backoff(retry);
+                    if Instant::now() + delay >= deadline {
+                        break;
+                    }
+                    tokio::time::sleep(delay).await;
+                }
+            }
+            if index + 1 < self.models.len() {
+                tracing::warn!("Model {} failed ({}); trying the next fallback", model, last_error);
+            }
+        }
+
+        Err(last_error)
+    }
+
+    // Failures are logged rather than returned, like audit events, so bookkeeping
+    // never fails a request that otherwise succeeded
+    async fn record_attempt(&self, attempt: &Attempt<'_>, result: Result<&TokenUsage, &LlmError>) {
+        let (outcome, status_code, error, total_tokens) = match result {
+            Ok(usage) => ("success", None, None, Some(usage.total_tokens)),
+            Err(e) => {
+                let message: String = e.to_string().chars().take(MAX_RECORDED_ERROR_LENGTH).collect();
+                // Failed calls can still cost tokens, which count towards the user's quota
+                let total_tokens = e.usage().map(|usage| usage.total_tokens);
+                (e.outcome(), e.status_code().map(i32::from), Some(message), total_tokens)
+            }
+        };
+
+        tracing::info!(
+            generation_id = %attempt.generation_id,
+            provider = self.provider.name(),
+            model = attempt.model,
+            attempt = attempt.attempt,
+            outcome,
+            latency_ms = attempt.latency.as_millis() as u64,
+            "LLM attempt"
+        );
+
+        let result = sqlx::query(
+            r#"
+            INSERT INTO llm_attempts (generation_id, user_id, provider, model, attempt, outcome, status_code, error, latency_ms, total_tokens)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+            "#,
+        )
+        .bind(attempt.generation_id)
+        .bind(attempt.user_id)
+        .bind(self.provider.name())
+        .bind(attempt.model)
+        .bind(attempt.attempt)
+        .bind(outcome)
+        .bind(status_code)
+        .bind(error)
+        .bind(attempt.latency.as_millis() as i32)
+        .bind(total_tokens)
+        .execute(&self.pool)
+        .await;
+
+        if let Err(e) = result {
+            tracing::error!("Failed to record LLM attempt: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LONG_COOLDOWN: Duration = Duration::from_secs(60);
+
+    fn is_closed(breaker: &CircuitBreaker) -> bool {
+        matches!(*breaker.state.lock().unwrap(), CircuitState::Closed { .. })
+    }
+
+    // Opens the circuit by failing `threshold` calls in a row
+    fn open(breaker: &CircuitBreaker) {
+        for _ in 0..breaker.failure_threshold {
+            breaker.try_acquire().unwrap().fail();
+        }
+    }
+
+    #[test]
+    fn opens_once_failures_reach_the_threshold() {
+        let breaker = CircuitBreaker::new(3, LONG_COOLDOWN);
+        assert!(!breaker.try_acquire().unwrap().fail());
+        assert!(!breaker.try_acquire().unwrap().fail());
+        assert!(breaker.try_acquire().unwrap().fail());
+
+        assert!(matches!(breaker.try_acquire().err(), Some(59..=60)));
+    }
+
+    #[test]
+    fn a_success_resets_the_failure_count() {
+        let breaker = CircuitBreaker::new(2, LONG_COOLDOWN);
+        breaker.try_acquire().unwrap().fail();
+        breaker.try_acquire().unwrap().succeed();
+        assert!(!breaker.try_acquire().unwrap().fail());
+        assert!(is_closed(&breaker));
+    }
+
+    #[test]
+    fn allows_a_single_trial_after_the_cooldown() {
+        let breaker = CircuitBreaker::new(1, Duration::ZERO);
+        open(&breaker);
+
+        let trial = breaker.try_acquire().unwrap();
+        assert_eq!(breaker.try_acquire().err(), Some(1));
+        trial.succeed();
+        assert!(is_closed(&breaker));
+    }
+
+    #[test]
+    fn a_failed_trial_reopens_the_circuit() {
+        let breaker = CircuitBreaker::new(3, LONG_COOLDOWN);
+        open(&breaker);
+        *breaker.state.lock().unwrap() = CircuitState::Open { until: Instant::now() };
+
+        assert!(breaker.try_acquire().unwrap().fail());
+        assert!(breaker.try_acquire().is_err());
+    }
+
+    #[test]
+    fn a_released_trial_closes_the_circuit() {
+        let breaker = CircuitBreaker::new(1, Duration::ZERO);
+        open(&breaker);
+
+        breaker.try_acquire().unwrap().release();
+        assert!(is_closed(&breaker));
+    }
+
+    #[test]
+    fn a_dropped_trial_allows_a_new_one() {
+        let breaker = CircuitBreaker::new(1, Duration::ZERO);
+        open(&breaker);
+
+        drop(breaker.try_acquire().unwrap());
+        assert!(matches!(*breaker.state.lock().unwrap(), CircuitState::Open { .. }));
+
+        breaker.try_acquire().unwrap().succeed();
+        assert!(is_closed(&breaker));
+    }
+
+    #[test]
+    fn a_dropped_call_leaves_a_closed_circuit_alone() {
+        let breaker = CircuitBreaker::new(2, LONG_COOLDOWN);
+        breaker.try_acquire().unwrap().fail();
+        drop(breaker.try_acquire().unwrap());
+
+        assert!(breaker.try_acquire().unwrap().fail());
+    }
+}
@@ -0,0 +1,68 @@
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::models::session::Session;
+use crate::services::auth_service;
+
+#[derive(Debug, thiserror::Error)]
+pub enum SessionError {
+    #[error("Session not found")]
+    NotFound,
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+/// Lists the user's active sessions, flagging the one whose refresh token hashes to `current_token_hash`.
+/// A session is a refresh token family, so its id stays the same across rotations.
+pub async fn list_sessions(
+    pool: &PgPool,
+    user_id: Uuid,
+    current_token_hash: Option<&str>,
+) -> Result<Vec<Session>, SessionError> {
+    let sessions = sqlx::query_as::<_, Session>(
+        r#"
+        SELECT t.family_id AS id, t.user_agent, t.ip_address,
+               (SELECT MIN(f.created_at) FROM refresh_tokens f WHERE f.family_id = t.family_id) AS created_at,
+               t.last_used_at, t.expires_at,
+               COALESCE(t.token_hash = $2, FALSE) AS is_current
+        FROM refresh_tokens t
+        WHERE t.user_id = $1 AND t.revoked = FALSE AND t.expires_at > NOW()
+        ORDER BY COALESCE(last_used_at, created_at) DESC
+        "#,
+    )
+    .bind(user_id)
+    .bind(current_token_hash)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(sessions)
+}
+
+pub async fn revoke_session(pool: &PgPool, user_id: Uuid, id: Uuid) -> Result<(), SessionError> {
+    let result = sqlx::query(
+        r#"
+        UPDATE refresh_tokens
+        SET revoked = TRUE, revoked_at = NOW()
+        WHERE family_id = $1 AND user_id = $2 AND revoked = FALSE
+        "#,
+    )
+    .bind(id)
+    .bind(user_id)
+    .execute(pool)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(SessionError::NotFound);
+    }
+
+    Ok(())
+}
+
+/// Signs out every other device, returning how many sessions were revoked
+pub async fn revoke_other_sessions(
+    pool: &PgPool,
+    user_id: Uuid,
+    current_token_hash: Option<&str>,
+) -> Result<u64, SessionError> {
+    Ok(auth_service::revoke_all_refresh_tokens(pool, user_id, current_token_hash).await?)
+}
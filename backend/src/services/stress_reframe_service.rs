@@ -0,0 +1,601 @@
+use chrono::{DateTime, Utc};
+use sqlx::{PgConnection, PgPool};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::models::stress_reframe::{
+    Perspective, PerspectiveFeedback, PerspectiveReframe, PerspectiveReframeRow, RatePerspectiveRequest,
+    ReframeResponse, StressReframe,
+};
+use crate::services::llm_service::{PerspectivePrompt, ReframeGeneration, ReframeRequest};
+use crate::services::thought_analysis_service;
+
+#[derive(Debug, thiserror::Error)]
+pub enum StressReframeError {
+    #[error("Unknown perspective: {0}")]
+    UnknownPerspective(String),
+    #[error("Perspective listed more than once: {0}")]
+    DuplicatePerspective(String),
+    #[error("Stress reframe not found")]
+    NotFound,
+    #[error("This reframe has no {0} perspective")]
+    PerspectiveNotFound(String),
+    #[error("Invalid pagination cursor")]
+    InvalidCursor,
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+/// Active perspectives, those the user has found most helpful first. Ratings are smoothed
+/// so a single vote does not outweigh the catalog's own order.
+pub async fn list_perspectives(pool: &PgPool, user_id: Uuid) -> Result<Vec<Perspective>, StressReframeError> {
+    let perspectives = sqlx::query_as::<_, Perspective>(
+        r#"
+        SELECT p.key, p.name, p.description, p.prompt_template, p.is_default
+        FROM perspectives p
+        LEFT JOIN (
+            SELECT f.perspective_key,
+                   COUNT(*) FILTER (WHERE f.helpful) AS helpful,
+                   COUNT(*) AS rated
+            FROM stress_reframe_feedback f
+            JOIN stress_reframes sr ON sr.id = f.stress_reframe_id
+            WHERE sr.user_id = $1
+            GROUP BY f.perspective_key
+        ) ratings ON ratings.perspective_key = p.key
+        WHERE p.is_active = TRUE
+        ORDER BY (COALESCE(ratings.helpful, 0) + 1)::FLOAT8 / (COALESCE(ratings.rated, 0) + 2) DESC,
+                 p.sort_order, p.key
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(perspectives)
+}
+
+/// Looks up the requested perspectives, keeping the caller's order, or the defaults
+/// ranked by the user's ratings when none were requested
+pub async fn resolve_perspectives(
+    pool: &PgPool,
+    user_id: Uuid,
+    keys: Option<&[String]>,
+) -> Result<Vec<Perspective>, StressReframeError> {
+    let catalog = list_perspectives(pool, user_id).await?;
+
+    let Some(keys) = keys else {
+        return Ok(catalog.into_iter().filter(|perspective| perspective.is_default).collect());
+    };
+
+    let mut resolved: Vec<Perspective> = Vec::with_capacity(keys.len());
+    for key in keys {
+        if resolved.iter().any(|perspective| &perspective.key == key) {
+            return Err(StressReframeError::DuplicatePerspective(key.clone()));
+        }
+        let perspective = catalog
+            .iter()
+            .find(|perspective| &perspective.key == key)
+            .ok_or_else(|| StressReframeError::UnknownPerspective(key.clone()))?;
+        resolved.push(perspective.clone());
+    }
+
+    Ok(resolved)
+}
+
+pub fn build_request(original_thought: &str, perspectives: &[Perspective]) -> ReframeRequest {
+    ReframeRequest {
+        original_thought: original_thought.to_string(),
+        perspectives: perspectives
+            .iter()
+            .map(|perspective| PerspectivePrompt {
+                key: perspective.key.clone(),
+                name: perspective.name.clone(),
+                instruction: perspective.prompt_template.clone(),
+            })
+            .collect(),
+    }
+}
+
+// Loads the perspectives of several reframes at once, grouped by reframe
+async fn load_perspectives(
+    pool: &PgPool,
+    reframe_ids: &[Uuid],
+) -> Result<HashMap<Uuid, Vec<PerspectiveReframe>>, StressReframeError> {
+    let rows = sqlx::query_as::<_, PerspectiveReframeRow>(
+        r#"
+        SELECT srp.stress_reframe_id, srp.perspective_key AS key, p.name, srp.content,
+               f.helpful, f.comment, f.updated_at AS rated_at
+        FROM stress_reframe_perspectives srp
+        JOIN perspectives p ON p.key = srp.perspective_key
+        LEFT JOIN stress_reframe_feedback f
+            ON f.stress_reframe_id = srp.stress_reframe_id AND f.perspective_key = srp.perspective_key
+        WHERE srp.stress_reframe_id = ANY($1)
+        ORDER BY srp.stress_reframe_id, srp.position
+        "#,
+    )
+    .bind(reframe_ids)
+    .fetch_all(pool)
+    .await?;
+
+    let mut grouped: HashMap<Uuid, Vec<PerspectiveReframe>> = HashMap::new();
+    for row in rows {
+        grouped.entry(row.stress_reframe_id).or_default().push(row.into());
+    }
+    Ok(grouped)
+}
+
+// Attaches each reframe's perspectives and distortion analysis
+async fn with_details(
+    pool: &PgPool,
+    reframes: Vec<StressReframe>,
+) -> Result<Vec<ReframeResponse>, StressReframeError> {
+    let ids: Vec<Uuid> = reframes.iter().map(|reframe| reframe.id).collect();
+    let mut perspectives = load_perspectives(pool, &ids).await?;
+    let mut analyses = thought_analysis_service::for_reframes(pool, &ids).await?;
+
+    Ok(reframes
+        .into_iter()
+        .map(|reframe| {
+            let reframe_perspectives = perspectives.remove(&reframe.id).unwrap_or_default();
+            let analysis = analyses.remove(&reframe.id);
+            ReframeResponse::new(reframe, reframe_perspectives, analysis)
+        })
+        .collect())
+}
+
+async fn with_details_one(pool: &PgPool, reframe: StressReframe) -> Result<ReframeResponse, StressReframeError> {
+    let mut responses = with_details(pool, vec![reframe]).await?;
+    Ok(responses.remove(0))
+}
+
+/// Returns the reframe already generated for a mental box entry, provided it covers every
+/// requested perspective. A pinned version wins over the latest one.
+pub async fn find_cached(
+    pool: &PgPool,
+    user_id: Uuid,
+    mental_box_id: Option<Uuid>,
+    perspectives: &[Perspective],
+) -> Result<Option<ReframeResponse>, StressReframeError> {
+    let Some(mental_box_id) = mental_box_id else {
+        return Ok(None);
+    };
+
+    let existing_reframe = sqlx::query_as::<_, StressReframe>(
+        r#"
+        SELECT id, user_id, mental_box_id, original_thought, thread_id, version, favorited_at, created_at
+        FROM stress_reframes
+        WHERE mental_box_id = $1 AND user_id = $2
+        ORDER BY favorited_at DESC NULLS LAST, created_at DESC
+        LIMIT 1
+        "#,
+    )
+    .bind(mental_box_id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+
+    let Some(reframe) = existing_reframe else {
+        return Ok(None);
+    };
+    let response = with_details_one(pool, reframe).await?;
+
+    let covered = perspectives
+        .iter()
+        .all(|wanted| response.perspectives.iter().any(|have| have.key == wanted.key));
+    Ok(covered.then_some(response))
+}
+
+// Held until the transaction ends
+async fn lock(conn: &mut PgConnection, key: Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        SELECT pg_advisory_xact_lock(hashtext('stress_reframes:' || $1::TEXT))
+        "#,
+    )
+    .bind(key)
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Stores a generated reframe with one row per perspective. It becomes the next version
+/// of `thread_id` when given, otherwise of the latest thread of its Mental Box entry.
+pub async fn save_reframe(
+    pool: &PgPool,
+    user_id: Uuid,
+    mental_box_id: Option<Uuid>,
+    original_thought: &str,
+    thread_id: Option<Uuid>,
+    generation: &ReframeGeneration,
+) -> Result<ReframeResponse, StressReframeError> {
+    let id = Uuid::new_v4();
+    let mut tx = pool.begin().await?;
+
+    // Serialises saves into the same entry's thread, so concurrent ones neither start two
+    // threads nor pick the same version. The entry is always locked before the thread.
+    if let Some(mental_box_id) = mental_box_id {
+        lock(&mut tx, mental_box_id).await?;
+    }
+
+    let thread_id = match (thread_id, mental_box_id) {
+        (Some(thread_id), _) => thread_id,
+        (None, Some(mental_box_id)) => sqlx::query_scalar::<_, Uuid>(
+            r#"
+            SELECT thread_id
+            FROM stress_reframes
+            WHERE mental_box_id = $1 AND user_id = $2
+            ORDER BY created_at DESC
+            LIMIT 1
+            "#,
+        )
+        .bind(mental_box_id)
+        .bind(user_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .unwrap_or(id),
+        (None, None) => id,
+    };
+    lock(&mut tx, thread_id).await?;
+
+    let reframe = sqlx::query_as::<_, StressReframe>(
+        r#"
+        INSERT INTO stress_reframes (id, user_id, mental_box_id, original_thought, thread_id, version)
+        SELECT $1, $2, $3, $4, $5, COALESCE(MAX(version), 0) + 1
+        FROM stress_reframes
+        WHERE thread_id = $5
+        RETURNING id, user_id, mental_box_id, original_thought, thread_id, version, favorited_at, created_at
+        "#,
+    )
+    .bind(id)
+    .bind(user_id)
+    .bind(mental_box_id)
+    .bind(original_thought)
+    .bind(thread_id)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    for (position, perspective) in generation.reframes.iter().enumerate() {
+        sqlx::query(
+            r#"
+            INSERT INTO stress_reframe_perspectives (stress_reframe_id, perspective_key, content, position)
+            VALUES ($1, $2, $3, $4)
+            "#,
+        )
+        .bind(reframe.id)
+        .bind(&perspective.key)
+        .bind(&perspective.content)
+        .bind(position as i32)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+
+    with_details_one(pool, reframe).await
+}
+
+pub async fn get_reframe(pool: &PgPool, user_id: Uuid, id: Uuid) -> Result<ReframeResponse, StressReframeError> {
+    let reframe = sqlx::query_as::<_, StressReframe>(
+        r#"
+        SELECT id, user_id, mental_box_id, original_thought, thread_id, version, favorited_at, created_at
+        FROM stress_reframes
+        WHERE id = $1 AND user_id = $2
+        "#,
+    )
+    .bind(id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?
+    .ok_or(StressReframeError::NotFound)?;
+
+    with_details_one(pool, reframe).await
+}
+
+/// Position after the last reframe of a page, in the `created_at DESC, id DESC` order
+#[derive(Debug, Clone, Copy)]
+pub struct ReframeCursor {
+    created_at: DateTime<Utc>,
+    id: Uuid,
+}
+
+impl ReframeCursor {
+    fn after(reframe: &ReframeResponse) -> Self {
+        Self {
+            created_at: reframe.created_at,
+            id: reframe.id,
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        format!("{}_{}", self.created_at.timestamp_micros(), self.id)
+    }
+
+    pub fn decode(cursor: &str) -> Result<Self, StressReframeError> {
+        let (micros, id) = cursor.split_once('_').ok_or(StressReframeError::InvalidCursor)?;
+        let micros: i64 = micros.parse().map_err(|_| StressReframeError::InvalidCursor)?;
+
+        Ok(Self {
+            created_at: DateTime::from_timestamp_micros(micros).ok_or(StressReframeError::InvalidCursor)?,
+            id: id.parse().map_err(|_| StressReframeError::InvalidCursor)?,
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ReframeFilter<'a> {
+    pub mental_box_id: Option<Uuid>,
+    pub search: Option<&'a str>,
+}
+
+pub struct ReframePage {
+    pub reframes: Vec<ReframeResponse>,
+    /// Set when more reframes follow this page
+    pub next_cursor: Option<String>,
+}
+
+// Escapes LIKE wildcards so the search text is matched literally
+fn like_pattern(search: &str) -> String {
+    let escaped = search.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+/// Lists the user's reframes newest first, one page at a time. Search matches whole words
+/// through the full-text index, and substrings for languages written without spaces.
+pub async fn list_reframes(
+    pool: &PgPool,
+    user_id: Uuid,
+    filter: &ReframeFilter<'_>,
+    cursor: Option<&str>,
+    limit: i64,
+) -> Result<ReframePage, StressReframeError> {
+    let cursor = cursor.map(ReframeCursor::decode).transpose()?;
+    let search = filter.search.map(str::trim).filter(|search| !search.is_empty());
+
+    // Each table is searched on its own, where its full-text and trigram indexes can be
+    // combined; OR-ing the perspectives into the outer query would rule the indexes out
+    let mut reframes = sqlx::query_as::<_, StressReframe>(
+        r#"
+        WITH matches AS (
+            SELECT sr.id
+            FROM stress_reframes sr
+            WHERE sr.user_id = $1
+              AND (sr.search_vector @@ websearch_to_tsquery('simple', $5) OR sr.original_thought ILIKE $6)
+            UNION
+            SELECT srp.stress_reframe_id
+            FROM stress_reframe_perspectives srp
+            WHERE srp.search_vector @@ websearch_to_tsquery('simple', $5) OR srp.content ILIKE $6
+        )
+        SELECT sr.id, sr.user_id, sr.mental_box_id, sr.original_thought, sr.thread_id, sr.version, sr.favorited_at, sr.created_at
+        FROM stress_reframes sr
+        WHERE sr.user_id = $1
+          AND ($2::UUID IS NULL OR sr.mental_box_id = $2)
+          AND ($3::TIMESTAMPTZ IS NULL OR (sr.created_at, sr.id) < ($3, $4::UUID))
+          AND ($5::TEXT IS NULL OR sr.id IN (SELECT id FROM matches))
+        ORDER BY sr.created_at DESC, sr.id DESC
+        LIMIT $7
+        "#,
+    )
+    .bind(user_id)
+    .bind(filter.mental_box_id)
+    .bind(cursor.map(|cursor| cursor.created_at))
+    .bind(cursor.map(|cursor| cursor.id))
+    .bind(search)
+    .bind(search.map(like_pattern))
+    // One extra row tells whether another page follows
+    .bind(limit + 1)
+    .fetch_all(pool)
+    .await?;
+
+    let has_more = reframes.len() as i64 > limit;
+    reframes.truncate(limit as usize);
+    let reframes = with_details(pool, reframes).await?;
+    let next_cursor = if has_more {
+        reframes.last().map(|reframe| ReframeCursor::after(reframe).encode())
+    } else {
+        None
+    };
+
+    Ok(ReframePage { reframes, next_cursor })
+}
+
+pub async fn delete_reframe(pool: &PgPool, user_id: Uuid, id: Uuid) -> Result<(), StressReframeError> {
+    let result = sqlx::query(
+        r#"
+        DELETE FROM stress_reframes
+        WHERE id = $1 AND user_id = $2
+        "#,
+    )
+    .bind(id)
+    .bind(user_id)
+    .execute(pool)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(StressReframeError::NotFound);
+    }
+    Ok(())
+}
+
+/// Every version in the reframe's thread, newest first
+pub async fn list_history(pool: &PgPool, user_id: Uuid, id: Uuid) -> Result<Vec<ReframeResponse>, StressReframeError> {
+    let reframes = sqlx::query_as::<_, StressReframe>(
+        r#"
+        SELECT sr.id, sr.user_id, sr.mental_box_id, sr.original_thought, sr.thread_id, sr.version, sr.favorited_at, sr.created_at
+        FROM stress_reframes sr
+        JOIN stress_reframes current ON current.thread_id = sr.thread_id AND current.user_id = sr.user_id
+        WHERE current.id = $1 AND current.user_id = $2
+        ORDER BY sr.version DESC
+        "#,
+    )
+    .bind(id)
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    if reframes.is_empty() {
+        return Err(StressReframeError::NotFound);
+    }
+    with_details(pool, reframes).await
+}
+
+pub async fn list_favorites(pool: &PgPool, user_id: Uuid) -> Result<Vec<ReframeResponse>, StressReframeError> {
+    let reframes = sqlx::query_as::<_, StressReframe>(
+        r#"
+        SELECT id, user_id, mental_box_id, original_thought, thread_id, version, favorited_at, created_at
+        FROM stress_reframes
+        WHERE user_id = $1 AND favorited_at IS NOT NULL
+        ORDER BY favorited_at DESC
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    with_details(pool, reframes).await
+}
+
+/// Pins or unpins a reframe. Pinning again keeps the original pin time.
+pub async fn set_favorite(
+    pool: &PgPool,
+    user_id: Uuid,
+    id: Uuid,
+    favorite: bool,
+) -> Result<ReframeResponse, StressReframeError> {
+    let reframe = sqlx::query_as::<_, StressReframe>(
+        r#"
+        UPDATE stress_reframes
+        SET favorited_at = CASE WHEN $3 THEN COALESCE(favorited_at, NOW()) ELSE NULL END
+        WHERE id = $1 AND user_id = $2
+        RETURNING id, user_id, mental_box_id, original_thought, thread_id, version, favorited_at, created_at
+        "#,
+    )
+    .bind(id)
+    .bind(user_id)
+    .bind(favorite)
+    .fetch_optional(pool)
+    .await?
+    .ok_or(StressReframeError::NotFound)?;
+
+    with_details_one(pool, reframe).await
+}
+
+// Fails with the right error when the reframe or its perspective does not exist
+async fn ensure_perspective(
+    pool: &PgPool,
+    user_id: Uuid,
+    id: Uuid,
+    perspective_key: &str,
+) -> Result<(), StressReframeError> {
+    let has_perspective: Option<bool> = sqlx::query_scalar(
+        r#"
+        SELECT EXISTS (
+            SELECT 1 FROM stress_reframe_perspectives
+            WHERE stress_reframe_id = sr.id AND perspective_key = $3
+        )
+        FROM stress_reframes sr
+        WHERE sr.id = $1 AND sr.user_id = $2
+        "#,
+    )
+    .bind(id)
+    .bind(user_id)
+    .bind(perspective_key)
+    .fetch_optional(pool)
+    .await?;
+
+    match has_perspective {
+        None => Err(StressReframeError::NotFound),
+        Some(false) => Err(StressReframeError::PerspectiveNotFound(perspective_key.to_string())),
+        Some(true) => Ok(()),
+    }
+}
+
+/// Records whether one perspective of a reframe helped, replacing any earlier rating
+pub async fn rate_perspective(
+    pool: &PgPool,
+    user_id: Uuid,
+    id: Uuid,
+    perspective_key: &str,
+    payload: &RatePerspectiveRequest,
+) -> Result<PerspectiveFeedback, StressReframeError> {
+    ensure_perspective(pool, user_id, id, perspective_key).await?;
+
+    let comment = payload
+        .comment
+        .as_deref()
+        .map(str::trim)
+        .filter(|comment| !comment.is_empty());
+
+    let feedback = sqlx::query_as::<_, PerspectiveFeedback>(
+        r#"
+        INSERT INTO stress_reframe_feedback (stress_reframe_id, perspective_key, helpful, comment)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (stress_reframe_id, perspective_key)
+        DO UPDATE SET helpful = EXCLUDED.helpful, comment = EXCLUDED.comment
+        RETURNING helpful, comment, updated_at AS rated_at
+        "#,
+    )
+    .bind(id)
+    .bind(perspective_key)
+    .bind(payload.helpful)
+    .bind(comment)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(feedback)
+}
+
+pub async fn clear_rating(
+    pool: &PgPool,
+    user_id: Uuid,
+    id: Uuid,
+    perspective_key: &str,
+) -> Result<(), StressReframeError> {
+    ensure_perspective(pool, user_id, id, perspective_key).await?;
+
+    sqlx::query(
+        r#"
+        DELETE FROM stress_reframe_feedback
+        WHERE stress_reframe_id = $1 AND perspective_key = $2
+        "#,
+    )
+    .bind(id)
+    .bind(perspective_key)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cursor_round_trips_through_its_encoding() {
+        let cursor = ReframeCursor {
+            created_at: DateTime::from_timestamp_micros(1_700_000_000_123_456).unwrap(),
+            id: Uuid::new_v4(),
+        };
+        let decoded = ReframeCursor::decode(&cursor.encode()).unwrap();
+        assert_eq!(decoded.created_at, cursor.created_at);
+        assert_eq!(decoded.id, cursor.id);
+    }
+
+    #[test]
+    fn cursor_keeps_microsecond_precision() {
+        let created_at = DateTime::from_timestamp_micros(1_700_000_000_000_001).unwrap();
+        let cursor = ReframeCursor { created_at, id: Uuid::nil() };
+        assert_eq!(cursor.encode(), format!("1700000000000001_{}", Uuid::nil()));
+    }
+
+    #[test]
+    fn cursor_rejects_malformed_input() {
+        for cursor in ["", "123", "abc_00000000-0000-0000-0000-000000000000", "123_not-a-uuid", "_"] {
+            assert!(
+                matches!(ReframeCursor::decode(cursor), Err(StressReframeError::InvalidCursor)),
+                "{cursor:?} should be rejected"
+            );
+        }
+    }
+}
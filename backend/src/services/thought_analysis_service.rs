@@ -0,0 +1,238 @@
+use sqlx::PgPool;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::models::thought_analysis::{DistortionRow, ThoughtAnalysis, ThoughtAnalysisRow};
+use crate::services::llm_service::DistortionAnalysis;
+
+/// `feature` recorded in `ai_usage` for distortion analyses
+pub const FEATURE_DISTORTION_ANALYSIS: &str = "distortion_analysis";
+
+/// What an analysis is stored against
+#[derive(Debug, Clone, Copy)]
+pub enum AnalysisTarget {
+    MentalBoxEntry(Uuid),
+    StressReframe(Uuid),
+}
+
+async fn load_distortions(
+    pool: &PgPool,
+    analysis_ids: &[Uuid],
+) -> Result<HashMap<Uuid, Vec<DistortionRow>>, sqlx::Error> {
+    let rows = sqlx::query_as::<_, DistortionRow>(
+        r#"
+        SELECT analysis_id, distortion, confidence, explanation
+        FROM thought_analysis_distortions
+        WHERE analysis_id = ANY($1)
+        ORDER BY analysis_id, confidence DESC
+        "#,
+    )
+    .bind(analysis_ids)
+    .fetch_all(pool)
+    .await?;
+
+    let mut grouped: HashMap<Uuid, Vec<DistortionRow>> = HashMap::new();
+    for row in rows {
+        grouped.entry(row.analysis_id).or_default().push(row);
+    }
+    Ok(grouped)
+}
+
+async fn with_distortions(
+    pool: &PgPool,
+    analyses: Vec<ThoughtAnalysisRow>,
+) -> Result<Vec<ThoughtAnalysis>, sqlx::Error> {
+    let ids: Vec<Uuid> = analyses.iter().map(|analysis| analysis.id).collect();
+    let mut distortions = load_distortions(pool, &ids).await?;
+
+    Ok(analyses
+        .into_iter()
+        .map(|analysis| {
+            let analysis_distortions = distortions.remove(&analysis.id).unwrap_or_default();
+            ThoughtAnalysis::new(analysis, analysis_distortions)
+        })
+        .collect())
+}
+
+pub async fn save_analysis(
+    pool: &PgPool,
+    user_id: Uuid,
+    target: AnalysisTarget,
+    analysis: &DistortionAnalysis,
+) -> Result<ThoughtAnalysis, sqlx::Error> {
+    let (mental_box_id, stress_reframe_id) = match target {
+        AnalysisTarget::MentalBoxEntry(id) => (Some(id), None),
+        AnalysisTarget::StressReframe(id) => (None, Some(id)),
+    };
+
+    let mut tx = pool.begin().await?;
+
+    let row = sqlx::query_as::<_, ThoughtAnalysisRow>(
+        r#"
+        INSERT INTO thought_analyses (user_id, mental_box_id, stress_reframe_id, model)
+        VALUES ($1, $2, $3, $4)
+        RETURNING id, mental_box_id, stress_reframe_id, created_at
+        "#,
+    )
+    .bind(user_id)
+    .bind(mental_box_id)
+    .bind(stress_reframe_id)
+    .bind(&analysis.model)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    for detected in &analysis.distortions {
+        sqlx::query(
+            r#"
+            INSERT INTO thought_analysis_distortions (analysis_id, distortion, confidence, explanation)
+            VALUES ($1, $2, $3, $4)
+            "#,
+        )
+        .bind(row.id)
+        .bind(detected.distortion.key())
+        .bind(detected.confidence)
+        .bind(&detected.explanation)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(with_distortions(pool, vec![row]).await?.remove(0))
+}
+
+/// The most recent analysis of a Mental Box entry
+pub async fn latest_for_entry(
+    pool: &PgPool,
+    user_id: Uuid,
+    mental_box_id: Uuid,
+) -> Result<Option<ThoughtAnalysis>, sqlx::Error> {
+    let row = sqlx::query_as::<_, ThoughtAnalysisRow>(
+        r#"
+        SELECT id, mental_box_id, stress_reframe_id, created_at
+        FROM thought_analyses
+        WHERE mental_box_id = $1 AND user_id = $2
+        ORDER BY created_at DESC
+        LIMIT 1
+        "#,
+    )
+    .bind(mental_box_id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+
+    match row {
+        Some(row) => Ok(with_distortions(pool, vec![row]).await?.pop()),
+        None => Ok(None),
+    }
+}
+
+/// The latest analysis of exactly `thought`, taken from an earlier version of `thread_id`
+/// or from the Mental Box entry the thought came from while the entry is unchanged, so a
+/// reframe of the same thought does not pay for a second analysis
+pub async fn find_reusable(
+    pool: &PgPool,
+    user_id: Uuid,
+    thought: &str,
+    mental_box_id: Option<Uuid>,
+    thread_id: Option<Uuid>,
+) -> Result<Option<ThoughtAnalysis>, sqlx::Error> {
+    let row = sqlx::query_as::<_, ThoughtAnalysisRow>(
+        r#"
+        SELECT ta.id, ta.mental_box_id, ta.stress_reframe_id, ta.created_at
+        FROM thought_analyses ta
+        LEFT JOIN stress_reframes sr ON sr.id = ta.stress_reframe_id
+        LEFT JOIN mental_box_entries mb ON mb.id = ta.mental_box_id
+        WHERE ta.user_id = $1
+          AND (
+              (sr.thread_id = $4 AND sr.original_thought = $2)
+              OR (mb.id = $3 AND mb.content = $2 AND ta.created_at >= mb.updated_at)
+          )
+        ORDER BY ta.created_at DESC
+        LIMIT 1
+        "#,
+    )
+    .bind(user_id)
+    .bind(thought)
+    .bind(mental_box_id)
+    .bind(thread_id)
+    .fetch_optional(pool)
+    .await?;
+
+    match row {
+        Some(row) => Ok(with_distortions(pool, vec![row]).await?.pop()),
+        None => Ok(None),
+    }
+}
+
+/// Stores a copy of an existing analysis against another target, without calling the model
+pub async fn copy_analysis(
+    pool: &PgPool,
+    user_id: Uuid,
+    source_id: Uuid,
+    target: AnalysisTarget,
+) -> Result<ThoughtAnalysis, sqlx::Error> {
+    let (mental_box_id, stress_reframe_id) = match target {
+        AnalysisTarget::MentalBoxEntry(id) => (Some(id), None),
+        AnalysisTarget::StressReframe(id) => (None, Some(id)),
+    };
+
+    let mut tx = pool.begin().await?;
+
+    let row = sqlx::query_as::<_, ThoughtAnalysisRow>(
+        r#"
+        INSERT INTO thought_analyses (user_id, mental_box_id, stress_reframe_id, model)
+        SELECT $1, $2, $3, model
+        FROM thought_analyses
+        WHERE id = $4 AND user_id = $1
+        RETURNING id, mental_box_id, stress_reframe_id, created_at
+        "#,
+    )
+    .bind(user_id)
+    .bind(mental_box_id)
+    .bind(stress_reframe_id)
+    .bind(source_id)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO thought_analysis_distortions (analysis_id, distortion, confidence, explanation)
+        SELECT $1, distortion, confidence, explanation
+        FROM thought_analysis_distortions
+        WHERE analysis_id = $2
+        "#,
+    )
+    .bind(row.id)
+    .bind(source_id)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(with_distortions(pool, vec![row]).await?.remove(0))
+}
+
+/// Analyses of several reframes at once, keyed by reframe
+pub async fn for_reframes(
+    pool: &PgPool,
+    stress_reframe_ids: &[Uuid],
+) -> Result<HashMap<Uuid, ThoughtAnalysis>, sqlx::Error> {
+    let rows = sqlx::query_as::<_, ThoughtAnalysisRow>(
+        r#"
+        SELECT DISTINCT ON (stress_reframe_id) id, mental_box_id, stress_reframe_id, created_at
+        FROM thought_analyses
+        WHERE stress_reframe_id = ANY($1)
+        ORDER BY stress_reframe_id, created_at DESC
+        "#,
+    )
+    .bind(stress_reframe_ids)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(with_distortions(pool, rows)
+        .await?
+        .into_iter()
+        .filter_map(|analysis| analysis.stress_reframe_id.map(|id| (id, analysis)))
+        .collect())
+}
@@ -0,0 +1,163 @@
+use chrono_tz::Tz;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::models::user::{ChangeEmailRequest, ChangePasswordRequest, UpdateProfileRequest, User};
+use crate::services::auth_service;
+use crate::utils::password;
+
+pub const SUPPORTED_LANGUAGES: &[&str] = &["th", "en"];
+pub const SUPPORTED_THEMES: &[&str] = &["light", "dark", "system"];
+
+#[derive(Debug, thiserror::Error)]
+pub enum UserError {
+    #[error("Unknown timezone: {0}")]
+    InvalidTimezone(String),
+    #[error("Unsupported language: {0}")]
+    InvalidLanguage(String),
+    #[error("Unsupported theme: {0}")]
+    InvalidTheme(String),
+    #[error("Email is already registered")]
+    EmailTaken,
+    #[error("Current password is incorrect")]
+    InvalidPassword,
+    #[error("User not found")]
+    NotFound,
+    #[error("Password hashing error: {0}")]
+    Hashing(#[from] bcrypt::BcryptError),
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+fn parse_timezone(timezone: &str) -> Result<Tz, UserError> {
+    timezone
+        .parse::<Tz>()
+        .map_err(|_| UserError::InvalidTimezone(timezone.to_string()))
+}
+
+pub async fn update_timezone(pool: &PgPool, user_id: Uuid, timezone: &str) -> Result<User, UserError> {
+    let tz = parse_timezone(timezone)?;
+
+    sqlx::query_as::<_, User>(
+        r#"
+        UPDATE users
+        SET timezone = $1
+        WHERE id = $2
+        RETURNING *
+        "#,
+    )
+    .bind(tz.name())
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?
+    .ok_or(UserError::NotFound)
+}
+
+pub async fn update_profile(
+    pool: &PgPool,
+    user: User,
+    payload: UpdateProfileRequest,
+) -> Result<User, UserError> {
+    let username = match payload.username {
+        Some(username) => username.trim().to_string(),
+        None => user.username,
+    };
+
+    let preferred_language = match payload.preferred_language {
+        Some(language) if SUPPORTED_LANGUAGES.contains(&language.as_str()) => language,
+        Some(language) => return Err(UserError::InvalidLanguage(language)),
+        None => user.preferred_language,
+    };
+
+    let preferred_theme = match payload.preferred_theme {
+        Some(theme) if SUPPORTED_THEMES.contains(&theme.as_str()) => theme,
+        Some(theme) => return Err(UserError::InvalidTheme(theme)),
+        None => user.preferred_theme,
+    };
+
+    let timezone = match payload.timezone {
+        Some(timezone) => parse_timezone(&timezone)?.name().to_string(),
+        None => user.timezone,
+    };
+
+    sqlx::query_as::<_, User>(
+        r#"
+        UPDATE users
+        SET username = $1, preferred_language = $2, preferred_theme = $3, timezone = $4
+        WHERE id = $5
+        RETURNING *
+        "#,
+    )
+    .bind(&username)
+    .bind(&preferred_language)
+    .bind(&preferred_theme)
+    .bind(&timezone)
+    .bind(user.id)
+    .fetch_optional(pool)
+    .await?
+    .ok_or(UserError::NotFound)
+}
+
+pub async fn change_email(
+    pool: &PgPool,
+    user: User,
+    payload: ChangeEmailRequest,
+) -> Result<User, UserError> {
+    // Re-authenticate before changing the address used to log in
+    if !password::verify_password(&payload.current_password, &user.password_hash)? {
+        return Err(UserError::InvalidPassword);
+    }
+
+    let new_email = payload.new_email.trim();
+
+    sqlx::query_as::<_, User>(
+        r#"
+        UPDATE users
+        SET email = $1
+        WHERE id = $2
+        RETURNING *
+        "#,
+    )
+    .bind(new_email)
+    .bind(user.id)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| match &e {
+        sqlx::Error::Database(db) if db.is_unique_violation() => UserError::EmailTaken,
+        _ => UserError::Database(e),
+    })?
+    .ok_or(UserError::NotFound)
+}
+
+/// Changes the password and signs out every other session
+pub async fn change_password(
+    pool: &PgPool,
+    user: User,
+    payload: ChangePasswordRequest,
+    current_token_hash: Option<&str>,
+) -> Result<(), UserError> {
+    if !password::verify_password(&payload.current_password, &user.password_hash)? {
+        return Err(UserError::InvalidPassword);
+    }
+    let password_hash = password::hash_password(&payload.new_password)?;
+
+    let mut tx = pool.begin().await?;
+
+    sqlx::query(
+        r#"
+        UPDATE users
+        SET password_hash = $1
+        WHERE id = $2
+        "#,
+    )
+    .bind(&password_hash)
+    .bind(user.id)
+    .execute(&mut *tx)
+    .await?;
+
+    auth_service::revoke_all_refresh_tokens(&mut *tx, user.id, current_token_hash).await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
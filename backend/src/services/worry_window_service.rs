@@ -1 +1,968 @@
-// Placeholder for worry window service
+use chrono::{Datelike, Duration, NaiveDate, NaiveTime, Utc, Weekday};
+use chrono_tz::Tz;
+use sqlx::{types::Json, PgConnection, PgExecutor, PgPool};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::models::worry_window::{
+    CreateWorryWindowRequest, OccurrenceOverrideResponse, ParkWorryRequest, ParkedWorry,
+    ParkedWorryDetail, RecordOutcomeRequest, RecurrenceFrequency, RecurrenceRule,
+    UpdateOccurrenceRequest, UpdateWorryWindowRequest, WorryWindow, WorryWindowOccurrence,
+    WorryWindowOccurrenceOverride, WorryWindowResponse, WorrySessionSummary,
+};
+
+/// Longest date range that may be expanded in one request
+const MAX_RANGE_DAYS: i64 = 366;
+
+#[derive(Debug, thiserror::Error)]
+pub enum WorryWindowError {
+    #[error("Worry window not found")]
+    NotFound,
+    #[error("Title must not be empty")]
+    EmptyTitle,
+    #[error("End time must be after start time")]
+    InvalidTimeRange,
+    #[error("Worry window overlaps with an existing window on the same date")]
+    Overlap,
+    #[error("Invalid recurrence rule: {0}")]
+    InvalidRecurrence(&'static str),
+    #[error("Date range must be at most {MAX_RANGE_DAYS} days and end on or after its start")]
+    InvalidDateRange,
+    #[error("Date is not an occurrence of this worry window")]
+    NotAnOccurrence,
+    #[error("An occurrence date is required for recurring worry windows")]
+    OccurrenceDateRequired,
+    #[error("Mental box entry not found")]
+    MentalBoxEntryNotFound,
+    #[error("Parked worry not found")]
+    ParkedWorryNotFound,
+    #[error("Mental box entry is already parked in this worry window")]
+    AlreadyParked,
+    #[error("Worries are parked in occurrences this change would remove; unpark them first")]
+    ParkedWorriesOrphaned,
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+fn validate(title: &str, start_time: NaiveTime, end_time: NaiveTime) -> Result<(), WorryWindowError> {
+    if title.trim().is_empty() {
+        return Err(WorryWindowError::EmptyTitle);
+    }
+    if end_time <= start_time {
+        return Err(WorryWindowError::InvalidTimeRange);
+    }
+    Ok(())
+}
+
+fn validate_recurrence(rule: &RecurrenceRule, scheduled_date: NaiveDate) -> Result<(), WorryWindowError> {
+    if rule.interval == 0 {
+        return Err(WorryWindowError::InvalidRecurrence("interval must be at least 1"));
+    }
+    if rule.until.is_some() && rule.count.is_some() {
+        return Err(WorryWindowError::InvalidRecurrence("until and count cannot both be set"));
+    }
+    if rule.until.is_some_and(|until| until < scheduled_date) {
+        return Err(WorryWindowError::InvalidRecurrence("until must not be before scheduled_date"));
+    }
+    if rule.count == Some(0) {
+        return Err(WorryWindowError::InvalidRecurrence("count must be at least 1"));
+    }
+    if rule.frequency == RecurrenceFrequency::Weekdays && rule.interval > 1 {
+        return Err(WorryWindowError::InvalidRecurrence("interval is not supported for weekdays recurrence"));
+    }
+    if rule.frequency != RecurrenceFrequency::Weekly && !rule.days_of_week.is_empty() {
+        return Err(WorryWindowError::InvalidRecurrence("days_of_week is only valid for weekly recurrence"));
+    }
+    Ok(())
+}
+
+// Day number of the Monday starting `date`'s week, without building a date that could
+// fall outside the supported range
+fn monday_number(date: NaiveDate) -> i64 {
+    i64::from(date.num_days_from_ce()) - i64::from(date.weekday().num_days_from_monday())
+}
+
+// Whether a weekly series occurs on `weekday` in the weeks it is active
+fn on_weekly_day(rule: &RecurrenceRule, start: NaiveDate, weekday: Weekday) -> bool {
+    if rule.days_of_week.is_empty() {
+        weekday == start.weekday()
+    } else {
+        rule.days_of_week.contains(&weekday)
+    }
+}
+
+fn matches_rule(rule: &RecurrenceRule, start: NaiveDate, date: NaiveDate) -> bool {
+    let interval = i64::from(rule.interval.max(1));
+
+    match rule.frequency {
+        RecurrenceFrequency::Daily => (date - start).num_days() % interval == 0,
+        RecurrenceFrequency::Weekdays => !matches!(date.weekday(), Weekday::Sat | Weekday::Sun),
+        RecurrenceFrequency::Weekly => {
+            // Count whole weeks between the Mondays of both dates
+            let weeks = (monday_number(date) - monday_number(start)) / 7;
+            on_weekly_day(rule, start, date.weekday()) && weeks % interval == 0
+        }
+    }
+}
+
+// Occurrences of a series starting on `start` that fall before `date`, counted without
+// walking the days in between so a series that began long ago costs no more than a new one
+fn occurrences_before(rule: &RecurrenceRule, start: NaiveDate, date: NaiveDate) -> i64 {
+    let days = (date - start).num_days();
+    if days <= 0 {
+        return 0;
+    }
+    let interval = i64::from(rule.interval.max(1));
+    // Days of the week numbered from Monday = 0
+    let weekday_numbers = || (0..7).map(|n| Weekday::try_from(n as u8).expect("0..7 is a weekday"));
+
+    match rule.frequency {
+        RecurrenceFrequency::Daily => (days + interval - 1) / interval,
+        RecurrenceFrequency::Weekdays => {
+            // Five in every whole week, plus those among the days left over, which start on
+            // the same weekday as the series
+            let first_left = i64::from(start.weekday().num_days_from_monday());
+            let left_over = (0..days % 7).filter(|offset| (first_left + offset) % 7 < 5).count() as i64;
+            days / 7 * 5 + left_over
+        }
+        RecurrenceFrequency::Weekly => {
+            let per_week = weekday_numbers().filter(|&day| on_weekly_day(rule, start, day)).count() as i64;
+            let last_week = (monday_number(date) - monday_number(start)) / 7;
+            let mut count = (last_week / interval + 1) * per_week;
+
+            // Take off the first week's days before `start`, and the last week's days from
+            // `date` on when that week is active
+            let start_day = start.weekday().num_days_from_monday();
+            count -= weekday_numbers()
+                .filter(|&day| day.num_days_from_monday() < start_day && on_weekly_day(rule, start, day))
+                .count() as i64;
+            if last_week % interval == 0 {
+                let date_day = date.weekday().num_days_from_monday();
+                count -= weekday_numbers()
+                    .filter(|&day| day.num_days_from_monday() >= date_day && on_weekly_day(rule, start, day))
+                    .count() as i64;
+            }
+            count
+        }
+    }
+}
+
+/// Dates on which `window` occurs between `from` and `to` (both inclusive)
+pub fn occurrence_dates(window: &WorryWindow, from: NaiveDate, to: NaiveDate) -> Vec<NaiveDate> {
+    let rule = window.recurrence.as_ref().map(|Json(rule)| rule);
+    series_dates(window.scheduled_date, rule, from, to)
+}
+
+fn series_dates(
+    scheduled_date: NaiveDate,
+    rule: Option<&RecurrenceRule>,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Vec<NaiveDate> {
+    let Some(rule) = rule else {
+        return if scheduled_date >= from && scheduled_date <= to {
+            vec![scheduled_date]
+        } else {
+            vec![]
+        };
+    };
+
+    let last = rule.until.map_or(to, |until| until.min(to));
+    let mut dates = Vec::new();
+    // Start at the first day in range; earlier occurrences only matter towards `count`
+    let mut date = scheduled_date.max(from);
+    let mut seen = occurrences_before(rule, scheduled_date, date);
+
+    while date <= last {
+        if matches_rule(rule, scheduled_date, date) {
+            seen += 1;
+            if rule.count.is_some_and(|count| seen > i64::from(count)) {
+                break;
+            }
+            dates.push(date);
+        }
+        let Some(next) = date.succ_opt() else {
+            break;
+        };
+        date = next;
+    }
+
+    dates
+}
+
+// Serialises the overlap check and write of a user's windows; held until the transaction ends
+async fn lock_windows(conn: &mut PgConnection, user_id: Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        SELECT pg_advisory_xact_lock(hashtext('worry_windows:' || $1::TEXT))
+        "#,
+    )
+    .bind(user_id)
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Rejects a window whose occurrences clash with any occurrence of another window. Open-ended
+/// series are compared over the first `MAX_RANGE_DAYS` days of the candidate. Callers hold
+/// `lock_windows` so no clashing window can be written between the check and their own write.
+async fn ensure_no_overlap(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    scheduled_date: NaiveDate,
+    recurrence: Option<&RecurrenceRule>,
+    start_time: NaiveTime,
+    end_time: NaiveTime,
+    exclude_id: Option<Uuid>,
+) -> Result<(), WorryWindowError> {
+    let horizon = scheduled_date
+        .checked_add_signed(Duration::days(MAX_RANGE_DAYS - 1))
+        .unwrap_or(NaiveDate::MAX);
+    let candidate_dates = series_dates(scheduled_date, recurrence, scheduled_date, horizon);
+    let (Some(&from), Some(&to)) = (candidate_dates.first(), candidate_dates.last()) else {
+        return Ok(());
+    };
+
+    // Two windows overlap when each one starts before the other ends
+    let windows = sqlx::query_as::<_, WorryWindow>(
+        r#"
+        SELECT id, user_id, title, description, scheduled_date, start_time, end_time, is_completed, recurrence, created_at, updated_at
+        FROM worry_windows
+        WHERE user_id = $1
+        AND start_time < $5
+        AND end_time > $4
+        AND ($6::UUID IS NULL OR id <> $6)
+        AND (
+            (recurrence IS NULL AND scheduled_date BETWEEN $2 AND $3)
+            OR (recurrence IS NOT NULL AND scheduled_date <= $3)
+        )
+        "#,
+    )
+    .bind(user_id)
+    .bind(from)
+    .bind(to)
+    .bind(start_time)
+    .bind(end_time)
+    .bind(exclude_id)
+    .fetch_all(conn)
+    .await?;
+
+    let overlaps = windows.iter().any(|window| {
+        occurrence_dates(window, from, to)
+            .iter()
+            .any(|date| candidate_dates.binary_search(date).is_ok())
+    });
+
+    if overlaps {
+        return Err(WorryWindowError::Overlap);
+    }
+
+    Ok(())
+}
+
+pub async fn create_window(
+    pool: &PgPool,
+    user_id: Uuid,
+    payload: CreateWorryWindowRequest,
+) -> Result<WorryWindow, WorryWindowError> {
+    validate(&payload.title, payload.start_time, payload.end_time)?;
+    if let Some(rule) = &payload.recurrence {
+        validate_recurrence(rule, payload.scheduled_date)?;
+    }
+
+    let mut tx = pool.begin().await?;
+    lock_windows(&mut tx, user_id).await?;
+    ensure_no_overlap(
+        &mut tx,
+        user_id,
+        payload.scheduled_date,
+        payload.recurrence.as_ref(),
+        payload.start_time,
+        payload.end_time,
+        None,
+    )
+    .await?;
+
+    let window = sqlx::query_as::<_, WorryWindow>(
+        r#"
+        INSERT INTO worry_windows (user_id, title, description, scheduled_date, start_time, end_time, recurrence)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        RETURNING id, user_id, title, description, scheduled_date, start_time, end_time, is_completed, recurrence, created_at, updated_at
+        "#,
+    )
+    .bind(user_id)
+    .bind(&payload.title)
+    .bind(&payload.description)
+    .bind(payload.scheduled_date)
+    .bind(payload.start_time)
+    .bind(payload.end_time)
+    .bind(payload.recurrence.map(Json))
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(window)
+}
+
+pub async fn list_windows(pool: &PgPool, user_id: Uuid) -> Result<Vec<WorryWindow>, WorryWindowError> {
+    let windows = sqlx::query_as::<_, WorryWindow>(
+        r#"
+        SELECT id, user_id, title, description, scheduled_date, start_time, end_time, is_completed, recurrence, created_at, updated_at
+        FROM worry_windows
+        WHERE user_id = $1
+        ORDER BY scheduled_date DESC, start_time ASC
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(windows)
+}
+
+pub async fn get_window(
+    executor: impl PgExecutor<'_>,
+    user_id: Uuid,
+    id: Uuid,
+) -> Result<WorryWindow, WorryWindowError> {
+    sqlx::query_as::<_, WorryWindow>(
+        r#"
+        SELECT id, user_id, title, description, scheduled_date, start_time, end_time, is_completed, recurrence, created_at, updated_at
+        FROM worry_windows
+        WHERE id = $1 AND user_id = $2
+        "#,
+    )
+    .bind(id)
+    .bind(user_id)
+    .fetch_optional(executor)
+    .await?
+    .ok_or(WorryWindowError::NotFound)
+}
+
+pub async fn update_window(
+    pool: &PgPool,
+    user_id: Uuid,
+    id: Uuid,
+    payload: UpdateWorryWindowRequest,
+) -> Result<WorryWindowResponse, WorryWindowError> {
+    let mut tx = pool.begin().await?;
+    lock_windows(&mut tx, user_id).await?;
+
+    let existing = get_window(&mut *tx, user_id, id).await?;
+    let was_completed = existing.is_completed;
+    let was_recurring = existing.recurrence.is_some();
+
+    let title = payload.title.unwrap_or(existing.title);
+    let description = payload.description.or(existing.description);
+    let scheduled_date = payload.scheduled_date.unwrap_or(existing.scheduled_date);
+    let start_time = payload.start_time.unwrap_or(existing.start_time);
+    let end_time = payload.end_time.unwrap_or(existing.end_time);
+    let is_completed = payload.is_completed.unwrap_or(existing.is_completed);
+    let recurrence = match payload.recurrence {
+        Some(recurrence) => recurrence,
+        None => existing.recurrence.map(|Json(rule)| rule),
+    };
+
+    validate(&title, start_time, end_time)?;
+    if let Some(rule) = &recurrence {
+        validate_recurrence(rule, scheduled_date)?;
+    }
+    ensure_no_overlap(
+        &mut tx,
+        user_id,
+        scheduled_date,
+        recurrence.as_ref(),
+        start_time,
+        end_time,
+        Some(id),
+    )
+    .await?;
+
+    // A one-off window takes its parked worries along to its new date. For a series, every
+    // parked worry must still fall on an occurrence of the updated schedule.
+    let moves_one_off = !was_recurring
+        && recurrence.is_none()
+        && existing.scheduled_date != scheduled_date;
+    if !moves_one_off {
+        let parked_dates: Vec<NaiveDate> = sqlx::query_scalar(
+            r#"
+            SELECT DISTINCT occurrence_date FROM worry_window_parked_entries
+            WHERE worry_window_id = $1
+            "#,
+        )
+        .bind(id)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let orphaned = parked_dates
+            .iter()
+            .any(|&date| series_dates(scheduled_date, recurrence.as_ref(), date, date).is_empty());
+        if orphaned {
+            return Err(WorryWindowError::ParkedWorriesOrphaned);
+        }
+    }
+
+    let window = sqlx::query_as::<_, WorryWindow>(
+        r#"
+        UPDATE worry_windows
+        SET title = $1, description = $2, scheduled_date = $3, start_time = $4, end_time = $5, is_completed = $6, recurrence = $7
+        WHERE id = $8 AND user_id = $9
+        RETURNING id, user_id, title, description, scheduled_date, start_time, end_time, is_completed, recurrence, created_at, updated_at
+        "#,
+    )
+    .bind(&title)
+    .bind(&description)
+    .bind(scheduled_date)
+    .bind(start_time)
+    .bind(end_time)
+    .bind(is_completed)
+    .bind(recurrence.map(Json))
+    .bind(id)
+    .bind(user_id)
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(WorryWindowError::NotFound)?;
+
+    if moves_one_off {
+        sqlx::query(
+            r#"
+            UPDATE worry_window_parked_entries
+            SET occurrence_date = $1
+            WHERE worry_window_id = $2 AND occurrence_date = $3
+            "#,
+        )
+        .bind(window.scheduled_date)
+        .bind(window.id)
+        .bind(existing.scheduled_date)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+
+    // Completing a one-off window closes its only occurrence, which is where its parked
+    // worries now live; series complete per occurrence
+    let session_summary = match window.recurrence {
+        None if window.is_completed && !was_completed => {
+            Some(build_summary(pool, window.id, window.scheduled_date).await?)
+        }
+        _ => None,
+    };
+
+    Ok(WorryWindowResponse { window, session_summary })
+}
+
+pub async fn delete_window(pool: &PgPool, user_id: Uuid, id: Uuid) -> Result<(), WorryWindowError> {
+    let result = sqlx::query(
+        r#"
+        DELETE FROM worry_windows
+        WHERE id = $1 AND user_id = $2
+        "#,
+    )
+    .bind(id)
+    .bind(user_id)
+    .execute(pool)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(WorryWindowError::NotFound);
+    }
+
+    Ok(())
+}
+
+pub async fn list_occurrences(
+    pool: &PgPool,
+    user_id: Uuid,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<Vec<WorryWindowOccurrence>, WorryWindowError> {
+    if to < from || (to - from).num_days() >= MAX_RANGE_DAYS {
+        return Err(WorryWindowError::InvalidDateRange);
+    }
+
+    // One-off windows inside the range plus every series that has started by its end
+    let windows = sqlx::query_as::<_, WorryWindow>(
+        r#"
+        SELECT id, user_id, title, description, scheduled_date, start_time, end_time, is_completed, recurrence, created_at, updated_at
+        FROM worry_windows
+        WHERE user_id = $1
+        AND (
+            (recurrence IS NULL AND scheduled_date BETWEEN $2 AND $3)
+            OR (recurrence IS NOT NULL AND scheduled_date <= $3)
+        )
+        "#,
+    )
+    .bind(user_id)
+    .bind(from)
+    .bind(to)
+    .fetch_all(pool)
+    .await?;
+
+    let overrides = sqlx::query_as::<_, WorryWindowOccurrenceOverride>(
+        r#"
+        SELECT o.id, o.worry_window_id, o.occurrence_date, o.is_skipped, o.is_completed, o.created_at, o.updated_at
+        FROM worry_window_occurrences o
+        JOIN worry_windows w ON w.id = o.worry_window_id
+        WHERE w.user_id = $1 AND o.occurrence_date BETWEEN $2 AND $3
+        "#,
+    )
+    .bind(user_id)
+    .bind(from)
+    .bind(to)
+    .fetch_all(pool)
+    .await?;
+
+    let overrides: HashMap<(Uuid, NaiveDate), WorryWindowOccurrenceOverride> = overrides
+        .into_iter()
+        .map(|o| ((o.worry_window_id, o.occurrence_date), o))
+        .collect();
+
+    let mut occurrences: Vec<WorryWindowOccurrence> = windows
+        .iter()
+        .flat_map(|window| {
+            occurrence_dates(window, from, to).into_iter().map(|date| {
+                let occurrence_override = overrides.get(&(window.id, date));
+                let is_recurring = window.recurrence.is_some();
+                WorryWindowOccurrence {
+                    worry_window_id: window.id,
+                    occurrence_date: date,
+                    title: window.title.clone(),
+                    description: window.description.clone(),
+                    start_time: window.start_time,
+                    end_time: window.end_time,
+                    is_recurring,
+                    is_completed: match occurrence_override {
+                        Some(o) => o.is_completed,
+                        None => !is_recurring && window.is_completed,
+                    },
+                    is_skipped: occurrence_override.is_some_and(|o| o.is_skipped),
+                }
+            })
+        })
+        .collect();
+
+    occurrences.sort_by_key(|o| (o.occurrence_date, o.start_time));
+
+    Ok(occurrences)
+}
+
+pub async fn update_occurrence(
+    pool: &PgPool,
+    user_id: Uuid,
+    id: Uuid,
+    occurrence_date: NaiveDate,
+    payload: UpdateOccurrenceRequest,
+) -> Result<OccurrenceOverrideResponse, WorryWindowError> {
+    let window = get_window(pool, user_id, id).await?;
+
+    // One-off windows are completed through the window itself
+    if window.recurrence.is_none()
+        || occurrence_dates(&window, occurrence_date, occurrence_date).is_empty()
+    {
+        return Err(WorryWindowError::NotAnOccurrence);
+    }
+
+    let was_completed: bool = sqlx::query_scalar(
+        r#"
+        SELECT EXISTS (
+            SELECT 1 FROM worry_window_occurrences
+            WHERE worry_window_id = $1 AND occurrence_date = $2 AND is_completed = TRUE
+        )
+        "#,
+    )
+    .bind(window.id)
+    .bind(occurrence_date)
+    .fetch_one(pool)
+    .await?;
+
+    let occurrence_override = sqlx::query_as::<_, WorryWindowOccurrenceOverride>(
+        r#"
+        INSERT INTO worry_window_occurrences (worry_window_id, occurrence_date, is_skipped, is_completed)
+        VALUES ($1, $2, COALESCE($3, FALSE), COALESCE($4, FALSE))
+        ON CONFLICT (worry_window_id, occurrence_date) DO UPDATE
+        SET is_skipped = COALESCE($3, worry_window_occurrences.is_skipped),
+            is_completed = COALESCE($4, worry_window_occurrences.is_completed)
+        RETURNING id, worry_window_id, occurrence_date, is_skipped, is_completed, created_at, updated_at
+        "#,
+    )
+    .bind(window.id)
+    .bind(occurrence_date)
+    .bind(payload.is_skipped)
+    .bind(payload.is_completed)
+    .fetch_one(pool)
+    .await?;
+
+    let session_summary = if occurrence_override.is_completed && !was_completed {
+        Some(build_summary(pool, window.id, occurrence_date).await?)
+    } else {
+        None
+    };
+
+    Ok(OccurrenceOverrideResponse {
+        occurrence: occurrence_override,
+        session_summary,
+    })
+}
+
+/// Occurrences for the current calendar day in `tz`
+pub async fn list_today(
+    pool: &PgPool,
+    user_id: Uuid,
+    tz: Tz,
+) -> Result<Vec<WorryWindowOccurrence>, WorryWindowError> {
+    let today = Utc::now().with_timezone(&tz).date_naive();
+    list_occurrences(pool, user_id, today, today).await
+}
+
+/// Picks the occurrence a parked worry belongs to, defaulting to the date of a one-off window
+fn resolve_occurrence_date(
+    window: &WorryWindow,
+    date: Option<NaiveDate>,
+) -> Result<NaiveDate, WorryWindowError> {
+    match date {
+        Some(date) => {
+            if occurrence_dates(window, date, date).is_empty() {
+                return Err(WorryWindowError::NotAnOccurrence);
+            }
+            Ok(date)
+        }
+        None if window.recurrence.is_none() => Ok(window.scheduled_date),
+        None => Err(WorryWindowError::OccurrenceDateRequired),
+    }
+}
+
+async fn fetch_parked_details(
+    pool: &PgPool,
+    worry_window_id: Uuid,
+    occurrence_date: NaiveDate,
+) -> Result<Vec<ParkedWorryDetail>, WorryWindowError> {
+    let entries = sqlx::query_as::<_, ParkedWorryDetail>(
+        r#"
+        SELECT p.id, p.worry_window_id, p.mental_box_id, p.occurrence_date, m.title, m.content,
+               p.outcome, p.outcome_note, p.outcome_recorded_at, p.created_at
+        FROM worry_window_parked_entries p
+        JOIN mental_box_entries m ON m.id = p.mental_box_id
+        WHERE p.worry_window_id = $1 AND p.occurrence_date = $2
+        ORDER BY p.created_at ASC
+        "#,
+    )
+    .bind(worry_window_id)
+    .bind(occurrence_date)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(entries)
+}
+
+async fn build_summary(
+    pool: &PgPool,
+    worry_window_id: Uuid,
+    occurrence_date: NaiveDate,
+) -> Result<WorrySessionSummary, WorryWindowError> {
+    let entries = fetch_parked_details(pool, worry_window_id, occurrence_date).await?;
+    let count = |outcome: &str| entries.iter().filter(|e| e.outcome.as_deref() == Some(outcome)).count() as i64;
+
+    Ok(WorrySessionSummary {
+        worry_window_id,
+        occurrence_date,
+        total: entries.len() as i64,
+        resolved: count("resolved"),
+        still_worrying: count("still_worrying"),
+        reframed: count("reframed"),
+        dropped: count("dropped"),
+        pending: entries.iter().filter(|e| e.outcome.is_none()).count() as i64,
+        entries,
+    })
+}
+
+pub async fn park_worry(
+    pool: &PgPool,
+    user_id: Uuid,
+    id: Uuid,
+    payload: ParkWorryRequest,
+) -> Result<ParkedWorry, WorryWindowError> {
+    let window = get_window(pool, user_id, id).await?;
+    let occurrence_date = resolve_occurrence_date(&window, payload.occurrence_date)?;
+
+    let owns_entry: bool = sqlx::query_scalar(
+        r#"
+        SELECT EXISTS (SELECT 1 FROM mental_box_entries WHERE id = $1 AND user_id = $2)
+        "#,
+    )
+    .bind(payload.mental_box_id)
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+
+    if !owns_entry {
+        return Err(WorryWindowError::MentalBoxEntryNotFound);
+    }
+
+    sqlx::query_as::<_, ParkedWorry>(
+        r#"
+        INSERT INTO worry_window_parked_entries (worry_window_id, mental_box_id, occurrence_date)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (worry_window_id, occurrence_date, mental_box_id) DO NOTHING
+        RETURNING id, worry_window_id, mental_box_id, occurrence_date, outcome, outcome_note, outcome_recorded_at, created_at, updated_at
+        "#,
+    )
+    .bind(window.id)
+    .bind(payload.mental_box_id)
+    .bind(occurrence_date)
+    .fetch_optional(pool)
+    .await?
+    .ok_or(WorryWindowError::AlreadyParked)
+}
+
+pub async fn list_parked(
+    pool: &PgPool,
+    user_id: Uuid,
+    id: Uuid,
+    date: Option<NaiveDate>,
+) -> Result<Vec<ParkedWorryDetail>, WorryWindowError> {
+    let window = get_window(pool, user_id, id).await?;
+    let occurrence_date = resolve_occurrence_date(&window, date)?;
+
+    fetch_parked_details(pool, window.id, occurrence_date).await
+}
+
+pub async fn record_outcome(
+    pool: &PgPool,
+    user_id: Uuid,
+    id: Uuid,
+    parked_id: Uuid,
+    payload: RecordOutcomeRequest,
+) -> Result<ParkedWorry, WorryWindowError> {
+    let window = get_window(pool, user_id, id).await?;
+
+    sqlx::query_as::<_, ParkedWorry>(
+        r#"
+        UPDATE worry_window_parked_entries
+        SET outcome = $1, outcome_note = $2, outcome_recorded_at = NOW()
+        WHERE id = $3 AND worry_window_id = $4
+        RETURNING id, worry_window_id, mental_box_id, occurrence_date, outcome, outcome_note, outcome_recorded_at, created_at, updated_at
+        "#,
+    )
+    .bind(payload.outcome.to_string())
+    .bind(&payload.note)
+    .bind(parked_id)
+    .bind(window.id)
+    .fetch_optional(pool)
+    .await?
+    .ok_or(WorryWindowError::ParkedWorryNotFound)
+}
+
+pub async fn unpark_worry(
+    pool: &PgPool,
+    user_id: Uuid,
+    id: Uuid,
+    parked_id: Uuid,
+) -> Result<(), WorryWindowError> {
+    let window = get_window(pool, user_id, id).await?;
+
+    let result = sqlx::query(
+        r#"
+        DELETE FROM worry_window_parked_entries
+        WHERE id = $1 AND worry_window_id = $2
+        "#,
+    )
+    .bind(parked_id)
+    .bind(window.id)
+    .execute(pool)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(WorryWindowError::ParkedWorryNotFound);
+    }
+
+    Ok(())
+}
+
+pub async fn session_summary(
+    pool: &PgPool,
+    user_id: Uuid,
+    id: Uuid,
+    date: Option<NaiveDate>,
+) -> Result<WorrySessionSummary, WorryWindowError> {
+    let window = get_window(pool, user_id, id).await?;
+    let occurrence_date = resolve_occurrence_date(&window, date)?;
+
+    build_summary(pool, window.id, occurrence_date).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn rule(frequency: RecurrenceFrequency, interval: u32) -> RecurrenceRule {
+        RecurrenceRule {
+            frequency,
+            interval,
+            days_of_week: vec![],
+            until: None,
+            count: None,
+        }
+    }
+
+    fn window(scheduled_date: NaiveDate, recurrence: Option<RecurrenceRule>) -> WorryWindow {
+        WorryWindow {
+            id: Uuid::new_v4(),
+            user_id: Uuid::new_v4(),
+            title: "Worry time".to_string(),
+            description: None,
+            scheduled_date,
+            start_time: NaiveTime::from_hms_opt(18, 0, 0).unwrap(),
+            end_time: NaiveTime::from_hms_opt(18, 30, 0).unwrap(),
+            is_completed: false,
+            recurrence: recurrence.map(Json),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn daily_rule_respects_interval() {
+        let every_third_day = rule(RecurrenceFrequency::Daily, 3);
+        let start = date(2024, 1, 1);
+
+        assert!(matches_rule(&every_third_day, start, start));
+        assert!(!matches_rule(&every_third_day, start, date(2024, 1, 2)));
+        assert!(matches_rule(&every_third_day, start, date(2024, 1, 4)));
+    }
+
+    #[test]
+    fn weekdays_rule_skips_weekends() {
+        let weekdays = rule(RecurrenceFrequency::Weekdays, 1);
+        // 2024-01-05 is a Friday
+        let start = date(2024, 1, 5);
+
+        assert!(matches_rule(&weekdays, start, start));
+        assert!(!matches_rule(&weekdays, start, date(2024, 1, 6)));
+        assert!(!matches_rule(&weekdays, start, date(2024, 1, 7)));
+        assert!(matches_rule(&weekdays, start, date(2024, 1, 8)));
+    }
+
+    #[test]
+    fn weekly_rule_uses_listed_days_and_week_interval() {
+        let mut fortnightly = rule(RecurrenceFrequency::Weekly, 2);
+        fortnightly.days_of_week = vec![Weekday::Mon, Weekday::Thu];
+        // 2024-01-03 is a Wednesday
+        let start = date(2024, 1, 3);
+
+        assert!(!matches_rule(&fortnightly, start, start));
+        assert!(matches_rule(&fortnightly, start, date(2024, 1, 4)));
+        assert!(!matches_rule(&fortnightly, start, date(2024, 1, 8)));
+        assert!(matches_rule(&fortnightly, start, date(2024, 1, 15)));
+        assert!(matches_rule(&fortnightly, start, date(2024, 1, 18)));
+    }
+
+    #[test]
+    fn weekly_rule_defaults_to_start_weekday() {
+        let weekly = rule(RecurrenceFrequency::Weekly, 1);
+        let start = date(2024, 1, 3);
+
+        assert!(matches_rule(&weekly, start, date(2024, 1, 10)));
+        assert!(!matches_rule(&weekly, start, date(2024, 1, 11)));
+    }
+
+    #[test]
+    fn validate_recurrence_rejects_weekdays_interval() {
+        let every_other_weekday = rule(RecurrenceFrequency::Weekdays, 2);
+
+        assert!(matches!(
+            validate_recurrence(&every_other_weekday, date(2024, 1, 1)),
+            Err(WorryWindowError::InvalidRecurrence(_))
+        ));
+    }
+
+    #[test]
+    fn one_off_window_occurs_only_on_its_date() {
+        let one_off = window(date(2024, 1, 10), None);
+
+        assert_eq!(occurrence_dates(&one_off, date(2024, 1, 1), date(2024, 1, 31)), vec![date(2024, 1, 10)]);
+        assert!(occurrence_dates(&one_off, date(2024, 1, 11), date(2024, 1, 31)).is_empty());
+    }
+
+    #[test]
+    fn occurrence_dates_stop_at_until() {
+        let mut daily = rule(RecurrenceFrequency::Daily, 1);
+        daily.until = Some(date(2024, 1, 3));
+        let series = window(date(2024, 1, 1), Some(daily));
+
+        assert_eq!(
+            occurrence_dates(&series, date(2024, 1, 1), date(2024, 1, 31)),
+            vec![date(2024, 1, 1), date(2024, 1, 2), date(2024, 1, 3)]
+        );
+    }
+
+    #[test]
+    fn occurrence_dates_count_includes_occurrences_before_range() {
+        let mut daily = rule(RecurrenceFrequency::Daily, 2);
+        daily.count = Some(3);
+        let series = window(date(2024, 1, 1), Some(daily));
+
+        // The series is 1, 3 and 5 January; only the last two fall in range
+        assert_eq!(
+            occurrence_dates(&series, date(2024, 1, 2), date(2024, 1, 31)),
+            vec![date(2024, 1, 3), date(2024, 1, 5)]
+        );
+    }
+
+    #[test]
+    fn occurrences_before_matches_a_day_by_day_count() {
+        let start = date(2024, 1, 10);
+        let mut rules = vec![
+            rule(RecurrenceFrequency::Daily, 1),
+            rule(RecurrenceFrequency::Daily, 3),
+            rule(RecurrenceFrequency::Weekdays, 1),
+            rule(RecurrenceFrequency::Weekly, 1),
+            rule(RecurrenceFrequency::Weekly, 2),
+        ];
+        let mut listed_days = rule(RecurrenceFrequency::Weekly, 3);
+        listed_days.days_of_week = vec![Weekday::Mon, Weekday::Wed, Weekday::Sun];
+        rules.push(listed_days);
+
+        for rule in &rules {
+            let mut expected = 0;
+            let mut day = start;
+            for _ in 0..60 {
+                assert_eq!(occurrences_before(rule, start, day), expected, "{:?} before {}", rule.frequency, day);
+                if matches_rule(rule, start, day) {
+                    expected += 1;
+                }
+                day = day.succ_opt().unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn occurrence_dates_skip_ahead_for_old_series() {
+        let mut daily = rule(RecurrenceFrequency::Daily, 2);
+        daily.count = Some(400_000);
+        let series = window(date(1000, 1, 1), Some(daily));
+        assert_eq!(
+            occurrence_dates(&series, date(2024, 1, 1), date(2024, 1, 5)),
+            vec![date(2024, 1, 1), date(2024, 1, 3), date(2024, 1, 5)]
+        );
+    }
+
+    #[test]
+    fn occurrence_dates_stop_at_the_last_supported_date() {
+        let series = window(NaiveDate::MAX - Duration::days(2), Some(rule(RecurrenceFrequency::Daily, 1)));
+        assert_eq!(occurrence_dates(&series, NaiveDate::MAX - Duration::days(5), NaiveDate::MAX).len(), 3);
+    }
+
+    #[test]
+    fn occurrence_dates_are_empty_before_series_start() {
+        let series = window(date(2024, 2, 1), Some(rule(RecurrenceFrequency::Daily, 1)));
+
+        assert!(occurrence_dates(&series, date(2024, 1, 1), date(2024, 1, 31)).is_empty());
+    }
+}
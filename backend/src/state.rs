@@ -0,0 +1,70 @@
+use axum::extract::FromRef;
+use sqlx::PgPool;
+use std::sync::Arc;
+
+use crate::config::{AiQuotaConfig, LoginThrottleConfig, PasswordResetConfig, PublicUrl};
+use crate::services::rate_limit_service::RateLimiter;
+use crate::services::reframer_service::Reframer;
+use crate::utils::jwt::JwtKeys;
+
+/// Shared application state. Handlers extract only the parts they need,
+/// e.g. `State<PgPool>`, through the `FromRef` impls below.
+#[derive(Clone)]
+pub struct AppState {
+    pub pool: PgPool,
+    pub jwt: Arc<JwtKeys>,
+    pub login_throttle: LoginThrottleConfig,
+    pub rate_limiter: Arc<RateLimiter>,
+    pub ai_quota: AiQuotaConfig,
+    pub reframer: Arc<Reframer>,
+    pub public_url: PublicUrl,
+    pub password_reset: PasswordResetConfig,
+}
+
+impl FromRef<AppState> for PgPool {
+    fn from_ref(state: &AppState) -> Self {
+        state.pool.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<JwtKeys> {
+    fn from_ref(state: &AppState) -> Self {
+        state.jwt.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<Reframer> {
+    fn from_ref(state: &AppState) -> Self {
+        state.reframer.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<RateLimiter> {
+    fn from_ref(state: &AppState) -> Self {
+        state.rate_limiter.clone()
+    }
+}
+
+impl FromRef<AppState> for LoginThrottleConfig {
+    fn from_ref(state: &AppState) -> Self {
+        state.login_throttle.clone()
+    }
+}
+
+impl FromRef<AppState> for AiQuotaConfig {
+    fn from_ref(state: &AppState) -> Self {
+        state.ai_quota.clone()
+    }
+}
+
+impl FromRef<AppState> for PublicUrl {
+    fn from_ref(state: &AppState) -> Self {
+        state.public_url.clone()
+    }
+}
+
+impl FromRef<AppState> for PasswordResetConfig {
+    fn from_ref(state: &AppState) -> Self {
+        state.password_reset.clone()
+    }
+}
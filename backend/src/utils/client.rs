@@ -0,0 +1,53 @@
+use axum::http::{header, HeaderMap};
+use std::{
+    env,
+    net::{IpAddr, SocketAddr},
+    sync::OnceLock,
+};
+
+const MAX_USER_AGENT_LENGTH: usize = 512;
+
+/// Details about the device a request came from, recorded on sessions
+#[derive(Debug, Clone, Default)]
+pub struct ClientInfo {
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+}
+
+impl ClientInfo {
+    pub fn from_request(headers: &HeaderMap, peer: SocketAddr) -> Self {
+        Self {
+            user_agent: headers
+                .get(header::USER_AGENT)
+                .and_then(|value| value.to_str().ok())
+                .map(|ua| ua.chars().take(MAX_USER_AGENT_LENGTH).collect()),
+            ip_address: Some(client_ip(headers, peer)),
+        }
+    }
+}
+
+fn trust_proxy_headers() -> bool {
+    static TRUST_PROXY_HEADERS: OnceLock<bool> = OnceLock::new();
+    *TRUST_PROXY_HEADERS.get_or_init(|| {
+        env::var("TRUST_PROXY_HEADERS")
+            .map(|value| value == "true")
+            .unwrap_or(false)
+    })
+}
+
+/// The client's IP address. `X-Real-IP` is only honoured with `TRUST_PROXY_HEADERS=true`,
+/// which must be set only when the backend is reachable solely through the nginx proxy;
+/// otherwise any client could pick its own address. Values that are not an IP are ignored.
+pub fn client_ip(headers: &HeaderMap, peer: SocketAddr) -> String {
+    if trust_proxy_headers() {
+        if let Some(ip) = headers
+            .get("X-Real-IP")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<IpAddr>().ok())
+        {
+            return ip.to_string();
+        }
+    }
+
+    peer.ip().to_string()
+}
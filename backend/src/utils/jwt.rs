@@ -1,81 +1,192 @@
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use hmac::{Hmac, Mac};
+use jsonwebtoken::{decode, decode_header, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
 use serde::{Deserialize, Serialize};
-use std::env;
+use sha2::Sha256;
+use std::collections::HashMap;
 use uuid::Uuid;
 
+use crate::config::JwtConfig;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TokenType {
+    #[serde(rename = "access")]
+    Access,
+    #[serde(rename = "refresh")]
+    Refresh,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
+    pub iss: String, // Issuer
+    pub aud: String, // Audience
     pub sub: String, // User ID
+    pub iat: usize,  // Issued at
+    pub nbf: usize,  // Not valid before
     pub exp: usize,  // Expiration time
-    pub token_type: String, // "access" or "refresh"
+    pub jti: String, // Unique token ID
+    pub token_type: TokenType,
 }
 
-pub fn generate_token(user_id: Uuid) -> Result<String, jsonwebtoken::errors::Error> {
-    let secret = env::var("JWT_SECRET").expect("JWT_SECRET must be set");
-    let expiration = env::var("JWT_EXPIRATION")
-        .unwrap_or_else(|_| "900".to_string())
-        .parse::<i64>()
-        .unwrap_or(900);
-
-    let exp = (chrono::Utc::now() + chrono::Duration::seconds(expiration)).timestamp() as usize;
-
-    let claims = Claims {
-        sub: user_id.to_string(),
-        exp,
-        token_type: "access".to_string(),
-    };
-
-    encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(secret.as_bytes()),
-    )
+#[derive(Debug, thiserror::Error)]
+pub enum JwtKeyError {
+    #[error("Failed to read key file {0}: {1}")]
+    Io(String, std::io::Error),
+    #[error("Invalid key: {0}")]
+    InvalidKey(#[from] jsonwebtoken::errors::Error),
+    #[error("Missing key configuration: {0}")]
+    Missing(&'static str),
 }
 
-pub fn generate_refresh_token(user_id: Uuid) -> Result<String, jsonwebtoken::errors::Error> {
-    let secret = env::var("JWT_SECRET").expect("JWT_SECRET must be set");
-    // Refresh token expires in 7 days
-    let expiration = env::var("JWT_REFRESH_EXPIRATION")
-        .unwrap_or_else(|_| "604800".to_string())
-        .parse::<i64>()
-        .unwrap_or(604800);
-
-    let exp = (chrono::Utc::now() + chrono::Duration::seconds(expiration)).timestamp() as usize;
-
-    let claims = Claims {
-        sub: user_id.to_string(),
-        exp,
-        token_type: "refresh".to_string(),
-    };
-
-    encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(secret.as_bytes()),
-    )
+/// Signing and verification keys, loaded once at startup from `JwtConfig`
+pub struct JwtKeys {
+    algorithm: Algorithm,
+    key_id: String,
+    encoding_key: EncodingKey,
+    /// Keyed by `kid`, each with the algorithm it verifies; always contains the current
+    /// signing key's public half
+    decoding_keys: HashMap<String, (Algorithm, DecodingKey)>,
+    issuer: String,
+    audience: String,
+    expiration: i64,
+    refresh_token_expiration: i64,
+    refresh_token_hash_key: Vec<u8>,
 }
 
-pub fn verify_token(token: &str) -> Result<Claims, jsonwebtoken::errors::Error> {
-    let secret = env::var("JWT_SECRET").expect("JWT_SECRET must be set");
-
-    let token_data = decode::<Claims>(
-        token,
-        &DecodingKey::from_secret(secret.as_bytes()),
-        &Validation::default(),
-    )?;
+fn read_key_file(path: &str) -> Result<Vec<u8>, JwtKeyError> {
+    std::fs::read(path).map_err(|e| JwtKeyError::Io(path.to_string(), e))
+}
 
-    Ok(token_data.claims)
+fn decoding_key(algorithm: Algorithm, material: &[u8]) -> Result<DecodingKey, JwtKeyError> {
+    Ok(match algorithm {
+        Algorithm::RS256 => DecodingKey::from_rsa_pem(material)?,
+        Algorithm::EdDSA => DecodingKey::from_ed_pem(material)?,
+        // Secret files usually end with a newline that was never part of the secret
+        _ => DecodingKey::from_secret(material.trim_ascii_end()),
+    })
 }
 
-pub fn verify_refresh_token(token: &str) -> Result<Claims, jsonwebtoken::errors::Error> {
-    let claims = verify_token(token)?;
+impl JwtKeys {
+    pub fn from_config(config: &JwtConfig) -> Result<Self, JwtKeyError> {
+        let (encoding_key, current_decoding_key) = match config.algorithm {
+            Algorithm::RS256 | Algorithm::EdDSA => {
+                let private_pem = read_key_file(
+                    config
+                        .private_key_path
+                        .as_deref()
+                        .ok_or(JwtKeyError::Missing("JWT_PRIVATE_KEY_PATH"))?,
+                )?;
+                let public_pem = read_key_file(
+                    config
+                        .public_key_path
+                        .as_deref()
+                        .ok_or(JwtKeyError::Missing("JWT_PUBLIC_KEY_PATH"))?,
+                )?;
+                let encoding_key = if config.algorithm == Algorithm::RS256 {
+                    EncodingKey::from_rsa_pem(&private_pem)?
+                } else {
+                    EncodingKey::from_ed_pem(&private_pem)?
+                };
+                (encoding_key, decoding_key(config.algorithm, &public_pem)?)
+            }
+            _ => {
+                let secret = config.secret.as_deref().ok_or(JwtKeyError::Missing("JWT_SECRET"))?;
+                (
+                    EncodingKey::from_secret(secret.as_bytes()),
+                    DecodingKey::from_secret(secret.as_bytes()),
+                )
+            }
+        };
+
+        let mut decoding_keys = HashMap::new();
+        for key in &config.verification_keys {
+            let material = read_key_file(&key.path)?;
+            decoding_keys.insert(key.key_id.clone(), (key.algorithm, decoding_key(key.algorithm, &material)?));
+        }
+        decoding_keys.insert(config.key_id.clone(), (config.algorithm, current_decoding_key));
+
+        Ok(Self {
+            algorithm: config.algorithm,
+            key_id: config.key_id.clone(),
+            encoding_key,
+            decoding_keys,
+            issuer: config.issuer.clone(),
+            audience: config.audience.clone(),
+            expiration: config.expiration,
+            refresh_token_expiration: config.refresh_token_expiration,
+            refresh_token_hash_key: config.refresh_token_hash_key.as_bytes().to_vec(),
+        })
+    }
+
+    pub fn refresh_token_expiration(&self) -> i64 {
+        self.refresh_token_expiration
+    }
+
+    fn sign(&self, user_id: Uuid, token_type: TokenType, expiration: i64) -> Result<String, jsonwebtoken::errors::Error> {
+        let now = chrono::Utc::now();
+
+        let claims = Claims {
+            iss: self.issuer.clone(),
+            aud: self.audience.clone(),
+            sub: user_id.to_string(),
+            iat: now.timestamp() as usize,
+            nbf: now.timestamp() as usize,
+            exp: (now + chrono::Duration::seconds(expiration)).timestamp() as usize,
+            jti: Uuid::new_v4().to_string(),
+            token_type,
+        };
+
+        let mut header = Header::new(self.algorithm);
+        header.kid = Some(self.key_id.clone());
+
+        encode(&header, &claims, &self.encoding_key)
+    }
+
+    pub fn generate_token(&self, user_id: Uuid) -> Result<String, jsonwebtoken::errors::Error> {
+        self.sign(user_id, TokenType::Access, self.expiration)
+    }
+
+    pub fn generate_refresh_token(&self, user_id: Uuid) -> Result<String, jsonwebtoken::errors::Error> {
+        self.sign(user_id, TokenType::Refresh, self.refresh_token_expiration)
+    }
+
+    fn verify(&self, token: &str, expected: TokenType) -> Result<Claims, jsonwebtoken::errors::Error> {
+        // Tokens issued before `kid` headers were introduced are checked against the current key
+        let kid = decode_header(token)?.kid.unwrap_or_else(|| self.key_id.clone());
+        let (algorithm, key) = self.decoding_keys.get(&kid).ok_or_else(|| {
+            jsonwebtoken::errors::Error::from(jsonwebtoken::errors::ErrorKind::InvalidKeyFormat)
+        })?;
+
+        let mut validation = Validation::new(*algorithm);
+        validation.set_issuer(&[&self.issuer]);
+        validation.set_audience(&[&self.audience]);
+        validation.set_required_spec_claims(&["exp", "nbf", "iss", "aud", "sub"]);
+        validation.validate_nbf = true;
 
-    // Ensure it's a refresh token
-    if claims.token_type != "refresh" {
-        return Err(jsonwebtoken::errors::Error::from(
-            jsonwebtoken::errors::ErrorKind::InvalidToken,
-        ));
+        let claims = decode::<Claims>(token, key, &validation)?.claims;
+
+        // An access token must never be usable as a refresh token, or the other way round
+        if claims.token_type != expected {
+            return Err(jsonwebtoken::errors::Error::from(
+                jsonwebtoken::errors::ErrorKind::InvalidToken,
+            ));
+        }
+
+        Ok(claims)
+    }
+
+    pub fn verify_token(&self, token: &str) -> Result<Claims, jsonwebtoken::errors::Error> {
+        self.verify(token, TokenType::Access)
     }
 
-    Ok(claims)
+    pub fn verify_refresh_token(&self, token: &str) -> Result<Claims, jsonwebtoken::errors::Error> {
+        self.verify(token, TokenType::Refresh)
+    }
+
+    /// Keyed hash of a refresh token, which is all the database ever stores
+    pub fn hash_refresh_token(&self, token: &str) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.refresh_token_hash_key)
+            .expect("HMAC accepts keys of any length");
+        mac.update(token.as_bytes());
+        hex::encode(mac.finalize().into_bytes())
+    }
 }
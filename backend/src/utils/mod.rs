@@ -1,2 +1,4 @@
 pub mod jwt;
 pub mod password;
+pub mod validation;
+pub mod client;
@@ -1,4 +1,11 @@
 use bcrypt::{hash, verify, DEFAULT_COST};
+use std::sync::OnceLock;
+use validator::ValidationError;
+
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+
+/// bcrypt ignores everything past 72 bytes, so longer passwords would silently be truncated
+pub const MAX_PASSWORD_BYTES: usize = 72;
 
 pub fn hash_password(password: &str) -> Result<String, bcrypt::BcryptError> {
     hash(password, DEFAULT_COST)
@@ -7,3 +14,30 @@ pub fn hash_password(password: &str) -> Result<String, bcrypt::BcryptError> {
 pub fn verify_password(password: &str, hash: &str) -> Result<bool, bcrypt::BcryptError> {
     verify(password, hash)
 }
+
+/// Runs a full bcrypt verification against a throwaway hash, so that a login for an
+/// unknown email takes as long as one with a wrong password
+pub fn dummy_verify(password: &str) {
+    static DUMMY_HASH: OnceLock<String> = OnceLock::new();
+    let dummy_hash = DUMMY_HASH.get_or_init(|| {
+        hash(uuid::Uuid::new_v4().to_string(), DEFAULT_COST).expect("bcrypt hashing a fixed-size input")
+    });
+    let _ = verify(password, dummy_hash);
+}
+
+/// Validator rule for new passwords: 8+ characters, at most 72 bytes, with a letter and a digit
+pub fn validate_strength(password: &str) -> Result<(), ValidationError> {
+    let message = if password.chars().count() < MIN_PASSWORD_LENGTH {
+        "Password must be at least 8 characters"
+    } else if password.len() > MAX_PASSWORD_BYTES {
+        "Password must be at most 72 bytes"
+    } else if !password.chars().any(char::is_alphabetic) || !password.chars().any(|c| c.is_ascii_digit()) {
+        "Password must contain at least one letter and one digit"
+    } else {
+        return Ok(());
+    };
+
+    let mut error = ValidationError::new("weak_password");
+    error.message = Some(message.into());
+    Err(error)
+}
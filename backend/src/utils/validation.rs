@@ -0,0 +1,110 @@
+use axum::{
+    async_trait,
+    extract::{FromRequest, FromRequestParts, Path, Query, Request},
+    http::request::Parts,
+    Json,
+};
+use serde::de::DeserializeOwned;
+use validator::{Validate, ValidationError};
+
+use crate::error::AppError;
+
+pub const MAX_ACTIVITY_LENGTH: usize = 50;
+
+/// JSON body extractor that runs the payload's `validator` rules before the handler
+/// sees it. Malformed bodies and failed rules are both reported as `AppError`s.
+pub struct ValidatedJson<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for ValidatedJson<T>
+where
+    T: DeserializeOwned + Validate,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let Json(value) = Json::<T>::from_request(req, state)
+            .await
+            .map_err(|rejection| AppError::BadRequest("invalid_json", rejection.body_text()))?;
+
+        value.validate()?;
+
+        Ok(ValidatedJson(value))
+    }
+}
+
+/// Path extractor that reports malformed segments, such as an invalid UUID, in the
+/// same JSON error shape as every other client error
+pub struct ValidatedPath<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for ValidatedPath<T>
+where
+    T: DeserializeOwned + Send,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Path(value) = Path::<T>::from_request_parts(parts, state)
+            .await
+            .map_err(|rejection| {
+                // A route/extractor mismatch is a bug, not the client's fault
+                if rejection.status().is_server_error() {
+                    AppError::Internal(rejection.body_text())
+                } else {
+                    AppError::BadRequest("invalid_path", rejection.body_text())
+                }
+            })?;
+
+        Ok(ValidatedPath(value))
+    }
+}
+
+/// Query string extractor that reports unparsable parameters as `AppError`s
+pub struct ValidatedQuery<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for ValidatedQuery<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Query(value) = Query::<T>::from_request_parts(parts, state)
+            .await
+            .map_err(|rejection| AppError::BadRequest("invalid_query", rejection.body_text()))?;
+
+        Ok(ValidatedQuery(value))
+    }
+}
+
+fn error(code: &'static str, message: &'static str) -> ValidationError {
+    let mut error = ValidationError::new(code);
+    error.message = Some(message.into());
+    error
+}
+
+/// Rejects strings that are empty once surrounding whitespace is removed
+pub fn not_blank(value: &str) -> Result<(), ValidationError> {
+    if value.trim().is_empty() {
+        return Err(error("blank", "Must not be blank"));
+    }
+    Ok(())
+}
+
+pub fn validate_activities(activities: &[String]) -> Result<(), ValidationError> {
+    if activities
+        .iter()
+        .any(|activity| activity.trim().is_empty() || activity.chars().count() > MAX_ACTIVITY_LENGTH)
+    {
+        return Err(error(
+            "activity_length",
+            "Each activity must be between 1 and 50 characters",
+        ));
+    }
+    Ok(())
+}